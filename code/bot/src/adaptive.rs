@@ -0,0 +1,128 @@
+//! Adapting video streams to how much the link to the dashboard can keep up with
+//!
+//! the only sign of congestion is the backlog of data that has been queued to send to the dashboard, but not sent yet.
+//! while it is too large, streams step down a ladder of [`OperatingPoint`]s, and once it has been clear for a while
+//! they step back up.
+
+use std::time::{Duration, Instant};
+
+use aareocams_net::OperatingPoint;
+
+/// the operating points a stream steps through, best first
+const LADDER: [OperatingPoint; 7] = [
+    OperatingPoint::FULL,
+    operating_point(1, 1, 6),
+    operating_point(2, 1, 6),
+    operating_point(2, 1, 5),
+    operating_point(2, 2, 5),
+    operating_point(3, 2, 4),
+    operating_point(4, 4, 4),
+];
+
+/// bytes waiting to be sent above which the link counts as congested
+pub const CONGESTED_BACKLOG: usize = 256 * 1024;
+/// bytes waiting to be sent below which the link counts as clear
+pub const CLEAR_BACKLOG: usize = 32 * 1024;
+/// shortest time between two steps down, so that the last one has time to take effect
+const STEP_DOWN_INTERVAL: Duration = Duration::from_millis(500);
+/// how long the link has to be clear before stepping up
+const STEP_UP_INTERVAL: Duration = Duration::from_secs(5);
+
+const fn operating_point(frame_interval: u32, downscale: u32, max_quality: u8) -> OperatingPoint {
+    OperatingPoint {
+        frame_interval,
+        downscale,
+        max_quality,
+    }
+}
+
+#[derive(Debug)]
+pub struct AdaptiveController {
+    /// index into [`LADDER`]
+    level: usize,
+    last_change: Instant,
+    /// since when the backlog has been below [`CLEAR_BACKLOG`], if it is
+    clear_since: Option<Instant>,
+}
+
+impl AdaptiveController {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            level: 0,
+            last_change: now,
+            clear_since: None,
+        }
+    }
+
+    pub fn operating_point(&self) -> OperatingPoint {
+        LADDER[self.level]
+    }
+
+    /// updates the operating point with `backlog`, the number of bytes waiting to be sent to the dashboard
+    pub fn update(&mut self, backlog: usize, now: Instant) {
+        if backlog >= CONGESTED_BACKLOG {
+            self.clear_since = None;
+            if self.level + 1 < LADDER.len() && now - self.last_change >= STEP_DOWN_INTERVAL {
+                self.level += 1;
+                self.last_change = now;
+            }
+        } else if backlog <= CLEAR_BACKLOG {
+            let clear_since = *self.clear_since.get_or_insert(now);
+            if self.level > 0
+                && now - clear_since >= STEP_UP_INTERVAL
+                && now - self.last_change >= STEP_UP_INTERVAL
+            {
+                self.level -= 1;
+                self.last_change = now;
+            }
+        } else {
+            // neither congested nor clear, so stay where we are
+            self.clear_since = None;
+        }
+    }
+}
+
+#[test]
+fn test_ladder_order() {
+    for pair in LADDER.windows(2) {
+        assert!(pair[0].frame_interval <= pair[1].frame_interval);
+        assert!(pair[0].downscale <= pair[1].downscale);
+        assert!(pair[0].max_quality >= pair[1].max_quality);
+        assert_ne!(pair[0], pair[1]);
+    }
+}
+
+#[test]
+fn test_step_down_and_up() {
+    let start = Instant::now();
+    let mut controller = AdaptiveController::new(start);
+    controller.update(CLEAR_BACKLOG, start);
+    assert_eq!(controller.operating_point(), OperatingPoint::FULL);
+
+    // steps down at most once per interval while congested
+    for ms in (0..=1000).step_by(100) {
+        controller.update(CONGESTED_BACKLOG, start + Duration::from_millis(ms));
+    }
+    assert_eq!(controller.operating_point(), LADDER[2]);
+    // and never past the bottom
+    for s in 1..=60 {
+        controller.update(CONGESTED_BACKLOG, start + Duration::from_secs(s));
+    }
+    assert_eq!(controller.operating_point(), LADDER[LADDER.len() - 1]);
+
+    // a backlog between the thresholds changes nothing
+    let now = start + Duration::from_secs(100);
+    controller.update(CONGESTED_BACKLOG - 1, now);
+    controller.update(CONGESTED_BACKLOG - 1, now + STEP_UP_INTERVAL * 2);
+    assert_eq!(controller.operating_point(), LADDER[LADDER.len() - 1]);
+
+    // steps up one level once clear for long enough
+    let now = now + STEP_UP_INTERVAL * 3;
+    controller.update(0, now);
+    controller.update(0, now + STEP_UP_INTERVAL / 2);
+    assert_eq!(controller.operating_point(), LADDER[LADDER.len() - 1]);
+    controller.update(0, now + STEP_UP_INTERVAL);
+    assert_eq!(controller.operating_point(), LADDER[LADDER.len() - 2]);
+    controller.update(0, now + STEP_UP_INTERVAL + Duration::from_secs(1));
+    assert_eq!(controller.operating_point(), LADDER[LADDER.len() - 2]);
+}
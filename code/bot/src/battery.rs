@@ -0,0 +1,164 @@
+//! Estimating how much charge is left in the battery, and deciding when it is low
+//!
+//! this only keeps track of the state of the battery, the power system reads the sensor and acts on it
+
+use std::time::{Duration, Instant};
+
+use aareocams_net::{PowerLevel, PowerStatus};
+
+use crate::{config::PowerConf, hal::PowerReading};
+
+/// the voltage is averaged over about this long, so that it dropping while the motor speeds up
+/// does not set off warnings
+pub const SMOOTHING_TIME: Duration = Duration::from_secs(10);
+/// the charge has to rise this far above a threshold to count as above it again,
+/// so that the level does not flicker while the charge is near a threshold
+pub const LEVEL_HYSTERESIS: f64 = 0.03;
+
+#[derive(Debug)]
+pub struct BatteryMonitor {
+    cfg: PowerConf,
+    /// averaged voltage without load, and when it was last updated. `None` before the first reading
+    voltage: Option<(f64, Instant)>,
+    /// the last reading, as it was
+    last_reading: Option<PowerReading>,
+    level: PowerLevel,
+}
+
+impl BatteryMonitor {
+    pub fn new(cfg: PowerConf) -> Self {
+        Self {
+            cfg,
+            voltage: None,
+            last_reading: None,
+            level: PowerLevel::Normal,
+        }
+    }
+
+    /// adds a reading taken at `now`, returning the new level if it changed
+    pub fn update(&mut self, reading: PowerReading, now: Instant) -> Option<PowerLevel> {
+        // the voltage drops while current is drawn, which is not lost charge
+        let open_circuit_voltage = reading.voltage + reading.current * self.cfg.internal_resistance;
+        let voltage = match self.voltage {
+            Some((voltage, last_update)) => {
+                let dt = now.saturating_duration_since(last_update).as_secs_f64();
+                let alpha = dt / (SMOOTHING_TIME.as_secs_f64() + dt);
+                voltage + (open_circuit_voltage - voltage) * alpha
+            }
+            None => open_circuit_voltage,
+        };
+        self.voltage = Some((voltage, now));
+        self.last_reading = Some(reading);
+
+        let level = self.level_at(self.charge()?);
+        if level == self.level {
+            return None;
+        }
+        self.level = level;
+        Some(level)
+    }
+
+    /// estimated state of charge, 0 (empty) to 1 (full). `None` before the first reading
+    pub fn charge(&self) -> Option<f64> {
+        let (voltage, _) = self.voltage?;
+        let charge =
+            (voltage - self.cfg.empty_voltage) / (self.cfg.full_voltage - self.cfg.empty_voltage);
+        Some(charge.clamp(0.0, 1.0))
+    }
+
+    pub fn level(&self) -> PowerLevel {
+        self.level
+    }
+
+    /// `None` before the first reading
+    pub fn status(&self) -> Option<PowerStatus> {
+        let reading = self.last_reading?;
+        Some(PowerStatus {
+            voltage: reading.voltage as f32,
+            current: reading.current as f32,
+            charge: self.charge()? as f32,
+            level: self.level,
+        })
+    }
+
+    fn level_at(&self, charge: f64) -> PowerLevel {
+        // leaving a level takes a bit more charge than it took to get into it
+        let critical = match self.level {
+            PowerLevel::Critical => self.cfg.critical_charge + LEVEL_HYSTERESIS,
+            _ => self.cfg.critical_charge,
+        };
+        let low = match self.level {
+            PowerLevel::Normal => self.cfg.low_charge,
+            _ => self.cfg.low_charge + LEVEL_HYSTERESIS,
+        };
+        if charge < critical {
+            PowerLevel::Critical
+        } else if charge < low {
+            PowerLevel::Low
+        } else {
+            PowerLevel::Normal
+        }
+    }
+}
+
+#[test]
+fn test_battery_monitor() {
+    use crate::config::CriticalPowerAction;
+
+    let mut monitor = BatteryMonitor::new(PowerConf {
+        full_voltage: 12.0,
+        empty_voltage: 10.0,
+        internal_resistance: 0.5,
+        low_charge: 0.25,
+        critical_charge: 0.1,
+        critical_action: CriticalPowerAction::Stop,
+        period: Duration::from_secs(1),
+    });
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let reading = |voltage, current| PowerReading { voltage, current };
+
+    assert_eq!(monitor.status(), None);
+    // 11.5V with 1A drawn is 12V without load
+    assert_eq!(monitor.update(reading(11.5, 1.0), at(0)), None);
+    assert_eq!(monitor.charge(), Some(1.0));
+
+    // a short drop is smoothed out
+    assert_eq!(monitor.update(reading(10.0, 0.0), at(1)), None);
+    assert!(monitor.charge().unwrap() > 0.9);
+
+    // 40% is not low, 20% is
+    let mut now = 2;
+    while monitor.charge().unwrap() > 0.41 {
+        assert_eq!(monitor.update(reading(10.8, 0.0), at(now)), None);
+        now += 1;
+    }
+    while monitor.level() == PowerLevel::Normal {
+        monitor.update(reading(10.4, 0.0), at(now));
+        now += 1;
+    }
+    assert!(monitor.charge().unwrap() < 0.25);
+    assert_eq!(monitor.status().unwrap().level, PowerLevel::Low);
+
+    // it has to go a bit above the threshold to count as normal again
+    while monitor.charge().unwrap() < 0.26 {
+        assert_eq!(monitor.update(reading(10.55, 0.0), at(now)), None);
+        now += 1;
+    }
+    let mut changed = None;
+    while changed.is_none() {
+        changed = monitor.update(reading(12.0, 0.0), at(now));
+        now += 1;
+    }
+    assert_eq!(changed, Some(PowerLevel::Normal));
+    assert!(monitor.charge().unwrap() >= 0.25 + LEVEL_HYSTERESIS);
+
+    // dropping straight to empty
+    let mut levels = vec![];
+    for _ in 0..100 {
+        levels.extend(monitor.update(reading(9.0, 0.0), at(now)));
+        now += 1;
+    }
+    assert_eq!(levels, [PowerLevel::Low, PowerLevel::Critical]);
+    assert_eq!(monitor.charge(), Some(0.0));
+}
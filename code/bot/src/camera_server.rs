@@ -1,186 +1,823 @@
 use std::{
-    sync::{atomic::AtomicBool, Arc},
+    collections::HashMap,
+    fs::{self, File},
+    io::Read,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use aareocams_net::{Message, VideoStreamAction, VideoStreamInfo};
-use flume::Receiver;
-use nokhwa::Camera;
+use aareocams_net::{
+    CameraDevice, CameraFormat, CameraListing, CameraState, CameraTelemetry, CloseReason, Message,
+    RecordingAction, RecordingInfo, Snapshot, SnapshotRequest, StreamFormat, VideoStreamAction,
+    VideoStreamInfo,
+};
+use anyhow::{anyhow, bail, Result};
+use flume::{Receiver, RecvError, RecvTimeoutError, SendTimeoutError};
+use image::RgbImage;
+use parking_lot::Mutex;
 use uuid::Uuid;
 
+use crate::{
+    config::{CameraRecoveryConf, RecordingConf},
+    hal::{test_pattern::TestPatternCamera, Camera, CameraBackend},
+    recorder::{self, ActiveRecordings, StreamRecorder},
+    recovery::{CameraRecovery, ReadFailure},
+    snapshot::encode_snapshot,
+    stream_encoder::StreamEncoder,
+    subscribers::Subscribers,
+};
+
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct CameraInterface {
     #[derivative(Debug = "ignore")]
-    pub cam: Camera,
+    pub cam: Box<dyn Camera>,
     pub id: Uuid,
-    pub encoder: lvenc::Encoder,
+    pub encoder: StreamEncoder,
     pub paused: bool,
+    /// `None` if the stream is not being recorded
+    pub recorder: Option<StreamRecorder>,
+    /// if `cam` is a [`ClosedCamera`], until the camera is opened again
+    pub closed: bool,
+}
+
+/// stands in for a camera that was closed so that its device can be opened again, keeping its format
+struct ClosedCamera {
+    resolution: (u32, u32),
+    frame_rate: u32,
+}
+
+impl Camera for ClosedCamera {
+    fn resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+
+    fn frame_rate(&self) -> u32 {
+        self.frame_rate
+    }
+
+    fn open_stream(&mut self) -> Result<()> {
+        Err(anyhow!("the camera is closed"))
+    }
+
+    fn frame(&mut self) -> Result<RgbImage> {
+        Err(anyhow!("the camera is closed"))
+    }
+
+    fn stop_stream(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl CameraInterface {
+    /// the format frames are currently sent in
+    fn format(&self) -> StreamFormat {
+        let (width, height) = self.cam.resolution();
+        StreamFormat {
+            width,
+            height,
+            fps: self.cam.frame_rate(),
+        }
+    }
+
+    /// records `frame` if the stream is being recorded, returning what to tell the dashboard about it (if anything)
+    fn record(&mut self, frame: &RgbImage) -> Option<VideoStreamInfo> {
+        let recorder = self.recorder.as_mut()?;
+        match recorder.record(frame) {
+            Ok(new_file) => new_file.map(|name| VideoStreamInfo::RecordingStarted { name }),
+            Err(record_error) => {
+                error!(
+                    "Recording of stream {} failed:\n{:#?}",
+                    self.id, record_error
+                );
+                if let Some(recorder) = self.recorder.take() {
+                    let _ = recorder.finish();
+                }
+                Some(VideoStreamInfo::RecordingError {
+                    message: format!("{:#}", record_error),
+                })
+            }
+        }
+    }
+
+    fn start_recording(
+        &mut self,
+        cfg: &RecordingConf,
+        active: &ActiveRecordings,
+    ) -> VideoStreamInfo {
+        if let Some(recorder) = &self.recorder {
+            return VideoStreamInfo::RecordingStarted {
+                name: recorder.name().into(),
+            };
+        }
+        let (width, height) = self.cam.resolution();
+        match StreamRecorder::new(cfg.clone(), active.clone(), self.id, width, height) {
+            Ok(recorder) => {
+                info!("Recording stream {} to {}", self.id, recorder.name());
+                let name = recorder.name().into();
+                self.recorder = Some(recorder);
+                VideoStreamInfo::RecordingStarted { name }
+            }
+            Err(record_error) => VideoStreamInfo::RecordingError {
+                message: format!("{:#}", record_error),
+            },
+        }
+    }
+
+    fn stop_recording(&mut self) -> VideoStreamInfo {
+        match self.recorder.take().map(StreamRecorder::finish) {
+            Some(Err(record_error)) => VideoStreamInfo::RecordingError {
+                message: format!("{:#}", record_error),
+            },
+            _ => VideoStreamInfo::RecordingStopped,
+        }
+    }
+
+    /// stops the camera and drops it, so that its device can be opened again
+    fn close(&mut self) {
+        let closed = ClosedCamera {
+            resolution: self.cam.resolution(),
+            frame_rate: self.cam.frame_rate(),
+        };
+        let mut camera = std::mem::replace(&mut self.cam, Box::new(closed));
+        // the device is released when it is dropped, even if it can not be stopped
+        if let Err(stop_error) = camera.stop_stream() {
+            warn!(
+                "Failed to stop the camera of stream {}:\n{:#?}",
+                self.id, stop_error
+            );
+        }
+        self.closed = true;
+    }
+
+    /// replaces the camera with a newly opened one, after the old one was lost or closed.
+    /// returns what to tell the dashboard about the recording, if it had to be stopped
+    fn reopen(&mut self, camera: Box<dyn Camera>) -> Result<Option<VideoStreamInfo>> {
+        let mut recording_info = None;
+        if camera.resolution() != self.cam.resolution() {
+            // the encoder and recorder are made for one resolution, so they have to start over
+            let (width, height) = camera.resolution();
+            self.encoder = StreamEncoder::new(
+                self.id,
+                width,
+                height,
+                *self.encoder.settings(),
+                Instant::now(),
+            )?;
+            if self.recorder.is_some() {
+                recording_info = Some(self.stop_recording());
+            }
+        }
+        self.cam = camera;
+        self.closed = false;
+        // packets were lost with the camera, so the dashboard has to start decoding over
+        self.encoder.request_keyframe();
+        Ok(recording_info)
+    }
 }
 
+/// a thread running the video streams from one camera
 #[derive(Debug)]
+struct CameraWorker {
+    dev: CameraDevice,
+    /// what the stream that opened the camera asked for
+    format: Option<CameraFormat>,
+    /// commands for the streams, with the id of the stream they are for. the worker stops if this is dropped
+    commands: flume::Sender<(Uuid, VideoStreamAction)>,
+    /// kept up to date by the worker thread
+    state: Arc<Mutex<CameraState>>,
+    handle: JoinHandle<()>,
+}
+
+impl CameraWorker {
+    /// if this worker is streaming from the camera with device ID `index`
+    fn is_using(&self, index: usize) -> bool {
+        self.dev == CameraDevice::Index(index) && !self.handle.is_finished()
+    }
+
+    /// tells the worker to stop once it has handled the commands already sent, without waiting for it to exit
+    fn stop(self) -> (CameraDevice, JoinHandle<()>) {
+        drop(self.commands);
+        (self.dev, self.handle)
+    }
+
+    /// stops the worker, and waits for it to exit
+    fn join(self, id: Uuid) {
+        let (_, handle) = self.stop();
+        join_worker(id, handle);
+    }
+}
+
+/// waits for room in the download queue to send `message`, returning false if the server was dropped first
+fn send_download(
+    queue: &flume::Sender<Message>,
+    kill_signal: &AtomicBool,
+    mut message: Message,
+) -> Result<bool> {
+    loop {
+        match queue.send_timeout(message, DOWNLOAD_WAIT) {
+            Ok(()) => return Ok(true),
+            Err(SendTimeoutError::Timeout(unsent)) => {
+                if kill_signal.load(Ordering::Relaxed) {
+                    return Ok(false);
+                }
+                message = unsent;
+            }
+            Err(SendTimeoutError::Disconnected(_)) => bail!("The download queue was closed"),
+        }
+    }
+}
+
+fn join_worker(id: Uuid, handle: JoinHandle<()>) {
+    if let Err(thread_err) = handle.join() {
+        error!(
+            "camera worker thread {} did not exit gracefully:\n{:#?}",
+            id, thread_err
+        );
+    }
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct CameraServer {
-    handles: Vec<JoinHandle<()>>,
+    #[derivative(Debug = "ignore")]
+    cameras: Arc<dyn CameraBackend>,
+    /// the workers of all open cameras, by the id of the stream that opened them
+    workers: HashMap<Uuid, CameraWorker>,
+    /// the worker each open stream gets its frames from, by stream id
+    streams: HashMap<Uuid, Uuid>,
+    /// workers that were told to stop after their last stream closed, until they exit. they are not waited for
+    /// then, since that would block the bus
+    stopping: Vec<(Uuid, CameraDevice, JoinHandle<()>)>,
     messages_send: flume::Sender<Message>,
     message_queue: flume::Receiver<Message>,
-    updates_receiver: flume::Receiver<(Uuid, VideoStreamAction)>,
-    /// after sending any messages at all, one unpark all threads
-    updates_queue: flume::Sender<(Uuid, VideoStreamAction)>,
+    /// the chunks of recordings being downloaded, which the connection only takes while its backlog is below
+    /// [`DOWNLOAD_BACKLOG_LIMIT`]. bounded, so that recordings are only read as fast as they are sent
+    downloads_send: flume::Sender<Message>,
+    download_queue: flume::Receiver<Message>,
+    /// tells recording downloads to stop once the server is dropped
     kill_signal: Arc<AtomicBool>,
+    /// bytes queued to send to the dashboard but not sent yet, kept up to date by the connection
+    link_backlog: Arc<AtomicUsize>,
+    recording: RecordingConf,
+    /// the files recordings are being written to, by every stream
+    active_recordings: ActiveRecordings,
+    camera_recovery: CameraRecoveryConf,
 }
 
+/// size of the chunks recordings are downloaded in
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+/// downloads wait for the send backlog to get below this before sending more, so that they do not
+/// crowd out everything else (this is below [`crate::adaptive::CONGESTED_BACKLOG`], so streams keep going)
+pub const DOWNLOAD_BACKLOG_LIMIT: usize = 128 * 1024;
+/// how often a download waiting for room in the queue checks if the server was dropped
+const DOWNLOAD_WAIT: Duration = Duration::from_millis(100);
+
 impl CameraServer {
-    pub fn new() -> Self {
-        let handles = vec![];
+    pub fn new(
+        cameras: Arc<dyn CameraBackend>,
+        recording: RecordingConf,
+        camera_recovery: CameraRecoveryConf,
+    ) -> Self {
         let (messages_send, message_queue) = flume::unbounded();
-        let (updates_queue, updates_receiver) = flume::unbounded();
+        let (downloads_send, download_queue) = flume::bounded(1);
 
         Self {
-            handles,
+            cameras,
+            workers: HashMap::new(),
+            streams: HashMap::new(),
+            stopping: vec![],
             messages_send,
             message_queue,
-            updates_receiver,
-            updates_queue,
+            downloads_send,
+            download_queue,
             kill_signal: Arc::new(AtomicBool::new(false)),
+            link_backlog: Arc::new(AtomicUsize::new(0)),
+            recording,
+            active_recordings: ActiveRecordings::default(),
+            camera_recovery,
         }
     }
 
     pub fn feed_ctrl_msg(&mut self, id: Uuid, msg: VideoStreamAction) {
         match msg {
-            VideoStreamAction::Init { dev } => {
-                info!("Launching new camera worker {}, device no. {}", id, dev);
-                let message_queue = self.messages_send.clone();
-                let update_queue = self.updates_receiver.clone();
-                let kill_signal = self.kill_signal.clone();
-
-                const PARK_DURATION: Duration = Duration::new(5, 0);
-
-                self.handles.push(thread::spawn(move || {
-                    let mut interface = match Camera::new(dev, None) {
-                        Ok(device) => {
-                            let resoultion = device.resolution();
-                            let interface = CameraInterface {
-                                cam: device,
-                                id,
-                                encoder: lvenc::Encoder::new(
-                                    resoultion.width(),
-                                    resoultion.height(),
-                                ),
-                                paused: false,
-                            };
-                            message_queue
-                                .send(Message::VideoStreamInfo {
-                                    id,
-                                    action: VideoStreamInfo::Initialized,
-                                })
+            VideoStreamAction::Init {
+                dev,
+                format,
+                settings,
+            } => {
+                self.clean();
+                if self.streams.contains_key(&id) {
+                    warn!("Stream {} was initialized, but it already exists", id);
+                    self.messages_send
+                        .send(Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::InitError {
+                                message: format!("there is already a stream with id {}", id),
+                            },
+                        })
+                        .unwrap();
+                    return;
+                }
+                // cameras can only be opened once, so streams from one that is already open share its worker
+                let shared = self
+                    .workers
+                    .iter()
+                    .find(|(_, worker)| worker.dev == dev && !worker.handle.is_finished())
+                    .map(|(worker_id, worker)| (*worker_id, worker));
+                if let Some((worker_id, worker)) = shared {
+                    // a camera can only capture in one format at a time (test patterns only have one)
+                    let other_format = match (format, &dev) {
+                        (Some(format), CameraDevice::Index(_)) => worker.format != Some(format),
+                        _ => false,
+                    };
+                    if other_format {
+                        warn!(
+                            "Stream {} asked for a different format than the stream {} sharing its camera",
+                            id, worker_id
+                        );
+                        let message = format!(
+                            "the camera is already streaming in {} for another stream, \
+                             open it in the same format or close that stream first",
+                            worker
+                                .format
+                                .map_or("its default format".to_string(), |format| format
+                                    .to_string())
+                        );
+                        for action in [
+                            VideoStreamInfo::InitError { message },
+                            VideoStreamInfo::Closed {
+                                reason: CloseReason::InitFailed,
+                            },
+                        ] {
+                            self.messages_send
+                                .send(Message::VideoStreamInfo { id, action })
                                 .unwrap();
-                            interface
                         }
+                        return;
+                    }
+                    let subscribe = VideoStreamAction::Init {
+                        dev: dev.clone(),
+                        format,
+                        settings,
+                    };
+                    // this only fails if the worker just stopped, then the camera is opened again
+                    if worker.commands.send((id, subscribe)).is_ok() {
+                        info!("Stream {} shares the camera of stream {}", id, worker_id);
+                        self.streams.insert(id, worker_id);
+                        return;
+                    }
+                }
+                info!("Launching new camera worker {}, device {:?}", id, dev);
+                // a worker that is still stopping has to let go of the camera before it can be opened again
+                let (previous, stopping) = std::mem::take(&mut self.stopping)
+                    .into_iter()
+                    .partition::<Vec<_>, _>(|(_, stopping_dev, _)| *stopping_dev == dev);
+                self.stopping = stopping;
+                let message_queue = self.messages_send.clone();
+                let (commands_send, commands) = flume::unbounded();
+                let link_backlog = self.link_backlog.clone();
+                let recording = self.recording.clone();
+                let active_recordings = self.active_recordings.clone();
+                let camera_recovery = self.camera_recovery;
+                let cameras = self.cameras.clone();
+                let worker_dev = dev.clone();
+                let state = Arc::new(Mutex::new(CameraState::Opening));
+                let worker_state = state.clone();
+
+                let handle = thread::spawn(move || {
+                    let mut subscribers = Subscribers::new(id);
+                    message_queue
+                        .send(Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::Opening,
+                        })
+                        .unwrap();
+                    for (previous_id, _, handle) in previous {
+                        join_worker(previous_id, handle);
+                    }
+                    let init_failed = |mut subscribers: Subscribers, error: VideoStreamInfo| {
+                        subscribe_waiting(&commands, &mut subscribers);
+                        send_all(&message_queue, subscribers.broadcast(error));
+                        send_all(
+                            &message_queue,
+                            subscribers.broadcast(VideoStreamInfo::Closed {
+                                reason: CloseReason::InitFailed,
+                            }),
+                        );
+                    };
+                    let device = open_device(cameras.as_ref(), &dev, format).and_then(|device| {
+                        let (width, height) = device.resolution();
+                        Ok((
+                            device,
+                            StreamEncoder::new(id, width, height, settings, Instant::now())?,
+                        ))
+                    });
+                    let mut interface = match device {
+                        Ok((device, encoder)) => CameraInterface {
+                            cam: device,
+                            id,
+                            encoder,
+                            paused: false,
+                            recorder: None,
+                            closed: false,
+                        },
                         Err(init_error) => {
-                            message_queue
-                                .send(Message::VideoStreamInfo {
-                                    id,
-                                    action: VideoStreamInfo::InitError {
-                                        message: format!("{:#?}", init_error),
-                                    },
-                                })
-                                .unwrap();
+                            init_failed(
+                                subscribers,
+                                VideoStreamInfo::InitError {
+                                    message: format!("{:#}", init_error),
+                                },
+                            );
                             return;
                         }
                     };
 
                     if let Err(open_error) = interface.cam.open_stream() {
-                        message_queue
-                            .send(Message::VideoStreamInfo {
-                                id,
-                                action: VideoStreamInfo::OpenCamError {
-                                    message: format!("{:#?}", open_error),
-                                },
-                            })
-                            .unwrap();
+                        init_failed(
+                            subscribers,
+                            VideoStreamInfo::OpenCamError {
+                                message: format!("{:#}", open_error),
+                            },
+                        );
                         return;
                     }
+                    message_queue
+                        .send(Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::Streaming(interface.format()),
+                        })
+                        .unwrap();
 
-                    'main: loop {
-                        if !interface.paused {
+                    let mut recovery = CameraRecovery::new(camera_recovery);
+                    let camera_state = |recovery: &CameraRecovery, interface: &CameraInterface| {
+                        *worker_state.lock() = if recovery.is_lost() {
+                            CameraState::Lost
+                        } else if interface.paused {
+                            CameraState::Paused
+                        } else {
+                            CameraState::Streaming
+                        };
+                    };
+                    let reason = 'main: loop {
+                        if recovery.is_lost() {
+                            if recovery
+                                .next_attempt()
+                                .map_or(false, |next_attempt| Instant::now() >= next_attempt)
+                            {
+                                match reopen_device(&mut interface, cameras.as_ref(), &dev, format)
+                                {
+                                    Ok(recording_info) => {
+                                        info!("Reopened the camera of stream {}", id);
+                                        recovery.reopened();
+                                        send_all(
+                                            &message_queue,
+                                            subscribers.broadcast(VideoStreamInfo::CameraRecovered),
+                                        );
+                                        if let Some(action) = recording_info {
+                                            send_all(&message_queue, subscribers.broadcast(action));
+                                        }
+                                        for subscriber in subscribers.streaming() {
+                                            message_queue
+                                                .send(Message::VideoStreamInfo {
+                                                    id: subscriber,
+                                                    action: VideoStreamInfo::Streaming(
+                                                        interface.format(),
+                                                    ),
+                                                })
+                                                .unwrap();
+                                        }
+                                    }
+                                    Err(reopen_error) => {
+                                        warn!(
+                                            "Failed to reopen the camera of stream {}:\n{:#?}",
+                                            id, reopen_error
+                                        );
+                                        if !recovery.reopen_failed(Instant::now()) {
+                                            error!(
+                                                "Giving up on the camera of stream {} after {} attempts to reopen it",
+                                                id,
+                                                recovery.attempts() + 1
+                                            );
+                                            send_all(
+                                                &message_queue,
+                                                subscribers.broadcast(VideoStreamInfo::CameraGaveUp {
+                                                    message: format!(
+                                                        "failed to reopen the camera {} times, the last error was: {:#}",
+                                                        recovery.attempts() + 1,
+                                                        reopen_error
+                                                    ),
+                                                }),
+                                            );
+                                            break 'main CloseReason::CameraGaveUp;
+                                        }
+                                    }
+                                }
+                            }
+                        } else if !interface.paused {
                             match interface.cam.frame() {
                                 Ok(frame) => {
-                                    interface.encoder.encode_frame(frame);
-                                    for packet in interface.encoder.packets() {
-                                        message_queue
-                                            .send(Message::VideoStreamData { id, packet })
-                                            .unwrap();
+                                    recovery.read_ok();
+                                    if let Some(action) = interface.record(&frame) {
+                                        send_all(&message_queue, subscribers.broadcast(action));
+                                    }
+                                    let backlog = link_backlog.load(Ordering::Relaxed);
+                                    match interface.encoder.encode(frame, backlog, Instant::now()) {
+                                        Ok(messages) => {
+                                            for message in messages {
+                                                send_all(
+                                                    &message_queue,
+                                                    subscribers.fan_out(&message),
+                                                );
+                                            }
+                                        }
+                                        Err(encode_error) => {
+                                            send_all(
+                                                &message_queue,
+                                                subscribers.broadcast(
+                                                    VideoStreamInfo::EncodeError {
+                                                        message: format!("{:#}", encode_error),
+                                                    },
+                                                ),
+                                            );
+                                        }
                                     }
                                 }
-                                Err(read_error) => {
-                                    message_queue
-                                        .send(Message::VideoStreamInfo {
-                                            id,
-                                            action: VideoStreamInfo::ReadError {
-                                                message: format!("{:#?}", read_error),
-                                            },
-                                        })
-                                        .unwrap();
-                                }
+                                Err(read_error) => match recovery.read_failed(Instant::now()) {
+                                    ReadFailure::Suppress => {}
+                                    ReadFailure::Report { suppressed } => {
+                                        let message = match suppressed {
+                                            0 => format!("{:#}", read_error),
+                                            suppressed => format!(
+                                                "{:#} ({} more errors since the last one reported)",
+                                                read_error, suppressed
+                                            ),
+                                        };
+                                        send_all(
+                                            &message_queue,
+                                            subscribers
+                                                .broadcast(VideoStreamInfo::ReadError { message }),
+                                        );
+                                    }
+                                    ReadFailure::Lost => {
+                                        warn!(
+                                            "Lost the camera of stream {}, reopening it:\n{:#?}",
+                                            id, read_error
+                                        );
+                                        // the dead handle has to be released before the device can be opened again
+                                        interface.close();
+                                        send_all(
+                                            &message_queue,
+                                            subscribers.broadcast(VideoStreamInfo::CameraLost {
+                                                message: format!("{:#}", read_error),
+                                            }),
+                                        );
+                                    }
+                                },
                             }
                         }
-                        if kill_signal.load(std::sync::atomic::Ordering::Relaxed) {
-                            // me using Iterator::<Item=WhoAsked>::find()
-                            let _ = interface.cam.stop_stream();
-                            break 'main;
-                        }
-                        match update_queue.try_recv() {
-                            Ok(message) => {
-                                // only pay attention if it is for us
-                                if message.0 == id {
-                                    match message.1 {
-                                        VideoStreamAction::Init { .. } => unreachable!(
-                                            "this shoulld be handled by the outer match statement"
-                                        ),
-                                        VideoStreamAction::Close => {
-                                            let _ = interface.cam.stop_stream();
-                                            break 'main;
+                        camera_state(&recovery, &interface);
+                        // handle every command that is waiting. if there is nothing to read from the camera, wait
+                        // for one instead (or until it is time to try reopening the camera)
+                        let mut timeout = match recovery.next_attempt() {
+                            Some(next_attempt) => {
+                                Some(next_attempt.saturating_duration_since(Instant::now()))
+                            }
+                            None if interface.paused => None,
+                            None => Some(Duration::ZERO),
+                        };
+                        loop {
+                            let (subscriber, command) = match next_command(&commands, timeout) {
+                                Ok(Some(command)) => command,
+                                Ok(None) => break,
+                                Err(RecvError::Disconnected) => {
+                                    // the server is gone
+                                    if !recovery.is_lost() {
+                                        let _ = interface.cam.stop_stream();
+                                    }
+                                    break 'main CloseReason::Shutdown;
+                                }
+                            };
+                            timeout = Some(Duration::ZERO);
+                            // answers only the stream that sent the command
+                            let reply = |action| {
+                                message_queue
+                                    .send(Message::VideoStreamInfo {
+                                        id: subscriber,
+                                        action,
+                                    })
+                                    .unwrap();
+                            };
+                            match command {
+                                VideoStreamAction::Init { settings, .. } => {
+                                    // another stream wants frames from this camera
+                                    if subscribers.subscribe(subscriber) {
+                                        info!("Stream {} joined stream {}", subscriber, id);
+                                        interface.encoder.request_keyframe();
+                                        reply(VideoStreamInfo::Streaming(interface.format()));
+                                        // the encoder is shared, so it gets the settings of the other streams
+                                        let shared_settings = *interface.encoder.settings();
+                                        if settings != shared_settings {
+                                            reply(VideoStreamInfo::Reconfigured(shared_settings));
                                         }
-                                        VideoStreamAction::Pause => {
-                                            interface.paused = true;
+                                    }
+                                }
+                                VideoStreamAction::Close if subscribers.len() > 1 => {
+                                    if subscribers.unsubscribe(subscriber) {
+                                        info!("Stream {} left stream {}", subscriber, id);
+                                        reply(VideoStreamInfo::Closed {
+                                            reason: CloseReason::Requested,
+                                        });
+                                    }
+                                }
+                                VideoStreamAction::Close => {
+                                    // the last stream is closed, so the camera is not needed anymore.
+                                    // a lost camera was already closed
+                                    if !recovery.is_lost() {
+                                        if let Err(close_error) = interface.cam.stop_stream() {
+                                            reply(VideoStreamInfo::CloseError {
+                                                message: format!("{:#}", close_error),
+                                            });
                                         }
-                                        VideoStreamAction::Resume => {
-                                            interface.paused = false;
+                                    }
+                                    break 'main CloseReason::Requested;
+                                }
+                                VideoStreamAction::Pause => {
+                                    subscribers.set_paused(subscriber, true);
+                                    reply(VideoStreamInfo::Paused);
+                                }
+                                VideoStreamAction::Resume => {
+                                    subscribers.set_paused(subscriber, false);
+                                    // it missed packets while it was paused
+                                    interface.encoder.request_keyframe();
+                                    reply(VideoStreamInfo::Streaming(interface.format()));
+                                }
+                                VideoStreamAction::Reconfigure(settings) => {
+                                    // the encoder is shared, so this changes every stream
+                                    match interface.encoder.reconfigure(settings, Instant::now()) {
+                                        Ok(()) => send_all(
+                                            &message_queue,
+                                            subscribers
+                                                .broadcast(VideoStreamInfo::Reconfigured(settings)),
+                                        ),
+                                        Err(reconfigure_error) => {
+                                            reply(VideoStreamInfo::ReconfigureError {
+                                                message: format!("{:#}", reconfigure_error),
+                                            })
                                         }
                                     }
                                 }
-                            }
-                            Err(recv_err) => {
-                                match recv_err {
-                                    flume::TryRecvError::Disconnected => panic!(
-                                        "update channel was closed before threads were shut down!"
-                                    ),
-                                    flume::TryRecvError::Empty => {
-                                        if interface.paused {
-                                            // woken up if anything important happens
-                                            // do not go to sleep at all if camera needs reading
-                                            // park_timeout instead of just park because this code has trust issues
-                                            std::thread::park_timeout(PARK_DURATION);
+                                VideoStreamAction::RequestKeyframe => {
+                                    interface.encoder.request_keyframe();
+                                }
+                                VideoStreamAction::Snapshot(_) if recovery.is_lost() => {
+                                    reply(VideoStreamInfo::SnapshotError {
+                                        message: "the camera was lost, and is being reopened"
+                                            .into(),
+                                    });
+                                }
+                                VideoStreamAction::Snapshot(request) => {
+                                    let snapshot = take_snapshot(
+                                        &mut interface,
+                                        cameras.as_ref(),
+                                        &dev,
+                                        format,
+                                        request,
+                                    );
+                                    if interface.closed {
+                                        // it was closed for a snapshot in a different format
+                                        match reopen_device(
+                                            &mut interface,
+                                            cameras.as_ref(),
+                                            &dev,
+                                            format,
+                                        ) {
+                                            Ok(recording_info) => {
+                                                if let Some(action) = recording_info {
+                                                    send_all(
+                                                        &message_queue,
+                                                        subscribers.broadcast(action),
+                                                    );
+                                                }
+                                            }
+                                            Err(reopen_error) => {
+                                                // the stream can not go on without it, so it is reopened like a lost camera
+                                                warn!(
+                                                    "Failed to reopen the camera of stream {} after a snapshot:\n{:#?}",
+                                                    id, reopen_error
+                                                );
+                                                recovery.lost(Instant::now());
+                                                send_all(
+                                                    &message_queue,
+                                                    subscribers.broadcast(
+                                                        VideoStreamInfo::CameraLost {
+                                                            message: format!(
+                                                                "failed to reopen the camera after a snapshot: {:#}",
+                                                                reopen_error
+                                                            ),
+                                                        },
+                                                    ),
+                                                );
+                                            }
                                         }
                                     }
+                                    reply(match snapshot {
+                                        Ok(snapshot) => VideoStreamInfo::Snapshot(snapshot),
+                                        Err(snapshot_error) => VideoStreamInfo::SnapshotError {
+                                            message: format!("{:#}", snapshot_error),
+                                        },
+                                    });
+                                }
+                                VideoStreamAction::StartRecording => {
+                                    let action =
+                                        interface.start_recording(&recording, &active_recordings);
+                                    send_all(&message_queue, subscribers.broadcast(action));
+                                }
+                                VideoStreamAction::StopRecording => {
+                                    let action = interface.stop_recording();
+                                    send_all(&message_queue, subscribers.broadcast(action));
                                 }
                             }
+                            interface.paused = subscribers.all_paused();
+                            camera_state(&recovery, &interface);
+                        }
+                    };
+                    if let Some(recorder) = interface.recorder.take() {
+                        if let Err(record_error) = recorder.finish() {
+                            error!(
+                                "Failed to finish recording of stream {}:\n{:#?}",
+                                id, record_error
+                            );
                         }
                     }
-                }));
+                    info!("Camera worker {} stopped: {:?}", id, reason);
+                    subscribe_waiting(&commands, &mut subscribers);
+                    send_all(
+                        &message_queue,
+                        subscribers.broadcast(VideoStreamInfo::Closed { reason }),
+                    );
+                });
+                self.workers.insert(
+                    id,
+                    CameraWorker {
+                        dev: worker_dev,
+                        format,
+                        commands: commands_send,
+                        state,
+                        handle,
+                    },
+                );
+                self.streams.insert(id, id);
             }
             other => {
-                if let Err(e) = self.updates_queue.send((id, other)) {
-                    warn!("video stream action was received, but no streams are active to receive it:\n{:#?}", e);
-                }
-                for handle in &mut self.handles {
-                    handle.thread().unpark(); // so they actually receive the message
+                let close = matches!(other, VideoStreamAction::Close);
+                let worker_id = self.streams.get(&id).copied();
+                let sent = match worker_id.and_then(|worker_id| self.workers.get(&worker_id)) {
+                    Some(worker) => worker.commands.send((id, other)).is_ok(),
+                    None => false,
+                };
+                if !sent {
+                    // the stream never existed, or its worker already stopped on its own
+                    warn!(
+                        "Video stream action was received for stream {}, which does not exist",
+                        id
+                    );
+                    self.streams.remove(&id);
+                    self.clean();
+                    self.messages_send
+                        .send(Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::NoSuchStream,
+                        })
+                        .unwrap();
+                } else if close {
+                    self.streams.remove(&id);
+                    // the worker stops once its last stream is closed
+                    if let Some(worker_id) = worker_id {
+                        if !self.streams.values().any(|other| *other == worker_id) {
+                            if let Some(worker) = self.workers.remove(&worker_id) {
+                                let (dev, handle) = worker.stop();
+                                self.stopping.push((worker_id, dev, handle));
+                            }
+                        }
+                    }
                 }
             }
         }
     }
 
+    /// closes every stream, once the dashboard that opened them is gone
+    pub fn close_all(&mut self) {
+        let ids = self.streams.keys().copied().collect::<Vec<_>>();
+        for id in ids {
+            self.feed_ctrl_msg(id, VideoStreamAction::Close);
+        }
+    }
+
     pub async fn collect_message(&mut self) -> Message {
         self.message_queue.recv_async().await.unwrap()
     }
@@ -189,33 +826,317 @@ impl CameraServer {
         self.message_queue.clone()
     }
 
-    pub fn clean(&mut self) {
-        debug!("camera server: cleaning up thread handles");
-        self.handles
-            .drain_filter(|i| i.is_finished())
-            .for_each(|i| {
-                if let Err(thread_err) = i.join() {
-                    error!(
-                        "camera worker thread did not exit gracefully:\n{:#?}",
-                        thread_err
-                    );
+    /// the chunks of recordings being downloaded, to send only while the link is not backed up (see
+    /// [`DOWNLOAD_BACKLOG_LIMIT`])
+    pub fn get_download_receiver(&self) -> Receiver<Message> {
+        self.download_queue.clone()
+    }
+
+    /// the counter of bytes waiting to be sent to the dashboard, for the connection to keep up to date
+    pub fn link_backlog(&self) -> Arc<AtomicUsize> {
+        self.link_backlog.clone()
+    }
+
+    /// lists the cameras on the bot, and if they have streams open on them
+    pub fn list_cameras(&self) -> Vec<CameraListing> {
+        let cameras = match self.cameras.list() {
+            Ok(cameras) => cameras,
+            Err(list_error) => {
+                error!("Failed to list cameras:\n{:#?}", list_error);
+                return vec![];
+            }
+        };
+        cameras
+            .into_iter()
+            .map(|info| CameraListing {
+                in_use: self
+                    .workers
+                    .values()
+                    .any(|worker| worker.is_using(info.index)),
+                index: info.index,
+                human_name: info.human_name,
+                description: info.description,
+            })
+            .collect()
+    }
+
+    /// sends the formats the camera with device ID `index` supports to the dashboard
+    pub fn send_camera_formats(&mut self, index: usize) {
+        let formats = self.cameras.formats(index).map_err(|formats_error| {
+            warn!(
+                "Failed to read the formats of camera {}:\n{:#?}",
+                index, formats_error
+            );
+            format!("{:#}", formats_error)
+        });
+        self.messages_send
+            .send(Message::CameraFormats { index, formats })
+            .unwrap();
+    }
+
+    pub fn recording_ctrl_msg(&mut self, action: RecordingAction) {
+        match action {
+            RecordingAction::List => self.send_recording_list(),
+            RecordingAction::Download { name } => {
+                let path = match recorder::recording_path(&self.recording.dir, &name) {
+                    Ok(path) => path,
+                    Err(path_error) => {
+                        self.messages_send
+                            .send(Message::RecordingInfo(RecordingInfo::DownloadError {
+                                name,
+                                message: format!("{:#}", path_error),
+                            }))
+                            .unwrap();
+                        return;
+                    }
+                };
+                info!("Sending recording {}", name);
+                let download_queue = self.downloads_send.clone();
+                let kill_signal = self.kill_signal.clone();
+                // the file is read as it is sent, so that it does not all sit in memory at once
+                thread::spawn(move || {
+                    let send_chunks = || -> Result<()> {
+                        let mut file = File::open(path)?;
+                        let mut chunk = vec![0; DOWNLOAD_CHUNK_SIZE];
+                        let mut filled = file.read(&mut chunk)?;
+                        loop {
+                            // read one chunk ahead, to know if this is the last one
+                            let mut next = vec![0; DOWNLOAD_CHUNK_SIZE];
+                            let next_filled = file.read(&mut next)?;
+                            chunk.truncate(filled);
+                            let last = next_filled == 0;
+                            let message = Message::RecordingInfo(RecordingInfo::Chunk {
+                                name: name.clone(),
+                                data: chunk,
+                                last,
+                            });
+                            if !send_download(&download_queue, &kill_signal, message)? || last {
+                                return Ok(());
+                            }
+                            chunk = next;
+                            filled = next_filled;
+                        }
+                    };
+                    if let Err(download_error) = send_chunks() {
+                        warn!("Failed to send recording {}:\n{:#?}", name, download_error);
+                        let _ = send_download(
+                            &download_queue,
+                            &kill_signal,
+                            Message::RecordingInfo(RecordingInfo::DownloadError {
+                                name: name.clone(),
+                                message: format!("{:#}", download_error),
+                            }),
+                        );
+                    }
+                });
+            }
+            RecordingAction::Delete { name } => {
+                let deleted =
+                    recorder::recording_path(&self.recording.dir, &name).and_then(|path| {
+                        if self.active_recordings.contains(&name) {
+                            bail!("{} is still being recorded", name);
+                        }
+                        Ok(fs::remove_file(path)?)
+                    });
+                match deleted {
+                    Ok(()) => {
+                        info!("Deleted recording {}", name);
+                        self.send_recording_list();
+                    }
+                    Err(delete_error) => {
+                        self.messages_send
+                            .send(Message::RecordingInfo(RecordingInfo::DeleteError {
+                                name,
+                                message: format!("{:#}", delete_error),
+                            }))
+                            .unwrap();
+                    }
                 }
+            }
+        }
+    }
+
+    /// sends the list of recordings to the dashboard
+    fn send_recording_list(&mut self) {
+        let recordings =
+            recorder::list_recordings(&self.recording.dir).unwrap_or_else(|list_error| {
+                error!("Failed to list recordings:\n{:#?}", list_error);
+                vec![]
             });
+        self.messages_send
+            .send(Message::RecordingInfo(RecordingInfo::List(recordings)))
+            .unwrap();
+    }
+
+    /// the state of every open camera
+    pub fn telemetry(&self) -> Vec<CameraTelemetry> {
+        self.workers
+            .iter()
+            .filter(|(_, worker)| !worker.handle.is_finished())
+            .map(|(worker_id, worker)| CameraTelemetry {
+                device: worker.dev.clone(),
+                streams: self
+                    .streams
+                    .values()
+                    .filter(|stream_worker| *stream_worker == worker_id)
+                    .count(),
+                state: *worker.state.lock(),
+            })
+            .collect()
+    }
+
+    /// sends the list of cameras to the dashboard
+    pub fn send_camera_list(&mut self) {
+        self.messages_send
+            .send(Message::Cameras(self.list_cameras()))
+            .unwrap();
+    }
+
+    pub fn clean(&mut self) {
+        debug!("camera server: cleaning up thread handles");
+        let finished = self
+            .workers
+            .iter()
+            .filter(|(_, worker)| worker.handle.is_finished())
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in finished {
+            self.workers.remove(&id).unwrap().join(id);
+            self.streams.retain(|_, worker_id| *worker_id != id);
+        }
+        let (stopped, stopping) = std::mem::take(&mut self.stopping)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(_, _, handle)| handle.is_finished());
+        self.stopping = stopping;
+        for (id, _, handle) in stopped {
+            join_worker(id, handle);
+        }
+    }
+}
+
+/// waits up to `timeout` for the next command for a stream, or forever if it is `None`.
+/// returns `None` if there was none in time
+fn next_command(
+    commands: &Receiver<(Uuid, VideoStreamAction)>,
+    timeout: Option<Duration>,
+) -> Result<Option<(Uuid, VideoStreamAction)>, RecvError> {
+    match timeout {
+        None => commands.recv().map(Some),
+        Some(timeout) => match commands.recv_timeout(timeout) {
+            Ok(command) => Ok(Some(command)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(RecvError::Disconnected),
+        },
     }
 }
 
+/// subscribes the streams that are waiting to join a worker that is stopping, so that they hear about it
+fn subscribe_waiting(
+    commands: &Receiver<(Uuid, VideoStreamAction)>,
+    subscribers: &mut Subscribers,
+) {
+    for (id, command) in commands.try_iter() {
+        if let VideoStreamAction::Init { .. } = command {
+            subscribers.subscribe(id);
+        }
+    }
+}
+
+fn send_all(message_queue: &flume::Sender<Message>, messages: Vec<Message>) {
+    for message in messages {
+        message_queue.send(message).unwrap();
+    }
+}
+
+/// opens what a stream gets its frames from
+fn open_device(
+    cameras: &dyn CameraBackend,
+    dev: &CameraDevice,
+    format: Option<CameraFormat>,
+) -> Result<Box<dyn Camera>> {
+    match dev {
+        CameraDevice::Index(index) => open_camera(cameras, *index, format),
+        CameraDevice::TestPattern(pattern) => TestPatternCamera::new(pattern.clone())
+            .map(|camera| Box::new(camera) as Box<dyn Camera>),
+    }
+}
+
+/// opens the device of a stream again in the format it was opened in, replacing its closed camera
+fn reopen_device(
+    interface: &mut CameraInterface,
+    cameras: &dyn CameraBackend,
+    dev: &CameraDevice,
+    format: Option<CameraFormat>,
+) -> Result<Option<VideoStreamInfo>> {
+    let mut camera = open_device(cameras, dev, format)?;
+    camera.open_stream()?;
+    interface.reopen(camera)
+}
+
+/// opens a camera, making sure it supports `format` first so that the error is clear if it does not
+fn open_camera(
+    cameras: &dyn CameraBackend,
+    index: usize,
+    format: Option<CameraFormat>,
+) -> Result<Box<dyn Camera>> {
+    if let Some(format) = format {
+        let supported = cameras.formats(index)?;
+        if !supported.contains(&format) {
+            return Err(anyhow!(
+                "camera {} does not support {}, the supported formats are: {}",
+                index,
+                format,
+                supported
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    }
+    cameras.open(index, format)
+}
+
+/// takes a snapshot from the camera of a stream, that was opened from `dev` in `stream_format`.
+/// the camera is left closed if the snapshot is in a different format, for the stream to open it again
+fn take_snapshot(
+    interface: &mut CameraInterface,
+    cameras: &dyn CameraBackend,
+    dev: &CameraDevice,
+    stream_format: Option<CameraFormat>,
+    request: SnapshotRequest,
+) -> Result<Snapshot> {
+    let frame = match (request.format, dev) {
+        (None, _) => interface.cam.frame()?,
+        (Some(format), _) if Some(format) == stream_format => interface.cam.frame()?,
+        (Some(format), CameraDevice::TestPattern(_)) => {
+            return Err(anyhow!(
+                "test patterns can not be captured in a different format ({})",
+                format
+            ))
+        }
+        (Some(format), CameraDevice::Index(index)) => {
+            // the camera can only be opened once, so the stream closes it while the snapshot is taken
+            interface.close();
+            let mut camera = open_camera(cameras, *index, Some(format))?;
+            camera.open_stream()?;
+            let frame = camera.frame();
+            let _ = camera.stop_stream();
+            frame?
+        }
+    };
+    encode_snapshot(frame, request.encoding)
+}
+
 impl Drop for CameraServer {
     fn drop(&mut self) {
         self.kill_signal
             .store(true, std::sync::atomic::Ordering::Relaxed);
-        for thread in self.handles.drain(..) {
-            thread.thread().unpark();
-            if let Err(thread_err) = thread.join() {
-                error!(
-                    "camera worker thread did not exit gracefully:\n{:#?}",
-                    thread_err
-                );
-            }
+        for (id, worker) in self.workers.drain() {
+            worker.join(id);
+        }
+        for (id, _, handle) in self.stopping.drain(..) {
+            join_worker(id, handle);
         }
     }
 }
@@ -1,31 +1,145 @@
-//! Configuration loading
-
-use anyhow::Result;
-use std::{fs::OpenOptions, io::Read, net::SocketAddrV4, path::Path};
-use yaml_rust::{Yaml, YamlLoader};
-
-pub fn load_config<P: AsRef<Path>>(path: P) -> Result<Conf> {
-    let mut yaml = String::new();
-    OpenOptions::new()
-        .read(true)
-        .open(path)?
-        .read_to_string(&mut yaml)?;
-    let raw_cfg = YamlLoader::load_from_str(&yaml)?[0]
-        .clone()
-        .into_hash()
-        .unwrap();
-    let addr = raw_cfg
-        .get(&Yaml::String("addr".into()))
-        .unwrap()
-        .as_str()
-        .unwrap()
-        .to_string()
-        .parse::<SocketAddrV4>()?;
-
-    Ok(Conf { addr })
+//! Configuration loading, the config file itself is defined in [`aareocams_config::bot`]
+
+pub use aareocams_config::bot::*;
+
+use aareocams_net::Waypoint;
+use anyhow::{bail, Context, Result};
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+};
+
+/// replaces the list of waypoints in the config file at `path`, leaving the rest of the file (and its comments) as is
+pub fn save_waypoints<P: AsRef<Path>>(path: P, waypoints: &[Waypoint]) -> Result<()> {
+    let path = path.as_ref();
+    let yaml = fs::read_to_string(path)?;
+    let new = replace_waypoints(&yaml, waypoints)?;
+    // the new file is written next to the old one and moved over it, so that losing power part way through leaves
+    // one or the other, and never a config the bot can not start with
+    let mut temp_name = path.file_name().map(OsString::from).unwrap_or_default();
+    temp_name.push(".tmp");
+    let temp = path.with_file_name(temp_name);
+    let mut file = File::create(&temp)?;
+    file.write_all(new.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp, path)?;
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// `yaml` with the top level `waypoints` key set to `waypoints`. only the lines of that key are changed, it is added
+/// to the end if it is not there
+fn replace_waypoints(yaml: &str, waypoints: &[Waypoint]) -> Result<String> {
+    let mut node = "waypoints:".to_string();
+    if waypoints.is_empty() {
+        node += " []\n";
+    } else {
+        node += "\n";
+        let list = serde_yaml::to_string(waypoints)?;
+        for line in list
+            .trim_start_matches("---")
+            .trim_start_matches('\n')
+            .lines()
+        {
+            node += &format!("  {}\n", line);
+        }
+    }
+
+    let lines = yaml.lines().collect::<Vec<_>>();
+    let start = lines.iter().position(|line| {
+        line.strip_prefix("waypoints")
+            .map_or(false, |rest| rest.trim_start().starts_with(':'))
+    });
+    let new = match start {
+        Some(start) => {
+            // the key goes on until the next line that is not indented, leaving the blank lines before it
+            let end = lines[start + 1..]
+                .iter()
+                .position(|line| !line.is_empty() && !line.starts_with([' ', '\t', '-']))
+                .map_or(lines.len(), |end| start + 1 + end);
+            let end = (start + 1..end)
+                .rev()
+                .find(|line| !lines[*line].trim().is_empty())
+                .map_or(start + 1, |last| last + 1);
+            let mut new = lines[..start].join("\n");
+            if start > 0 {
+                new += "\n";
+            }
+            new += &node;
+            for line in &lines[end..] {
+                new += line;
+                new += "\n";
+            }
+            new
+        }
+        None => {
+            let mut new = yaml.to_string();
+            if !new.is_empty() && !new.ends_with('\n') {
+                new += "\n";
+            }
+            new + &node
+        }
+    };
+    // so that a config the bot can not start with is never written
+    let cfg = parse_config(&new).context("The config would not be valid with the new waypoints")?;
+    if cfg.waypoints != waypoints {
+        bail!("The waypoints were not saved as they should have been");
+    }
+    Ok(new)
+}
+
+#[test]
+fn test_replace_waypoints() {
+    let waypoints = [
+        Waypoint {
+            name: "start".to_string(),
+            position: 0.0,
+        },
+        Waypoint {
+            name: "top: of the hill".to_string(),
+            position: 12.5,
+        },
+    ];
+    let yaml = include_str!("../../../config/bot.yml.template");
+    let new = replace_waypoints(yaml, &waypoints).unwrap();
+    assert_eq!(parse_config(&new).unwrap().waypoints, waypoints);
+    // the comments are kept, and the lines around the waypoints are not touched
+    let unchanged = |yaml: &str| {
+        yaml.lines()
+            .filter(|line| !line.starts_with("  ") && !line.starts_with('-'))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(unchanged(&new), unchanged(yaml));
+    assert_eq!(replace_waypoints(&new, &waypoints).unwrap(), new);
+
+    let new = replace_waypoints(&new, &[]).unwrap();
+    assert!(new.contains("\nwaypoints: []\n"));
+    assert!(parse_config(&new).unwrap().waypoints.is_empty());
+
+    let minimal = "# the bot\nnetwork:\n  addr: \"0.0.0.0:6440\"";
+    let new = replace_waypoints(minimal, &waypoints[..1]).unwrap();
+    assert!(new.starts_with(minimal));
+    assert_eq!(parse_config(&new).unwrap().waypoints, &waypoints[..1]);
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct Conf {
-    pub addr: SocketAddrV4,
+#[test]
+fn test_save_waypoints() {
+    let dir = std::env::temp_dir().join(format!("aareocams-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("bot.yml");
+    fs::write(&path, include_str!("../../../config/bot.yml.template")).unwrap();
+    let waypoints = [Waypoint {
+        name: "start".to_string(),
+        position: 1.5,
+    }];
+    save_waypoints(&path, &waypoints).unwrap();
+    assert_eq!(load_config(&path).unwrap().waypoints, waypoints);
+    // the temporary file was moved over the config
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    fs::remove_dir_all(dir).unwrap();
 }
@@ -0,0 +1,721 @@
+//! Closed loop control of the drivetrain
+//!
+//! the controllers here do not touch hardware directly, they take the measured state (from [`crate::encoder`])
+//! and return a motor speed, and [`Drivetrain`] ties them to a [`MotorController`] and [`QIEncoderInterface`].
+
+use std::time::{Duration, Instant};
+
+use aareocams_net::{HomingState, TravelLimit};
+
+use crate::{
+    config::{DrivetrainConf, PidGains},
+    encoder::{Odometry, QuadratureDecoder},
+    hal::{MotorController, QIEncoderInterface},
+    limits::{LimitEvent, TravelLimits},
+};
+
+/// maximum output of the controllers, matching the range of [`MotorController::set_speed`]
+const MAX_OUTPUT: f64 = 100.0;
+
+#[derive(Debug, Clone)]
+pub struct Pid {
+    gains: PidGains,
+    integral: f64,
+    last_error: Option<f64>,
+}
+
+impl Pid {
+    pub fn new(gains: PidGains) -> Self {
+        Self {
+            gains,
+            integral: 0.0,
+            last_error: None,
+        }
+    }
+
+    /// returns the output of the controller (not including feed forward), limited to `-limit..=limit`
+    pub fn update(&mut self, error: f64, dt: f64, limit: f64) -> f64 {
+        let derivative = match self.last_error {
+            Some(last) if dt > 0.0 => (error - last) / dt,
+            _ => 0.0,
+        };
+        self.last_error = Some(error);
+        self.integral += error * dt;
+        // keep the integral from winding up past what could ever be output
+        if self.gains.ki != 0.0 {
+            let max_integral = limit / self.gains.ki.abs();
+            self.integral = self.integral.clamp(-max_integral, max_integral);
+        }
+        (self.gains.kp * error + self.gains.ki * self.integral + self.gains.kd * derivative)
+            .clamp(-limit, limit)
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last_error = None;
+    }
+
+    pub fn gains(&self) -> PidGains {
+        self.gains
+    }
+}
+
+/// a point along a motion profile
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Setpoint {
+    pub position: f64,
+    pub velocity: f64,
+}
+
+/// generates a trapezoidal (acceleration limited) motion profile, one step at a time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrapezoidalProfile {
+    pub max_velocity: f64,
+    pub max_acceleration: f64,
+}
+
+impl TrapezoidalProfile {
+    /// the next setpoint on the way from `current` to `target`
+    pub fn next(&self, current: Setpoint, target: f64, dt: f64) -> Setpoint {
+        let remaining = target - current.position;
+        // fastest speed that can still stop at the target
+        let stopping_velocity = (2.0 * self.max_acceleration * remaining.abs()).sqrt();
+        let desired = remaining.signum() * stopping_velocity.min(self.max_velocity);
+        let max_change = self.max_acceleration * dt;
+        let velocity =
+            current.velocity + (desired - current.velocity).clamp(-max_change, max_change);
+        let position = current.position + (current.velocity + velocity) / 2.0 * dt;
+
+        if (target - position).signum() != remaining.signum() || remaining == 0.0 {
+            // went past the target during this step
+            Setpoint {
+                position: target,
+                velocity: 0.0,
+            }
+        } else {
+            Setpoint { position, velocity }
+        }
+    }
+
+    /// distance needed to stop from `velocity`
+    pub fn stopping_distance(&self, velocity: f64) -> f64 {
+        velocity * velocity / (2.0 * self.max_acceleration)
+    }
+}
+
+/// closed loop velocity controller, with acceleration limiting
+#[derive(Debug, Clone)]
+pub struct VelocityController {
+    pid: Pid,
+    max_acceleration: f64,
+    target: f64,
+    /// acceleration limited version of `target`
+    setpoint: f64,
+    error: f64,
+}
+
+impl VelocityController {
+    pub fn new(gains: PidGains, max_acceleration: f64) -> Self {
+        Self {
+            pid: Pid::new(gains),
+            max_acceleration,
+            target: 0.0,
+            setpoint: 0.0,
+            error: 0.0,
+        }
+    }
+
+    /// start controlling from the current (measured) velocity, so there is no jump in output
+    pub fn reset(&mut self, measured: f64) {
+        self.pid.reset();
+        self.setpoint = measured;
+        self.target = measured;
+    }
+
+    pub fn set_target(&mut self, target: f64) {
+        self.target = target;
+    }
+
+    /// sets the setpoint directly, bypassing acceleration limiting (for when it has already been limited)
+    pub fn set_setpoint(&mut self, setpoint: f64) {
+        self.target = setpoint;
+        self.setpoint = setpoint;
+    }
+
+    /// returns the motor speed
+    pub fn update(&mut self, measured: f64, dt: f64) -> f64 {
+        let max_change = self.max_acceleration * dt;
+        self.setpoint += (self.target - self.setpoint).clamp(-max_change, max_change);
+        self.error = self.setpoint - measured;
+        let feed_forward = self.pid.gains().kf * self.setpoint;
+        (feed_forward + self.pid.update(self.error, dt, MAX_OUTPUT)).clamp(-MAX_OUTPUT, MAX_OUTPUT)
+    }
+
+    pub fn setpoint(&self) -> f64 {
+        self.setpoint
+    }
+
+    /// difference between the setpoint and measured velocity, as of the last update
+    pub fn tracking_error(&self) -> f64 {
+        self.error
+    }
+}
+
+/// closed loop position controller, following a trapezoidal profile
+#[derive(Debug, Clone)]
+pub struct PositionController {
+    pid: Pid,
+    profile: TrapezoidalProfile,
+    velocity: VelocityController,
+    target: f64,
+    setpoint: Setpoint,
+    error: f64,
+}
+
+impl PositionController {
+    pub fn new(gains: PidGains, velocity_gains: PidGains, profile: TrapezoidalProfile) -> Self {
+        Self {
+            pid: Pid::new(gains),
+            profile,
+            velocity: VelocityController::new(velocity_gains, profile.max_acceleration),
+            target: 0.0,
+            setpoint: Setpoint::default(),
+            error: 0.0,
+        }
+    }
+
+    /// start controlling from the current (measured) state
+    pub fn reset(&mut self, position: f64, velocity: f64) {
+        self.pid.reset();
+        self.velocity.reset(velocity);
+        self.setpoint = Setpoint { position, velocity };
+        self.target = position;
+    }
+
+    pub fn set_target(&mut self, target: f64) {
+        self.target = target;
+    }
+
+    pub fn target(&self) -> f64 {
+        self.target
+    }
+
+    /// sets the cruising speed of the profile
+    pub fn set_max_velocity(&mut self, max_velocity: f64) {
+        self.profile.max_velocity = max_velocity;
+    }
+
+    /// returns the motor speed
+    pub fn update(&mut self, position: f64, velocity: f64, dt: f64) -> f64 {
+        self.setpoint = self.profile.next(self.setpoint, self.target, dt);
+        self.error = self.setpoint.position - position;
+        let correction = self.pid.update(self.error, dt, self.profile.max_velocity);
+        self.velocity
+            .set_setpoint(self.setpoint.velocity + correction);
+        self.velocity.update(velocity, dt)
+    }
+
+    pub fn setpoint(&self) -> Setpoint {
+        self.setpoint
+    }
+
+    /// difference between the profile position and measured position, as of the last update
+    pub fn tracking_error(&self) -> f64 {
+        self.error
+    }
+
+    /// if the profile has finished, and the measured position is within `tolerance` of the target
+    pub fn is_done(&self, tolerance: f64) -> bool {
+        self.setpoint.position == self.target && self.error.abs() <= tolerance
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlMode {
+    /// motor is stopped, with no control
+    Stopped,
+    /// open loop speed (-100 to 100)
+    OpenLoop(f32),
+    /// closed loop velocity, in m/s
+    Velocity(f64),
+    /// closed loop position, in meters along the cable
+    Position(f64),
+}
+
+/// the current state of the drivetrain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriveStatus {
+    pub mode: ControlMode,
+    /// meters along the cable
+    pub position: f64,
+    /// m/s
+    pub velocity: f64,
+    /// the last speed sent to the motor
+    pub output: f32,
+    /// difference between where the controller wants to be and where it is
+    /// (in m for position control, m/s for velocity control, zero otherwise)
+    pub tracking_error: f64,
+}
+
+/// the drivetrain, with a motor and encoder
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct Drivetrain<M: MotorController, E: QIEncoderInterface> {
+    #[derivative(Debug = "ignore")]
+    motor: M,
+    #[derivative(Debug = "ignore")]
+    decoder: QuadratureDecoder<E>,
+    odometry: Odometry,
+    control_period: Duration,
+    max_velocity: f64,
+    max_acceleration: f64,
+    last_control: Option<Instant>,
+    velocity: VelocityController,
+    position: PositionController,
+    limits: TravelLimits,
+    /// limit changes that have not been taken yet
+    limit_events: Vec<LimitEvent>,
+    status: DriveStatus,
+}
+
+impl<M: MotorController, E: QIEncoderInterface> Drivetrain<M, E> {
+    pub fn new(mut motor: M, encoder: E, cfg: &DrivetrainConf, limits: TravelLimits) -> Self {
+        motor.set_speed(0.0);
+        let decoder = QuadratureDecoder::new(encoder);
+        let profile = TrapezoidalProfile {
+            max_velocity: cfg.max_velocity,
+            max_acceleration: cfg.max_acceleration,
+        };
+        Self {
+            motor,
+            odometry: Odometry::new(cfg.wheel_diameter, decoder.counts_per_rev()),
+            decoder,
+            control_period: cfg.control_period,
+            max_velocity: cfg.max_velocity,
+            max_acceleration: cfg.max_acceleration,
+            last_control: None,
+            velocity: VelocityController::new(cfg.velocity_gains, cfg.max_acceleration),
+            position: PositionController::new(cfg.position_gains, cfg.velocity_gains, profile),
+            limits,
+            limit_events: vec![],
+            status: DriveStatus {
+                mode: ControlMode::Stopped,
+                position: 0.0,
+                velocity: 0.0,
+                output: 0.0,
+                tracking_error: 0.0,
+            },
+        }
+    }
+
+    /// changes the control mode.
+    ///
+    /// if the new mode would move toward a travel limit that has been reached (or a position outside the soft limits)
+    /// it is refused, and the mode is not changed
+    pub fn set_mode(&mut self, mode: ControlMode) -> Result<(), TravelLimit> {
+        let (position, velocity) = (self.position(), self.velocity());
+        let refused = match mode {
+            ControlMode::Stopped => None,
+            ControlMode::OpenLoop(speed) => self.limits.blocking(speed as f64),
+            ControlMode::Velocity(target) => self.limits.blocking(target),
+            ControlMode::Position(target) => self
+                .limits
+                .outside(target)
+                .or_else(|| self.limits.blocking(target - position)),
+        };
+        if let Some(limit) = refused {
+            return Err(limit);
+        }
+
+        match mode {
+            ControlMode::Stopped => {
+                self.motor.set_speed(0.0);
+                self.status.output = 0.0;
+            }
+            ControlMode::OpenLoop(speed) => {
+                self.motor.set_speed(speed);
+                self.status.output = speed;
+            }
+            ControlMode::Velocity(target) => {
+                if !matches!(self.status.mode, ControlMode::Velocity(..)) {
+                    self.velocity.reset(velocity);
+                }
+                self.velocity.set_target(target);
+            }
+            ControlMode::Position(target) => {
+                if !matches!(self.status.mode, ControlMode::Position(..)) {
+                    self.position.reset(position, velocity);
+                }
+                self.position.set_target(target);
+            }
+        }
+        self.status.mode = mode;
+        self.status.tracking_error = 0.0;
+        Ok(())
+    }
+
+    /// go to a position along the cable, at `speed` (m/s) or the configured maximum.
+    ///
+    /// `speed` is limited to the configured maximum
+    pub fn go_to(&mut self, target: f64, speed: Option<f64>) -> Result<(), TravelLimit> {
+        let speed = speed.map_or(self.max_velocity, |speed| {
+            speed.abs().min(self.max_velocity)
+        });
+        self.position.set_max_velocity(speed);
+        self.set_mode(ControlMode::Position(target))
+    }
+
+    /// sets the current position along the cable, without moving
+    pub fn set_position(&mut self, position: f64) {
+        self.decoder.set_count(self.odometry.counts(position));
+        self.restart_profile();
+    }
+
+    /// sets the position to zero at the next index pulse of the encoder
+    pub fn arm_homing(&mut self) {
+        self.decoder.arm_homing();
+    }
+
+    pub fn homing(&self) -> HomingState {
+        self.decoder.homing()
+    }
+
+    /// plans the profile to the target again from where the drivetrain is, after the position changed without it
+    /// moving
+    fn restart_profile(&mut self) {
+        if let ControlMode::Position(target) = self.status.mode {
+            let (position, velocity) = (self.position(), self.velocity());
+            self.position.reset(position, velocity);
+            self.position.set_target(target);
+        }
+    }
+
+    /// samples the encoder, and runs the control loop if it is due.
+    ///
+    /// this should be called as often as possible, since the encoder is read here
+    pub fn tick(&mut self, now: Instant) {
+        let homing = self.decoder.homing();
+        self.decoder.update(now);
+        if homing != self.decoder.homing() && self.decoder.homing() == HomingState::Homed {
+            info!("Homed at the index pulse of the encoder");
+            self.restart_profile();
+        }
+        self.check_limits();
+        let dt = match self.last_control {
+            Some(last) => now.saturating_duration_since(last),
+            None => {
+                self.last_control = Some(now);
+                return;
+            }
+        };
+        if dt < self.control_period {
+            return;
+        }
+        self.last_control = Some(now);
+        let dt = dt.as_secs_f64();
+
+        self.status.position = self.position();
+        self.status.velocity = self.velocity();
+        if let ControlMode::OpenLoop(speed) = self.status.mode {
+            // open loop output does not slow down on its own, so once it can only just stop before a soft limit,
+            // come to a controlled stop instead
+            let (min, max) = self
+                .limits
+                .velocity_range(self.status.position, self.max_acceleration);
+            if (speed > 0.0 && self.status.velocity > max)
+                || (speed < 0.0 && self.status.velocity < min)
+            {
+                info!("Stopping open loop output before the soft limit");
+                self.velocity.reset(self.status.velocity);
+                self.velocity.set_target(0.0);
+                self.status.mode = ControlMode::Velocity(0.0);
+            }
+        }
+        let output = match self.status.mode {
+            ControlMode::Stopped | ControlMode::OpenLoop(..) => return,
+            ControlMode::Velocity(target) => {
+                // slow down early enough to stop at the soft limits
+                let (min, max) = self
+                    .limits
+                    .velocity_range(self.status.position, self.max_acceleration);
+                self.velocity.set_target(target.clamp(min, max));
+                let output = self.velocity.update(self.status.velocity, dt);
+                self.status.tracking_error = self.velocity.tracking_error();
+                output
+            }
+            ControlMode::Position(..) => {
+                let output = self
+                    .position
+                    .update(self.status.position, self.status.velocity, dt);
+                self.status.tracking_error = self.position.tracking_error();
+                output
+            }
+        } as f32;
+        let output = if self.limits.blocking(output as f64).is_some() {
+            0.0
+        } else {
+            output
+        };
+        self.motor.set_speed(output);
+        self.status.output = output;
+    }
+
+    /// checks the travel limits, stopping if one has been reached while moving toward it
+    fn check_limits(&mut self) {
+        let events = self.limits.update(self.position());
+        if events.is_empty() {
+            return;
+        }
+        if let Some(limit) = self.limits.blocking(self.status.output as f64) {
+            warn!("Reached travel limit {:?}, stopping", limit);
+            self.motor.set_speed(0.0);
+            self.status.output = 0.0;
+            match self.status.mode {
+                ControlMode::Stopped | ControlMode::Velocity(..) => {}
+                ControlMode::OpenLoop(..) => self.status.mode = ControlMode::Stopped,
+                ControlMode::Position(..) => {
+                    // give up on getting to the target, and come to a controlled stop
+                    self.velocity.reset(self.velocity());
+                    self.velocity.set_target(0.0);
+                    self.status.mode = ControlMode::Velocity(0.0);
+                }
+            }
+        }
+        self.limit_events.extend(events);
+    }
+
+    /// takes the changes in which travel limits have been reached
+    pub fn take_limit_events(&mut self) -> Vec<LimitEvent> {
+        std::mem::take(&mut self.limit_events)
+    }
+
+    /// meters along the cable
+    pub fn position(&self) -> f64 {
+        self.odometry.distance(self.decoder.count())
+    }
+
+    /// m/s
+    pub fn velocity(&self) -> f64 {
+        self.odometry.velocity(self.decoder.velocity())
+    }
+
+    pub fn status(&self) -> DriveStatus {
+        DriveStatus {
+            position: self.position(),
+            velocity: self.velocity(),
+            ..self.status
+        }
+    }
+
+    /// if the drivetrain is in position control and has reached its target
+    pub fn at_target(&self, tolerance: f64) -> bool {
+        matches!(self.status.mode, ControlMode::Position(..)) && self.position.is_done(tolerance)
+    }
+
+    pub fn decoder(&mut self) -> &mut QuadratureDecoder<E> {
+        &mut self.decoder
+    }
+
+    pub fn odometry(&self) -> Odometry {
+        self.odometry
+    }
+}
+
+#[cfg(test)]
+fn test_drivetrain() -> (
+    Drivetrain<crate::hal::sim::SimulatedMotor, crate::hal::sim::SimulatedEncoder>,
+    crate::hal::sim::SimulatedMotorHandle,
+) {
+    use crate::hal::sim::{SimulatedEncoder, SimulatedMotor};
+
+    let encoder = SimulatedEncoder::new(100);
+    // 2000 counts/s at full speed, about 0.785 m/s
+    let motor = SimulatedMotor::new(encoder.handle(), 2000.0, 0.05);
+    let handle = motor.handle();
+    let cfg = DrivetrainConf {
+        wheel_diameter: 0.05,
+        max_velocity: 0.5,
+        max_acceleration: 1.0,
+        control_period: Duration::from_millis(10),
+        velocity_gains: PidGains {
+            kp: 40.0,
+            ki: 100.0,
+            kd: 0.0,
+            kf: 127.0,
+        },
+        position_gains: PidGains {
+            kp: 5.0,
+            ki: 0.0,
+            kd: 0.0,
+            kf: 0.0,
+        },
+        manual_speed: 0.2,
+        position_tolerance: 0.005,
+        status_period: Duration::from_millis(100),
+    };
+    (
+        Drivetrain::new(motor, encoder, &cfg, TravelLimits::default()),
+        handle,
+    )
+}
+
+/// runs the drivetrain and plant together for `time`, sampling every 250us
+#[cfg(test)]
+fn run_for(
+    drivetrain: &mut Drivetrain<crate::hal::sim::SimulatedMotor, crate::hal::sim::SimulatedEncoder>,
+    plant: &crate::hal::sim::SimulatedMotorHandle,
+    start: &mut Instant,
+    time: Duration,
+) {
+    const STEP: Duration = Duration::from_micros(250);
+    let end = *start + time;
+    while *start < end {
+        plant.advance(STEP);
+        *start += STEP;
+        drivetrain.tick(*start);
+    }
+}
+
+#[test]
+fn test_profile_limits() {
+    let profile = TrapezoidalProfile {
+        max_velocity: 1.0,
+        max_acceleration: 2.0,
+    };
+    let mut sp = Setpoint::default();
+    let dt = 0.001;
+    let mut steps = 0;
+    while sp.position != 3.0 {
+        let next = profile.next(sp, 3.0, dt);
+        assert!(next.velocity <= 1.0 + 1e-9);
+        // the last step snaps to the target, so it is allowed to stop suddenly
+        if next.position != 3.0 {
+            assert!((next.velocity - sp.velocity).abs() <= 2.0 * dt + 1e-9);
+        } else {
+            assert!(sp.velocity < 0.1);
+        }
+        sp = next;
+        steps += 1;
+        assert!(steps < 10_000, "profile never reached the target");
+    }
+    // 0.5s accelerating, 2.5s cruising, 0.5s decelerating
+    assert!((steps as f64 * dt - 3.5).abs() < 0.05);
+}
+
+#[test]
+fn test_velocity_control() {
+    let (mut drivetrain, plant) = test_drivetrain();
+    let mut now = Instant::now();
+    drivetrain.set_mode(ControlMode::Velocity(0.3)).unwrap();
+    run_for(&mut drivetrain, &plant, &mut now, Duration::from_secs(2));
+    let status = drivetrain.status();
+    assert!((status.velocity - 0.3).abs() < 0.02, "{:?}", status);
+    assert!(status.tracking_error.abs() < 0.02, "{:?}", status);
+}
+
+#[test]
+fn test_position_control() {
+    let (mut drivetrain, plant) = test_drivetrain();
+    let mut now = Instant::now();
+    drivetrain.set_mode(ControlMode::Position(1.25)).unwrap();
+    run_for(&mut drivetrain, &plant, &mut now, Duration::from_secs(5));
+    let status = drivetrain.status();
+    assert!(drivetrain.at_target(0.005), "{:?}", status);
+    assert!((status.position - 1.25).abs() < 0.005, "{:?}", status);
+
+    // and back again, past the start
+    drivetrain.set_mode(ControlMode::Position(-0.5)).unwrap();
+    run_for(&mut drivetrain, &plant, &mut now, Duration::from_secs(6));
+    let status = drivetrain.status();
+    assert!((status.position + 0.5).abs() < 0.005, "{:?}", status);
+}
+
+#[test]
+fn test_soft_limits() {
+    let (mut drivetrain, plant) = test_drivetrain();
+    drivetrain.limits.min_position = Some(-0.1);
+    drivetrain.limits.max_position = Some(1.0);
+    let mut now = Instant::now();
+
+    assert_eq!(
+        drivetrain.set_mode(ControlMode::Position(1.5)),
+        Err(TravelLimit::Max)
+    );
+    // drive toward the limit at full speed, it should stop on its own
+    drivetrain.set_mode(ControlMode::Velocity(0.5)).unwrap();
+    run_for(&mut drivetrain, &plant, &mut now, Duration::from_secs(4));
+    let status = drivetrain.status();
+    assert!((status.position - 1.0).abs() < 0.02, "{:?}", status);
+    assert!(status.velocity.abs() < 0.01, "{:?}", status);
+
+    assert_eq!(
+        drivetrain.take_limit_events(),
+        vec![LimitEvent {
+            limit: TravelLimit::Max,
+            source: Some(aareocams_net::LimitSource::Soft),
+        }]
+    );
+    assert_eq!(
+        drivetrain.set_mode(ControlMode::Velocity(0.1)),
+        Err(TravelLimit::Max)
+    );
+    // moving away is always allowed
+    drivetrain.set_mode(ControlMode::Velocity(-0.1)).unwrap();
+}
+
+#[test]
+fn test_open_loop_soft_limits() {
+    let (mut drivetrain, plant) = test_drivetrain();
+    drivetrain.limits.min_position = Some(-0.1);
+    drivetrain.limits.max_position = Some(0.5);
+    let mut now = Instant::now();
+
+    // about 0.4 m/s, it should slow down before the limit instead of running past it
+    drivetrain.set_mode(ControlMode::OpenLoop(50.0)).unwrap();
+    run_for(&mut drivetrain, &plant, &mut now, Duration::from_secs(3));
+    let status = drivetrain.status();
+    assert!((status.position - 0.5).abs() < 0.02, "{:?}", status);
+    assert!(status.velocity.abs() < 0.01, "{:?}", status);
+    assert_eq!(status.mode, ControlMode::Velocity(0.0));
+
+    drivetrain.set_mode(ControlMode::OpenLoop(-50.0)).unwrap();
+    run_for(&mut drivetrain, &plant, &mut now, Duration::from_secs(3));
+    let status = drivetrain.status();
+    assert!((status.position + 0.1).abs() < 0.02, "{:?}", status);
+    assert!(status.velocity.abs() < 0.01, "{:?}", status);
+}
+
+#[test]
+fn test_limit_switch() {
+    use crate::{hal::sim::SimulatedSwitch, limits::LimitSwitch};
+
+    let (mut drivetrain, plant) = test_drivetrain();
+    let switch = SimulatedSwitch::new(false);
+    let switch_handle = switch.handle();
+    drivetrain.limits.max_switch = Some(LimitSwitch::new(Box::new(switch), false));
+    let mut now = Instant::now();
+
+    drivetrain.set_mode(ControlMode::OpenLoop(50.0)).unwrap();
+    run_for(
+        &mut drivetrain,
+        &plant,
+        &mut now,
+        Duration::from_millis(500),
+    );
+    switch_handle.set(true);
+    run_for(
+        &mut drivetrain,
+        &plant,
+        &mut now,
+        Duration::from_millis(500),
+    );
+    let status = drivetrain.status();
+    assert_eq!(status.mode, ControlMode::Stopped);
+    assert_eq!(status.output, 0.0);
+    assert!(status.velocity.abs() < 0.01, "{:?}", status);
+    assert_eq!(
+        drivetrain.set_mode(ControlMode::OpenLoop(50.0)),
+        Err(TravelLimit::Max)
+    );
+    drivetrain.set_mode(ControlMode::OpenLoop(-50.0)).unwrap();
+}
@@ -0,0 +1,319 @@
+//! Quadrature encoder decoding and odometry
+//!
+//! [`QuadratureDecoder`] reads the edges of a [`QIEncoderInterface`] and turns them into a signed count,
+//! direction and velocity, and [`Odometry`] converts those counts into distance along the cable.
+
+use std::{
+    f64::consts::PI,
+    time::{Duration, Instant},
+};
+
+use aareocams_net::HomingState;
+
+use crate::hal::QIEncoderInterface;
+
+/// minimum amount of time between velocity updates, so that a couple of counts do not cause huge spikes
+const MIN_VELOCITY_WINDOW: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+}
+
+/// decodes the output of a quadrature encoder (with index), counting every edge of both channels (x4 decoding)
+#[derive(Debug)]
+pub struct QuadratureDecoder<E: QIEncoderInterface> {
+    encoder: E,
+    /// counts per revolution (4x the encoder PPR)
+    cpr: i64,
+    /// last position in the gray code sequence (0-3)
+    phase: u8,
+    index: bool,
+    count: i64,
+    direction: Option<Direction>,
+    /// number of transitions where both channels changed at once, meaning a count was missed
+    missed: usize,
+    homing: HomingState,
+    /// counts per second
+    velocity: f64,
+    last_velocity_sample: Option<(Instant, i64)>,
+}
+
+impl<E: QIEncoderInterface> QuadratureDecoder<E> {
+    pub fn new(mut encoder: E) -> Self {
+        let cpr = encoder.get_res() as i64 * 4;
+        let [a, b, index] = encoder.get_raw_vals();
+        Self {
+            encoder,
+            cpr,
+            phase: phase_of(a, b),
+            index,
+            count: 0,
+            direction: None,
+            missed: 0,
+            homing: HomingState::Unhomed,
+            velocity: 0.0,
+            last_velocity_sample: None,
+        }
+    }
+
+    /// reads the edges of the encoder since the last sample, updating the count.
+    ///
+    /// unless the encoder records its edges, this must be called at least once per edge, otherwise counts will be
+    /// missed
+    pub fn sample(&mut self) {
+        for [a, b, index] in self.encoder.take_edges() {
+            self.decode(a, b, index);
+        }
+    }
+
+    fn decode(&mut self, a: bool, b: bool, index: bool) {
+        let phase = phase_of(a, b);
+        match (phase + 4 - self.phase) % 4 {
+            0 => {}
+            1 => {
+                self.count += 1;
+                self.direction = Some(Direction::Forward);
+            }
+            3 => {
+                self.count -= 1;
+                self.direction = Some(Direction::Reverse);
+            }
+            _ => {
+                self.missed += 1;
+            }
+        }
+        self.phase = phase;
+
+        if index && !self.index {
+            match self.homing {
+                HomingState::Unhomed => {}
+                HomingState::Armed => {
+                    self.count = 0;
+                    self.homing = HomingState::Homed;
+                }
+                HomingState::Homed => {
+                    // the index only ever lines up with a whole revolution, so any difference is accumulated error
+                    self.count = (self.count as f64 / self.cpr as f64).round() as i64 * self.cpr;
+                }
+            }
+        }
+        self.index = index;
+    }
+
+    /// samples the encoder, and updates the velocity estimate as of `now`
+    pub fn update(&mut self, now: Instant) {
+        self.sample();
+        match self.last_velocity_sample {
+            Some((last_time, last_count)) => {
+                let elapsed = now.saturating_duration_since(last_time);
+                if elapsed >= MIN_VELOCITY_WINDOW {
+                    self.velocity = (self.count - last_count) as f64 / elapsed.as_secs_f64();
+                    self.last_velocity_sample = Some((now, self.count));
+                }
+            }
+            None => self.last_velocity_sample = Some((now, self.count)),
+        }
+    }
+
+    /// reset the count to zero on the next index pulse
+    pub fn arm_homing(&mut self) {
+        self.homing = HomingState::Armed;
+    }
+
+    /// sets the current count, as if the encoder was homed at some other position
+    pub fn set_count(&mut self, count: i64) {
+        self.count = count;
+        self.last_velocity_sample = None;
+    }
+
+    pub fn count(&self) -> i64 {
+        self.count
+    }
+
+    /// direction of the last count, or `None` if it has not moved yet
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+    }
+
+    /// velocity in counts per second
+    pub fn velocity(&self) -> f64 {
+        self.velocity
+    }
+
+    pub fn counts_per_rev(&self) -> i64 {
+        self.cpr
+    }
+
+    pub fn missed(&self) -> usize {
+        self.missed
+    }
+
+    pub fn homing(&self) -> HomingState {
+        self.homing
+    }
+
+    pub fn encoder(&mut self) -> &mut E {
+        &mut self.encoder
+    }
+}
+
+/// position in the gray code sequence `00 -> 10 -> 11 -> 01` (forward)
+fn phase_of(a: bool, b: bool) -> u8 {
+    match (a, b) {
+        (false, false) => 0,
+        (true, false) => 1,
+        (true, true) => 2,
+        (false, true) => 3,
+    }
+}
+
+/// converts encoder counts into distance along the cable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Odometry {
+    /// wheel diameter, in meters
+    wheel_diameter: f64,
+    counts_per_rev: i64,
+}
+
+impl Odometry {
+    pub fn new(wheel_diameter: f64, counts_per_rev: i64) -> Self {
+        Self {
+            wheel_diameter,
+            counts_per_rev,
+        }
+    }
+
+    /// meters traveled per encoder count
+    pub fn distance_per_count(&self) -> f64 {
+        PI * self.wheel_diameter / self.counts_per_rev as f64
+    }
+
+    /// converts a count into meters
+    pub fn distance(&self, counts: i64) -> f64 {
+        counts as f64 * self.distance_per_count()
+    }
+
+    /// converts meters into the nearest count
+    pub fn counts(&self, distance: f64) -> i64 {
+        (distance / self.distance_per_count()).round() as i64
+    }
+
+    /// converts counts per second into meters per second
+    pub fn velocity(&self, counts_per_sec: f64) -> f64 {
+        counts_per_sec * self.distance_per_count()
+    }
+}
+
+#[test]
+fn test_decoder_counts_and_direction() {
+    use crate::hal::sim::SimulatedEncoder;
+
+    let sim = SimulatedEncoder::new(100);
+    let handle = sim.handle();
+    let mut decoder = QuadratureDecoder::new(sim);
+    assert_eq!(decoder.counts_per_rev(), 400);
+
+    for _ in 0..10 {
+        handle.step(1);
+        decoder.sample();
+    }
+    assert_eq!(decoder.count(), 10);
+    assert_eq!(decoder.direction(), Some(Direction::Forward));
+
+    for _ in 0..25 {
+        handle.step(-1);
+        decoder.sample();
+    }
+    assert_eq!(decoder.count(), -15);
+    assert_eq!(decoder.direction(), Some(Direction::Reverse));
+    assert_eq!(decoder.missed(), 0);
+
+    // skipping two edges at once is ambiguous, and should not be counted
+    handle.set_position(handle.position() + 2);
+    decoder.sample();
+    assert_eq!(decoder.count(), -15);
+    assert_eq!(decoder.missed(), 1);
+}
+
+#[test]
+fn test_decoder_recorded_edges() {
+    use crate::hal::sim::SimulatedEncoder;
+
+    let sim = SimulatedEncoder::new(100);
+    let handle = sim.handle();
+    let mut decoder = QuadratureDecoder::new(sim);
+    decoder.arm_homing();
+
+    // the simulated encoder records every edge it passes, so they are all counted however rarely it is read
+    handle.step(450);
+    decoder.sample();
+    assert_eq!(decoder.homing(), HomingState::Homed);
+    assert_eq!(decoder.count(), 50);
+    handle.step(-120);
+    decoder.sample();
+    assert_eq!(decoder.count(), -70);
+    assert_eq!(decoder.missed(), 0);
+}
+
+#[test]
+fn test_decoder_velocity() {
+    use crate::hal::sim::SimulatedEncoder;
+
+    let sim = SimulatedEncoder::new(100);
+    let handle = sim.handle();
+    let mut decoder = QuadratureDecoder::new(sim);
+
+    let start = Instant::now();
+    decoder.update(start);
+    // 200 counts over 100ms
+    for i in 1..=200u64 {
+        handle.step(1);
+        decoder.update(start + Duration::from_micros(i * 500));
+    }
+    assert!((decoder.velocity() - 2000.0).abs() < 1.0);
+}
+
+#[test]
+fn test_decoder_homing() {
+    use crate::hal::sim::SimulatedEncoder;
+
+    let sim = SimulatedEncoder::new(100);
+    let handle = sim.handle();
+    // start partway through a revolution, so the index is somewhere ahead
+    handle.set_position(150);
+    let mut decoder = QuadratureDecoder::new(sim);
+    decoder.arm_homing();
+
+    for _ in 0..250 {
+        handle.step(1);
+        decoder.sample();
+    }
+    assert_eq!(decoder.homing(), HomingState::Homed);
+    // the index is at 400, so 150 + 250 = 400 is exactly at the index
+    assert_eq!(decoder.count(), 0);
+
+    for _ in 0..10 {
+        handle.step(1);
+        decoder.sample();
+    }
+    assert_eq!(decoder.count(), 10);
+
+    // accumulated error is removed the next time the index passes
+    decoder.set_count(13);
+    for _ in 0..390 {
+        handle.step(1);
+        decoder.sample();
+    }
+    assert_eq!(decoder.count(), 400);
+}
+
+#[test]
+fn test_odometry() {
+    let odom = Odometry::new(0.1, 400);
+    assert!((odom.distance(400) - PI * 0.1).abs() < 1e-9);
+    assert!((odom.distance(-200) + PI * 0.05).abs() < 1e-9);
+    assert_eq!(odom.counts(PI * 0.1), 400);
+    assert!((odom.velocity(400.0) - PI * 0.1).abs() < 1e-9);
+}
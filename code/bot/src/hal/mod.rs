@@ -0,0 +1,255 @@
+//! Hardware abstraction layer
+//!
+//! everything the bot touches on the robot goes through the traits here, so that it can be run against
+//! simulated hardware (see [`sim`]) on any computer. the real implementations are behind the `rppi` (GPIO, motor, encoder,
+//! power sensor) and `v4l` (cameras) features, and which one is used is selected by `hal.backend` in `bot.yml`
+
+#[cfg(feature = "rppi")]
+pub mod rppi;
+pub mod sim;
+pub mod test_pattern;
+#[cfg(feature = "v4l")]
+pub mod v4l;
+
+use std::{collections::VecDeque, sync::Arc};
+
+use aareocams_net::CameraFormat;
+use anyhow::Result;
+use image::RgbImage;
+
+use crate::config::{HalBackend, HalConf, SafetyConf};
+
+/// interface to the hardware of a quadrature encoder (with index)
+pub trait QIEncoderInterface {
+    /// get the encoder resolution in PPR
+    fn get_res(&mut self) -> usize;
+
+    // rotational progression:
+    // NOTE: i could be 1 at any point throughout, but it can only be 1 at ONE PLACE and that place stays constant
+    //
+    // a b i
+    // 0 0 1
+    // 1 0 0
+    // 1 1 0
+    // 0 1 0
+    //
+    /// reads the three channels of the encoder (a, b, index)
+    fn get_raw_vals(&self) -> [bool; 3];
+
+    /// the channels after every edge since this was last called, oldest first. by default they are only read now,
+    /// so this has to be called at least once per edge to not miss counts. encoders that record their edges as they
+    /// happen (see [`EdgeLog`]) can be read less often
+    fn take_edges(&mut self) -> Vec<[bool; 3]> {
+        vec![self.get_raw_vals()]
+    }
+}
+
+/// edges recorded before they are dropped, in case the encoder is not read for a while
+const MAX_QUEUED_EDGES: usize = 4096;
+
+/// the channels of an encoder (a, b, index) after each of its edges, recorded as they happen for
+/// [`QIEncoderInterface::take_edges`]
+#[derive(Debug)]
+pub struct EdgeLog {
+    levels: [bool; 3],
+    edges: VecDeque<[bool; 3]>,
+}
+
+impl EdgeLog {
+    pub fn new(levels: [bool; 3]) -> Self {
+        Self {
+            levels,
+            edges: VecDeque::new(),
+        }
+    }
+
+    /// records the channels changing to `levels`
+    pub fn push(&mut self, levels: [bool; 3]) {
+        if levels == self.levels {
+            return;
+        }
+        if self.edges.len() == MAX_QUEUED_EDGES {
+            self.edges.pop_front();
+        }
+        self.edges.push_back(levels);
+        self.levels = levels;
+    }
+
+    /// records one channel changing to `level`
+    pub fn set_channel(&mut self, channel: usize, level: bool) {
+        let mut levels = self.levels;
+        levels[channel] = level;
+        self.push(levels);
+    }
+
+    pub fn take(&mut self) -> Vec<[bool; 3]> {
+        self.edges.drain(..).collect()
+    }
+}
+
+/// a simple motor controller implementation.
+///
+/// minimum speed is 0, maximum speed is 100, and can be made negative to reverse the motor
+pub trait MotorController {
+    /// inverse the motor direction
+    fn inverse(&mut self);
+
+    /// sets the number of seconds it takes to go to maximmum speed (open loop ramp rate)
+    fn set_ol_ramp_rate(&mut self, rate: f32);
+
+    /// sets the motors current speed (-100 to 100) (reversed full speed to full speed)
+    fn set_speed(&mut self, speed: f32);
+}
+
+/// a digital input, such as a switch
+pub trait DigitalInput {
+    /// reads the current level of the input
+    fn is_high(&self) -> bool;
+}
+
+/// opens GPIO pins
+pub trait GpioBackend {
+    /// opens `pin` (BCM numbering) as an input, with the pull up enabled
+    fn input(&self, pin: u8) -> Result<Box<dyn DigitalInput + Send>>;
+}
+
+/// a reading from a [`PowerSensor`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerReading {
+    /// battery voltage, in volts
+    pub voltage: f64,
+    /// current drawn from the battery, in amps
+    pub current: f64,
+}
+
+/// measures the voltage and current of the battery
+pub trait PowerSensor {
+    fn read(&mut self) -> Result<PowerReading>;
+}
+
+/// a camera, which is opened on the thread it is used on
+pub trait Camera {
+    /// (width, height) of the frames
+    fn resolution(&self) -> (u32, u32);
+
+    /// frames per second the camera was opened with
+    fn frame_rate(&self) -> u32;
+
+    fn open_stream(&mut self) -> Result<()>;
+
+    /// waits for, and returns the next frame
+    fn frame(&mut self) -> Result<RgbImage>;
+
+    fn stop_stream(&mut self) -> Result<()>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CameraInfo {
+    pub index: usize,
+    pub human_name: String,
+    pub description: String,
+}
+
+/// finds and opens cameras
+pub trait CameraBackend: Send + Sync {
+    /// lists available cameras, sorted by index
+    fn list(&self) -> Result<Vec<CameraInfo>>;
+
+    /// lists the formats the camera with device ID `index` can be opened in
+    fn formats(&self, index: usize) -> Result<Vec<CameraFormat>>;
+
+    /// opens a camera in `format`, or whatever format the camera defaults to if it is `None`
+    fn open(&self, index: usize, format: Option<CameraFormat>) -> Result<Box<dyn Camera>>;
+}
+
+impl<E: QIEncoderInterface + ?Sized> QIEncoderInterface for Box<E> {
+    fn get_res(&mut self) -> usize {
+        (**self).get_res()
+    }
+
+    fn get_raw_vals(&self) -> [bool; 3] {
+        (**self).get_raw_vals()
+    }
+
+    fn take_edges(&mut self) -> Vec<[bool; 3]> {
+        (**self).take_edges()
+    }
+}
+
+impl<M: MotorController + ?Sized> MotorController for Box<M> {
+    fn inverse(&mut self) {
+        (**self).inverse()
+    }
+
+    fn set_ol_ramp_rate(&mut self, rate: f32) {
+        (**self).set_ol_ramp_rate(rate)
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        (**self).set_speed(speed)
+    }
+}
+
+/// all of the hardware the bot uses
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct Hal {
+    #[derivative(Debug = "ignore")]
+    pub motor: Box<dyn MotorController + Send>,
+    #[derivative(Debug = "ignore")]
+    pub encoder: Box<dyn QIEncoderInterface + Send>,
+    #[derivative(Debug = "ignore")]
+    pub gpio: Box<dyn GpioBackend>,
+    #[derivative(Debug = "ignore")]
+    pub cameras: Arc<dyn CameraBackend>,
+    /// `None` if the bot has no power sensor
+    #[derivative(Debug = "ignore")]
+    pub power: Option<Box<dyn PowerSensor + Send>>,
+}
+
+impl Hal {
+    pub fn from_config(cfg: &HalConf, safety: &SafetyConf) -> Result<Self> {
+        match cfg.backend {
+            HalBackend::Simulated => {
+                warn!("Using simulated hardware");
+                Ok(sim::simulated_hal(cfg, safety))
+            }
+            HalBackend::Hardware => hardware_hal(cfg),
+        }
+    }
+}
+
+#[cfg(all(feature = "rppi", feature = "v4l"))]
+fn hardware_hal(cfg: &HalConf) -> Result<Hal> {
+    let pins = cfg
+        .pins
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("hal.pins must be set to use the hardware backend"))?;
+    let gpio = rppi::RppiGpio::new()?;
+    Ok(Hal {
+        motor: Box::new(gpio.motor(pins.motor_pwm, pins.motor_dir)?),
+        encoder: Box::new(gpio.encoder(
+            pins.encoder_a,
+            pins.encoder_b,
+            pins.encoder_index,
+            cfg.encoder_ppr,
+        )?),
+        power: match &cfg.power_sensor {
+            Some(sensor) => Some(Box::new(rppi::Ina219::new(
+                sensor.bus,
+                sensor.address,
+                sensor.shunt_resistance,
+            )?)),
+            None => None,
+        },
+        gpio: Box::new(gpio),
+        cameras: Arc::new(v4l::V4lCameras),
+    })
+}
+
+#[cfg(not(all(feature = "rppi", feature = "v4l")))]
+fn hardware_hal(_cfg: &HalConf) -> Result<Hal> {
+    anyhow::bail!(
+        "this build does not support real hardware, rebuild with the `rppi` and `v4l` features or set hal.backend to simulated"
+    )
+}
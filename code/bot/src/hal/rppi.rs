@@ -0,0 +1,202 @@
+//! Raspberry pi GPIO hardware
+
+use std::{sync::Arc, time::Instant};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use rppal::{
+    gpio::{Gpio, InputPin, Level, OutputPin, Trigger},
+    i2c::I2c,
+};
+
+use super::{
+    DigitalInput, EdgeLog, GpioBackend, MotorController, PowerReading, PowerSensor,
+    QIEncoderInterface,
+};
+
+/// software PWM frequency for the motor driver, in Hz
+const PWM_FREQUENCY: f64 = 1000.0;
+
+const INA219_CONFIG: u8 = 0x00;
+const INA219_SHUNT_VOLTAGE: u8 = 0x01;
+const INA219_BUS_VOLTAGE: u8 = 0x02;
+/// 32V bus range, 320mV shunt range, 12 bit samples, measuring both voltages continuously
+const INA219_CONFIG_VALUE: u16 = 0x399F;
+/// volts per bit of the shunt voltage
+const INA219_SHUNT_LSB: f64 = 10e-6;
+/// volts per bit of the bus voltage (after shifting out the status bits)
+const INA219_BUS_LSB: f64 = 4e-3;
+
+impl DigitalInput for InputPin {
+    fn is_high(&self) -> bool {
+        InputPin::is_high(self)
+    }
+}
+
+#[derive(Debug)]
+pub struct RppiGpio {
+    gpio: Gpio,
+}
+
+impl RppiGpio {
+    pub fn new() -> Result<Self> {
+        Ok(Self { gpio: Gpio::new()? })
+    }
+
+    /// a motor driven by a PWM (speed) and direction pin, for H bridge style drivers
+    pub fn motor(&self, pwm_pin: u8, dir_pin: u8) -> Result<PwmMotor> {
+        let mut motor = PwmMotor {
+            pwm: self.gpio.get(pwm_pin)?.into_output(),
+            dir: self.gpio.get(dir_pin)?.into_output(),
+            inverted: false,
+            ramp_rate: 0.0,
+            speed: 0.0,
+            last_update: Instant::now(),
+        };
+        motor.set_speed(0.0);
+        Ok(motor)
+    }
+
+    pub fn encoder(&self, a_pin: u8, b_pin: u8, index_pin: u8, ppr: usize) -> Result<GpioEncoder> {
+        let a = self.gpio.get(a_pin)?.into_input_pullup();
+        let b = self.gpio.get(b_pin)?.into_input_pullup();
+        let index = self.gpio.get(index_pin)?.into_input_pullup();
+        let levels = [a.is_high(), b.is_high(), index.is_high()];
+        let mut encoder = GpioEncoder {
+            a,
+            b,
+            index,
+            ppr,
+            edges: Arc::new(Mutex::new(EdgeLog::new(levels))),
+        };
+        // every edge is recorded by the interrupt threads as it happens, so the drivetrain does not need to poll
+        for (channel, pin) in [&mut encoder.a, &mut encoder.b, &mut encoder.index]
+            .into_iter()
+            .enumerate()
+        {
+            let edges = encoder.edges.clone();
+            pin.set_async_interrupt(Trigger::Both, move |level| {
+                edges.lock().set_channel(channel, level == Level::High)
+            })?;
+        }
+        Ok(encoder)
+    }
+}
+
+impl GpioBackend for RppiGpio {
+    fn input(&self, pin: u8) -> Result<Box<dyn DigitalInput + Send>> {
+        Ok(Box::new(self.gpio.get(pin)?.into_input_pullup()))
+    }
+}
+
+/// a quadrature encoder read directly from GPIO pins
+#[derive(Debug)]
+pub struct GpioEncoder {
+    a: InputPin,
+    b: InputPin,
+    index: InputPin,
+    ppr: usize,
+    edges: Arc<Mutex<EdgeLog>>,
+}
+
+impl QIEncoderInterface for GpioEncoder {
+    fn get_res(&mut self) -> usize {
+        self.ppr
+    }
+
+    fn get_raw_vals(&self) -> [bool; 3] {
+        [self.a.is_high(), self.b.is_high(), self.index.is_high()]
+    }
+
+    fn take_edges(&mut self) -> Vec<[bool; 3]> {
+        self.edges.lock().take()
+    }
+}
+
+/// a DC motor, with speed set by software PWM and direction by a second pin
+#[derive(Debug)]
+pub struct PwmMotor {
+    pwm: OutputPin,
+    dir: OutputPin,
+    inverted: bool,
+    ramp_rate: f32,
+    speed: f32,
+    last_update: Instant,
+}
+
+impl MotorController for PwmMotor {
+    fn inverse(&mut self) {
+        self.inverted = !self.inverted;
+        self.set_speed(self.speed);
+    }
+
+    fn set_ol_ramp_rate(&mut self, rate: f32) {
+        self.ramp_rate = rate;
+    }
+
+    /// NOTE: ramping is only applied when this is called, so call it regularly when using a ramp rate
+    fn set_speed(&mut self, speed: f32) {
+        let now = Instant::now();
+        let speed = speed.clamp(-100.0, 100.0);
+        self.speed = if self.ramp_rate > 0.0 {
+            let max_change =
+                100.0 * now.duration_since(self.last_update).as_secs_f32() / self.ramp_rate;
+            self.speed + (speed - self.speed).clamp(-max_change, max_change)
+        } else {
+            speed
+        };
+        self.last_update = now;
+
+        if (self.speed < 0.0) != self.inverted {
+            self.dir.set_high();
+        } else {
+            self.dir.set_low();
+        }
+        if let Err(pwm_error) = self
+            .pwm
+            .set_pwm_frequency(PWM_FREQUENCY, (self.speed.abs() / 100.0) as f64)
+        {
+            error!("Failed to set motor PWM:\n{:#?}", pwm_error);
+        }
+    }
+}
+
+/// an INA219 current and voltage sensor on I2C, with the shunt on the high side of the battery
+#[derive(Debug)]
+pub struct Ina219 {
+    i2c: I2c,
+    /// ohms
+    shunt_resistance: f64,
+}
+
+impl Ina219 {
+    pub fn new(bus: u8, address: u16, shunt_resistance: f64) -> Result<Self> {
+        let mut i2c = I2c::with_bus(bus)?;
+        i2c.set_slave_address(address)?;
+        let [high, low] = INA219_CONFIG_VALUE.to_be_bytes();
+        i2c.write(&[INA219_CONFIG, high, low])?;
+        Ok(Self {
+            i2c,
+            shunt_resistance,
+        })
+    }
+
+    fn read_register(&self, register: u8) -> Result<u16> {
+        let mut value = [0; 2];
+        self.i2c.write_read(&[register], &mut value)?;
+        Ok(u16::from_be_bytes(value))
+    }
+}
+
+impl PowerSensor for Ina219 {
+    fn read(&mut self) -> Result<PowerReading> {
+        let shunt_voltage =
+            self.read_register(INA219_SHUNT_VOLTAGE)? as i16 as f64 * INA219_SHUNT_LSB;
+        let bus_voltage = (self.read_register(INA219_BUS_VOLTAGE)? >> 3) as f64 * INA219_BUS_LSB;
+        Ok(PowerReading {
+            // the bus voltage is measured after the shunt
+            voltage: bus_voltage + shunt_voltage,
+            current: shunt_voltage / self.shunt_resistance,
+        })
+    }
+}
@@ -0,0 +1,453 @@
+//! Simulated hardware, for testing without the robot
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use aareocams_net::{CameraFormat, PixelFormat, TestPattern, TestPatternKind};
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+
+use super::{
+    test_pattern::TestPatternCamera, Camera, CameraBackend, CameraInfo, DigitalInput, EdgeLog,
+    GpioBackend, Hal, MotorController, PowerReading, PowerSensor, QIEncoderInterface,
+};
+use crate::config::{HalConf, SafetyConf};
+
+/// top speed of the simulated motor, in encoder counts per second
+const SIM_MOTOR_MAX_VELOCITY: f64 = 2000.0;
+/// time constant of the simulated motor, in seconds
+const SIM_MOTOR_TIME_CONSTANT: f64 = 0.05;
+/// how much time the simulated motor advances each step
+const SIM_STEP: Duration = Duration::from_micros(250);
+const SIM_CAMERA_COUNT: usize = 2;
+/// the simulated battery is a 3 cell lithium polymer pack, and its voltage drops linearly from full to empty
+const SIM_BATTERY_FULL_VOLTAGE: f64 = 12.6;
+const SIM_BATTERY_EMPTY_VOLTAGE: f64 = 9.9;
+/// amp hours
+const SIM_BATTERY_CAPACITY: f64 = 2.2;
+/// ohms
+const SIM_BATTERY_INTERNAL_RESISTANCE: f64 = 0.1;
+/// amps drawn by everything but the motor
+const SIM_IDLE_CURRENT: f64 = 0.5;
+/// amps drawn by the motor at full speed
+const SIM_MOTOR_CURRENT: f64 = 3.0;
+/// formats the simulated cameras support, the first one is the default
+const SIM_CAMERA_FORMATS: [CameraFormat; 4] = [
+    sim_camera_format(640, 480, 30, PixelFormat::Mjpeg),
+    sim_camera_format(640, 480, 30, PixelFormat::Yuyv),
+    sim_camera_format(1280, 720, 15, PixelFormat::Mjpeg),
+    sim_camera_format(320, 240, 60, PixelFormat::Mjpeg),
+];
+
+const fn sim_camera_format(
+    width: u32,
+    height: u32,
+    fps: u32,
+    pixel_format: PixelFormat,
+) -> CameraFormat {
+    CameraFormat {
+        width,
+        height,
+        fps,
+        pixel_format,
+    }
+}
+
+/// sets up simulated hardware, and starts the motor simulation
+pub fn simulated_hal(cfg: &HalConf, safety: &SafetyConf) -> Hal {
+    let encoder = SimulatedEncoder::new(cfg.encoder_ppr);
+    let motor = SimulatedMotor::new(
+        encoder.handle(),
+        SIM_MOTOR_MAX_VELOCITY,
+        SIM_MOTOR_TIME_CONSTANT,
+    );
+    motor.handle().spawn(SIM_STEP);
+    let battery = SimulatedBattery::new(encoder.handle(), SIM_MOTOR_MAX_VELOCITY);
+    Hal {
+        motor: Box::new(motor),
+        encoder: Box::new(encoder),
+        gpio: Box::new(SimulatedGpio {
+            // limit switches are never pressed
+            idle_level: safety.limit_switch_active_low,
+        }),
+        cameras: Arc::new(SimulatedCameras),
+        power: Some(Box::new(battery)),
+    }
+}
+
+/// a simulated quadrature encoder, with the index pulse at position zero (and every revolution after that)
+#[derive(Debug)]
+pub struct SimulatedEncoder {
+    ppr: usize,
+    /// position, in counts (4 per pulse)
+    position: Arc<AtomicI64>,
+    edges: Arc<Mutex<EdgeLog>>,
+}
+
+impl SimulatedEncoder {
+    pub fn new(ppr: usize) -> Self {
+        Self {
+            ppr,
+            position: Arc::new(AtomicI64::new(0)),
+            edges: Arc::new(Mutex::new(EdgeLog::new(channels_at(0, ppr)))),
+        }
+    }
+
+    /// get a handle for moving the encoder, after it has been given to something else
+    pub fn handle(&self) -> SimulatedEncoderHandle {
+        SimulatedEncoderHandle {
+            ppr: self.ppr,
+            position: self.position.clone(),
+            edges: self.edges.clone(),
+        }
+    }
+}
+
+/// the channels (a, b, index) of an encoder with `ppr` pulses per revolution at `position`
+fn channels_at(position: i64, ppr: usize) -> [bool; 3] {
+    let (a, b) = match position.rem_euclid(4) {
+        0 => (false, false),
+        1 => (true, false),
+        2 => (true, true),
+        _ => (false, true),
+    };
+    let index = position.rem_euclid(ppr as i64 * 4) == 0;
+    [a, b, index]
+}
+
+impl QIEncoderInterface for SimulatedEncoder {
+    fn get_res(&mut self) -> usize {
+        self.ppr
+    }
+
+    fn get_raw_vals(&self) -> [bool; 3] {
+        channels_at(self.position.load(Ordering::Relaxed), self.ppr)
+    }
+
+    fn take_edges(&mut self) -> Vec<[bool; 3]> {
+        self.edges.lock().take()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulatedEncoderHandle {
+    ppr: usize,
+    position: Arc<AtomicI64>,
+    edges: Arc<Mutex<EdgeLog>>,
+}
+
+impl SimulatedEncoderHandle {
+    /// move the encoder by some number of counts, passing every edge on the way
+    pub fn step(&self, counts: i64) {
+        let mut edges = self.edges.lock();
+        let start = self.position.fetch_add(counts, Ordering::Relaxed);
+        for passed in 1..=counts.abs() {
+            edges.push(channels_at(start + passed * counts.signum(), self.ppr));
+        }
+    }
+
+    /// moves the encoder straight to `counts`, without passing the edges in between
+    pub fn set_position(&self, counts: i64) {
+        let mut edges = self.edges.lock();
+        self.position.store(counts, Ordering::Relaxed);
+        edges.push(channels_at(counts, self.ppr));
+    }
+
+    pub fn position(&self) -> i64 {
+        self.position.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct MotorState {
+    /// speed set by the controller (-100 to 100)
+    commanded: f32,
+    /// speed after ramping
+    speed: f32,
+    inverted: bool,
+    /// seconds to go from zero to full speed, or zero for no ramping
+    ramp_rate: f32,
+    /// counts per second
+    velocity: f64,
+    /// part of a count that has been moved, but not yet shown on the encoder
+    remainder: f64,
+}
+
+/// a simulated DC motor, driving a [`SimulatedEncoder`]
+///
+/// the motor is modeled as a first order system, reaching `max_velocity` (in encoder counts per second) at full speed
+#[derive(Debug)]
+pub struct SimulatedMotor {
+    state: Arc<Mutex<MotorState>>,
+    encoder: SimulatedEncoderHandle,
+    max_velocity: f64,
+    /// time constant of the motor, in seconds
+    time_constant: f64,
+}
+
+impl SimulatedMotor {
+    pub fn new(encoder: SimulatedEncoderHandle, max_velocity: f64, time_constant: f64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(MotorState {
+                commanded: 0.0,
+                speed: 0.0,
+                inverted: false,
+                ramp_rate: 0.0,
+                velocity: 0.0,
+                remainder: 0.0,
+            })),
+            encoder,
+            max_velocity,
+            time_constant,
+        }
+    }
+
+    /// get a handle for running the simulation, after the motor has been given to something else
+    pub fn handle(&self) -> SimulatedMotorHandle {
+        SimulatedMotorHandle {
+            state: self.state.clone(),
+            encoder: self.encoder.clone(),
+            max_velocity: self.max_velocity,
+            time_constant: self.time_constant,
+        }
+    }
+}
+
+impl MotorController for SimulatedMotor {
+    fn inverse(&mut self) {
+        let mut state = self.state.lock();
+        state.inverted = !state.inverted;
+    }
+
+    fn set_ol_ramp_rate(&mut self, rate: f32) {
+        self.state.lock().ramp_rate = rate;
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.state.lock().commanded = speed.clamp(-100.0, 100.0);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulatedMotorHandle {
+    state: Arc<Mutex<MotorState>>,
+    encoder: SimulatedEncoderHandle,
+    max_velocity: f64,
+    time_constant: f64,
+}
+
+impl SimulatedMotorHandle {
+    /// advance the simulation by `dt`, moving the encoder
+    ///
+    /// keep `dt` small enough that the encoder moves less than two counts, or it will be misread
+    pub fn advance(&self, dt: Duration) {
+        let dt = dt.as_secs_f64();
+        let mut state = self.state.lock();
+
+        let max_change = if state.ramp_rate > 0.0 {
+            100.0 * dt as f32 / state.ramp_rate
+        } else {
+            f32::INFINITY
+        };
+        state.speed += (state.commanded - state.speed).clamp(-max_change, max_change);
+
+        let direction = if state.inverted { -1.0 } else { 1.0 };
+        let target = direction * state.speed as f64 / 100.0 * self.max_velocity;
+        let alpha = if self.time_constant > 0.0 {
+            (dt / self.time_constant).min(1.0)
+        } else {
+            1.0
+        };
+        state.velocity += (target - state.velocity) * alpha;
+
+        state.remainder += state.velocity * dt;
+        let counts = state.remainder.trunc();
+        state.remainder -= counts;
+        self.encoder.step(counts as i64);
+    }
+
+    /// counts per second
+    pub fn velocity(&self) -> f64 {
+        self.state.lock().velocity
+    }
+
+    /// runs the simulation in the background, advancing by `step` every `step`.
+    ///
+    /// the simulation runs for as long as the motor exists
+    pub fn spawn(self, step: Duration) -> JoinHandle<()> {
+        thread::spawn(move || {
+            // the motor holds the other reference
+            while Arc::strong_count(&self.state) > 1 {
+                self.advance(step);
+                thread::sleep(step);
+            }
+        })
+    }
+}
+
+/// a simulated digital input, such as a limit switch
+#[derive(Debug)]
+pub struct SimulatedSwitch {
+    level: Arc<AtomicBool>,
+}
+
+impl SimulatedSwitch {
+    pub fn new(level: bool) -> Self {
+        Self {
+            level: Arc::new(AtomicBool::new(level)),
+        }
+    }
+
+    /// get a handle for changing the input, after it has been given to something else
+    pub fn handle(&self) -> SimulatedSwitchHandle {
+        SimulatedSwitchHandle {
+            level: self.level.clone(),
+        }
+    }
+}
+
+impl DigitalInput for SimulatedSwitch {
+    fn is_high(&self) -> bool {
+        self.level.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulatedSwitchHandle {
+    level: Arc<AtomicBool>,
+}
+
+impl SimulatedSwitchHandle {
+    pub fn set(&self, level: bool) {
+        self.level.store(level, Ordering::Relaxed);
+    }
+}
+
+/// GPIO where every input is a [`SimulatedSwitch`]
+#[derive(Debug)]
+pub struct SimulatedGpio {
+    /// level of newly opened inputs
+    idle_level: bool,
+}
+
+impl GpioBackend for SimulatedGpio {
+    fn input(&self, _pin: u8) -> Result<Box<dyn DigitalInput + Send>> {
+        Ok(Box::new(SimulatedSwitch::new(self.idle_level)))
+    }
+}
+
+/// a simulated battery, which drains faster while the motor moves
+#[derive(Debug)]
+pub struct SimulatedBattery {
+    /// amp hours left
+    charge: Arc<Mutex<f64>>,
+    /// to tell how fast the motor is going
+    encoder: SimulatedEncoderHandle,
+    /// top speed of the motor, in encoder counts per second
+    max_velocity: f64,
+    /// when the battery was last read, and where the encoder was then
+    last_read: Option<(Instant, i64)>,
+}
+
+impl SimulatedBattery {
+    /// a full battery
+    pub fn new(encoder: SimulatedEncoderHandle, max_velocity: f64) -> Self {
+        Self {
+            charge: Arc::new(Mutex::new(SIM_BATTERY_CAPACITY)),
+            encoder,
+            max_velocity,
+            last_read: None,
+        }
+    }
+
+    /// get a handle for changing the charge, after the battery has been given to something else
+    pub fn handle(&self) -> SimulatedBatteryHandle {
+        SimulatedBatteryHandle {
+            charge: self.charge.clone(),
+        }
+    }
+}
+
+impl PowerSensor for SimulatedBattery {
+    fn read(&mut self) -> Result<PowerReading> {
+        let now = Instant::now();
+        let position = self.encoder.position();
+        // the motor is only moving between reads if the encoder did
+        let (dt, speed) = match self.last_read {
+            Some((last_time, last_position)) => {
+                let dt = now.duration_since(last_time).as_secs_f64();
+                let velocity = (position - last_position).abs() as f64 / dt.max(f64::EPSILON);
+                (dt, (velocity / self.max_velocity).min(1.0))
+            }
+            None => (0.0, 0.0),
+        };
+        self.last_read = Some((now, position));
+
+        let current = SIM_IDLE_CURRENT + SIM_MOTOR_CURRENT * speed;
+        let mut charge = self.charge.lock();
+        *charge = (*charge - current * dt / 3600.0).max(0.0);
+        let open_circuit_voltage = SIM_BATTERY_EMPTY_VOLTAGE
+            + (SIM_BATTERY_FULL_VOLTAGE - SIM_BATTERY_EMPTY_VOLTAGE) * *charge
+                / SIM_BATTERY_CAPACITY;
+        Ok(PowerReading {
+            voltage: open_circuit_voltage - current * SIM_BATTERY_INTERNAL_RESISTANCE,
+            current,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulatedBatteryHandle {
+    charge: Arc<Mutex<f64>>,
+}
+
+impl SimulatedBatteryHandle {
+    /// sets the state of charge, from 0 (empty) to 1 (full)
+    pub fn set_charge(&self, charge: f64) {
+        *self.charge.lock() = charge.clamp(0.0, 1.0) * SIM_BATTERY_CAPACITY;
+    }
+}
+
+#[derive(Debug)]
+pub struct SimulatedCameras;
+
+impl CameraBackend for SimulatedCameras {
+    fn list(&self) -> Result<Vec<CameraInfo>> {
+        Ok((0..SIM_CAMERA_COUNT)
+            .map(|index| CameraInfo {
+                index,
+                human_name: format!("Simulated camera {}", index),
+                description: "moving gradient".to_string(),
+            })
+            .collect())
+    }
+
+    fn formats(&self, index: usize) -> Result<Vec<CameraFormat>> {
+        if index >= SIM_CAMERA_COUNT {
+            return Err(anyhow!("no simulated camera {}", index));
+        }
+        Ok(SIM_CAMERA_FORMATS.to_vec())
+    }
+
+    fn open(&self, index: usize, format: Option<CameraFormat>) -> Result<Box<dyn Camera>> {
+        if index >= SIM_CAMERA_COUNT {
+            return Err(anyhow!("no simulated camera {}", index));
+        }
+        let format = format.unwrap_or(SIM_CAMERA_FORMATS[0]);
+        if !SIM_CAMERA_FORMATS.contains(&format) {
+            return Err(anyhow!("simulated cameras do not support {}", format));
+        }
+        Ok(Box::new(TestPatternCamera::new(TestPattern {
+            kind: TestPatternKind::MovingGradient,
+            width: format.width,
+            height: format.height,
+            fps: format.fps,
+        })?))
+    }
+}
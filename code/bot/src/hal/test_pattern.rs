@@ -0,0 +1,250 @@
+//! Synthetic camera frames, for testing the video pipeline without a camera
+//!
+//! frames have a frame counter and the time they were made (UTC, `hh:mm:ss.mmm`) drawn in the top left corner,
+//! so that dropped frames and latency can be seen on the dashboard.
+
+use std::{
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use aareocams_net::{TestPattern, TestPatternKind};
+use anyhow::{anyhow, Result};
+use image::{Rgb, RgbImage};
+
+use super::Camera;
+
+const MAX_RESOLUTION: u32 = 4096;
+const MAX_FPS: u32 = 240;
+
+/// 75% color bars, left to right
+const COLOR_BARS: [Rgb<u8>; 7] = [
+    Rgb([191, 191, 191]),
+    Rgb([191, 191, 0]),
+    Rgb([0, 191, 191]),
+    Rgb([0, 191, 0]),
+    Rgb([191, 0, 191]),
+    Rgb([191, 0, 0]),
+    Rgb([0, 0, 191]),
+];
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+/// a camera that generates its frames, at the frame rate of the pattern
+#[derive(Debug)]
+pub struct TestPatternCamera {
+    pattern: TestPattern,
+    frame_period: Duration,
+    frame: u64,
+    /// when the next frame is due, if the stream is open
+    next_frame: Option<Instant>,
+}
+
+impl TestPatternCamera {
+    pub fn new(pattern: TestPattern) -> Result<Self> {
+        if !(1..=MAX_RESOLUTION).contains(&pattern.width)
+            || !(1..=MAX_RESOLUTION).contains(&pattern.height)
+        {
+            return Err(anyhow!(
+                "test pattern resolution must be between 1x1 and {0}x{0}, not {1}x{2}",
+                MAX_RESOLUTION,
+                pattern.width,
+                pattern.height
+            ));
+        }
+        if !(1..=MAX_FPS).contains(&pattern.fps) {
+            return Err(anyhow!(
+                "test pattern frame rate must be between 1 and {}, not {}",
+                MAX_FPS,
+                pattern.fps
+            ));
+        }
+        Ok(Self {
+            frame_period: Duration::from_secs(1) / pattern.fps,
+            pattern,
+            frame: 0,
+            next_frame: None,
+        })
+    }
+}
+
+impl Camera for TestPatternCamera {
+    fn resolution(&self) -> (u32, u32) {
+        (self.pattern.width, self.pattern.height)
+    }
+
+    fn frame_rate(&self) -> u32 {
+        self.pattern.fps
+    }
+
+    fn open_stream(&mut self) -> Result<()> {
+        self.next_frame = Some(Instant::now());
+        Ok(())
+    }
+
+    fn frame(&mut self) -> Result<RgbImage> {
+        let next_frame = self
+            .next_frame
+            .ok_or_else(|| anyhow!("stream is not open"))?;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        }
+        self.next_frame = Some(next_frame + self.frame_period);
+        self.frame += 1;
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(render(&self.pattern, self.frame, time))
+    }
+
+    fn stop_stream(&mut self) -> Result<()> {
+        self.next_frame = None;
+        Ok(())
+    }
+}
+
+/// draws frame number `frame` of `pattern`, made at `time` (since the unix epoch)
+pub fn render(pattern: &TestPattern, frame: u64, time: Duration) -> RgbImage {
+    let (width, height) = (pattern.width, pattern.height);
+    let mut image = match pattern.kind {
+        TestPatternKind::ColorBars => RgbImage::from_fn(width, height, |x, _y| {
+            COLOR_BARS[(x as usize * COLOR_BARS.len()) / width as usize]
+        }),
+        TestPatternKind::MovingGradient => {
+            let offset = (frame.wrapping_mul(4) % width as u64) as u32;
+            RgbImage::from_fn(width, height, |x, y| {
+                Rgb([
+                    ((x + offset) % width * 255 / width) as u8,
+                    (y * 255 / height) as u8,
+                    (offset % 256) as u8,
+                ])
+            })
+        }
+    };
+
+    let secs = time.as_secs() % (24 * 60 * 60);
+    let timestamp = format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        time.subsec_millis()
+    );
+    // about 1/100th of the height per line of text
+    let scale = (height / (GLYPH_HEIGHT * 20)).max(1);
+    let line_height = (GLYPH_HEIGHT + 2) * scale;
+    draw_text(&mut image, &format!("{:06}", frame), scale, scale, scale);
+    draw_text(&mut image, &timestamp, scale, scale + line_height, scale);
+    image
+}
+
+/// draws white text on a black background, with the top left corner at (x, y) and each pixel of the font
+/// `scale` pixels across. anything that does not fit in the image is cut off
+fn draw_text(image: &mut RgbImage, text: &str, x: u32, y: u32, scale: u32) {
+    let advance = (GLYPH_WIDTH + 1) * scale;
+    let text_width = text.chars().count() as u32 * advance + scale;
+    let text_height = (GLYPH_HEIGHT + 2) * scale;
+    for py in y.saturating_sub(scale)..(y + text_height - scale).min(image.height()) {
+        for px in x.saturating_sub(scale)..(x + text_width - scale).min(image.width()) {
+            image.put_pixel(px, py, Rgb([0, 0, 0]));
+        }
+    }
+
+    for (i, c) in text.chars().enumerate() {
+        let glyph_x = x + i as u32 * advance;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - col)) == 0 {
+                    continue;
+                }
+                let (left, top) = (glyph_x + col * scale, y + row as u32 * scale);
+                for py in top..(top + scale).min(image.height()) {
+                    for px in left..(left + scale).min(image.width()) {
+                        image.put_pixel(px, py, Rgb([255, 255, 255]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 3x5 bitmap of a character, one row per byte (msb on the left). unknown characters are blank
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        _ => [0; GLYPH_HEIGHT as usize],
+    }
+}
+
+#[test]
+fn test_color_bars() {
+    let pattern = TestPattern {
+        kind: TestPatternKind::ColorBars,
+        width: 700,
+        height: 100,
+        fps: 30,
+    };
+    let image = render(&pattern, 1, Duration::ZERO);
+    assert_eq!(image.dimensions(), (700, 100));
+    // the bottom row is clear of the overlay
+    for (i, color) in COLOR_BARS.iter().enumerate() {
+        assert_eq!(image.get_pixel(i as u32 * 100 + 50, 99), color);
+    }
+}
+
+#[test]
+fn test_overlay() {
+    let pattern = TestPattern {
+        kind: TestPatternKind::MovingGradient,
+        width: 320,
+        height: 240,
+        fps: 30,
+    };
+    let time = Duration::from_secs(1_000_000) + Duration::from_millis(123);
+    let first = render(&pattern, 10, time);
+    let second = render(&pattern, 10, time);
+    assert_eq!(first, second);
+
+    // the overlay is drawn over a black box
+    assert_eq!(first.get_pixel(0, 0), &Rgb([0, 0, 0]));
+    let counter = |image: &RgbImage| {
+        image
+            .enumerate_pixels()
+            .filter(|(x, y, _)| *x < 50 && *y < 14)
+            .map(|(_, _, p)| *p)
+            .collect::<Vec<_>>()
+    };
+    assert_ne!(counter(&first), counter(&render(&pattern, 11, time)));
+    // a different time changes the timestamp, but not the counter
+    let later = render(&pattern, 10, time + Duration::from_millis(1));
+    assert_eq!(counter(&first), counter(&later));
+    assert_ne!(first, later);
+}
+
+#[test]
+fn test_invalid_pattern() {
+    let pattern = |width, height, fps| TestPattern {
+        kind: TestPatternKind::ColorBars,
+        width,
+        height,
+        fps,
+    };
+    assert!(TestPatternCamera::new(pattern(640, 480, 30)).is_ok());
+    assert!(TestPatternCamera::new(pattern(0, 480, 30)).is_err());
+    assert!(TestPatternCamera::new(pattern(640, 480, 0)).is_err());
+    assert!(TestPatternCamera::new(pattern(640, MAX_RESOLUTION + 1, 30)).is_err());
+}
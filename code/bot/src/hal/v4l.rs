@@ -0,0 +1,92 @@
+//! V4L cameras, through nokhwa
+
+use aareocams_net::{CameraFormat, PixelFormat};
+use anyhow::Result;
+use image::RgbImage;
+use nokhwa::FrameFormat;
+
+use super::{Camera, CameraBackend, CameraInfo};
+
+#[derive(Debug)]
+pub struct V4lCameras;
+
+impl CameraBackend for V4lCameras {
+    fn list(&self) -> Result<Vec<CameraInfo>> {
+        let mut cameras = nokhwa::query()?
+            .into_iter()
+            .map(|info| CameraInfo {
+                index: info.index(),
+                human_name: info.human_name().to_string(),
+                description: info.description().to_string(),
+            })
+            .collect::<Vec<_>>();
+        cameras.sort_by(|a, b| a.index.cmp(&b.index));
+        Ok(cameras)
+    }
+
+    fn formats(&self, index: usize) -> Result<Vec<CameraFormat>> {
+        let mut camera = nokhwa::Camera::new(index, None)?;
+        let mut formats = vec![];
+        for frame_format in camera.compatible_fourcc()? {
+            let pixel_format = match frame_format {
+                FrameFormat::MJPEG => PixelFormat::Mjpeg,
+                FrameFormat::YUYV => PixelFormat::Yuyv,
+            };
+            for (resolution, frame_rates) in camera.compatible_list_by_resolution(frame_format)? {
+                formats.extend(frame_rates.into_iter().map(|fps| CameraFormat {
+                    width: resolution.width(),
+                    height: resolution.height(),
+                    fps,
+                    pixel_format,
+                }));
+            }
+        }
+        // largest and fastest first
+        formats.sort_by(|a, b| {
+            (b.width * b.height, b.fps, b.pixel_format as u8).cmp(&(
+                a.width * a.height,
+                a.fps,
+                a.pixel_format as u8,
+            ))
+        });
+        Ok(formats)
+    }
+
+    fn open(&self, index: usize, format: Option<CameraFormat>) -> Result<Box<dyn Camera>> {
+        let format = format.map(|format| {
+            nokhwa::CameraFormat::new_from(
+                format.width,
+                format.height,
+                match format.pixel_format {
+                    PixelFormat::Mjpeg => FrameFormat::MJPEG,
+                    PixelFormat::Yuyv => FrameFormat::YUYV,
+                },
+                format.fps,
+            )
+        });
+        Ok(Box::new(nokhwa::Camera::new(index, format)?))
+    }
+}
+
+impl Camera for nokhwa::Camera {
+    fn resolution(&self) -> (u32, u32) {
+        let resolution = nokhwa::Camera::resolution(self);
+        (resolution.width(), resolution.height())
+    }
+
+    fn frame_rate(&self) -> u32 {
+        nokhwa::Camera::frame_rate(self)
+    }
+
+    fn open_stream(&mut self) -> Result<()> {
+        Ok(nokhwa::Camera::open_stream(self)?)
+    }
+
+    fn frame(&mut self) -> Result<RgbImage> {
+        Ok(nokhwa::Camera::frame(self)?)
+    }
+
+    fn stop_stream(&mut self) -> Result<()> {
+        Ok(nokhwa::Camera::stop_stream(self)?)
+    }
+}
@@ -0,0 +1,124 @@
+//! A log4rs appender that writes to the systemd journal
+//!
+//! it uses journald's native protocol, so records keep their level and where they were logged from, without
+//! depending on libsystemd. configured as an appender with `kind: journald`
+
+use std::os::unix::net::UnixDatagram;
+
+use anyhow::Result;
+use log::{Level, Record};
+use log4rs::{
+    append::Append,
+    config::{Deserialize, Deserializers},
+};
+
+/// where journald listens for entries
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+/// what the records are logged as, unless configured otherwise
+const DEFAULT_IDENTIFIER: &str = "aareocams-bot";
+
+#[derive(Debug)]
+pub struct JournaldAppender {
+    socket: UnixDatagram,
+    identifier: String,
+}
+
+impl JournaldAppender {
+    pub fn new(identifier: String) -> Result<Self> {
+        Ok(Self {
+            socket: UnixDatagram::unbound()?,
+            identifier,
+        })
+    }
+}
+
+impl Append for JournaldAppender {
+    fn append(&self, record: &Record) -> Result<()> {
+        let message = record.args().to_string();
+        let line = record.line().map(|line| line.to_string());
+        let mut fields = vec![
+            ("MESSAGE", message.as_str()),
+            ("PRIORITY", priority(record.level())),
+            ("SYSLOG_IDENTIFIER", self.identifier.as_str()),
+            ("TARGET", record.target()),
+        ];
+        if let Some(file) = record.file() {
+            fields.push(("CODE_FILE", file));
+        }
+        if let Some(line) = &line {
+            fields.push(("CODE_LINE", line));
+        }
+        self.socket
+            .send_to(&encode_entry(&fields), JOURNALD_SOCKET)?;
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JournaldAppenderConfig {
+    /// `SYSLOG_IDENTIFIER` of the records, what `journalctl -t` filters by
+    identifier: Option<String>,
+}
+
+/// lets log4rs configs use the `journald` appender kind
+pub struct JournaldAppenderDeserializer;
+
+impl Deserialize for JournaldAppenderDeserializer {
+    type Trait = dyn Append;
+    type Config = JournaldAppenderConfig;
+
+    fn deserialize(
+        &self,
+        config: JournaldAppenderConfig,
+        _: &Deserializers,
+    ) -> Result<Box<dyn Append>> {
+        Ok(Box::new(JournaldAppender::new(
+            config
+                .identifier
+                .unwrap_or_else(|| DEFAULT_IDENTIFIER.to_string()),
+        )?))
+    }
+}
+
+/// syslog priority of a level
+fn priority(level: Level) -> &'static str {
+    match level {
+        Level::Error => "3",
+        Level::Warn => "4",
+        Level::Info => "6",
+        Level::Debug | Level::Trace => "7",
+    }
+}
+
+/// an entry in journald's native format. values with newlines in them are sent with their length first,
+/// since a newline otherwise ends the field
+fn encode_entry(fields: &[(&str, &str)]) -> Vec<u8> {
+    let mut entry = vec![];
+    for (name, value) in fields {
+        entry.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            entry.push(b'\n');
+            entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        } else {
+            entry.push(b'=');
+        }
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(b'\n');
+    }
+    entry
+}
+
+#[test]
+fn test_encode_entry() {
+    assert_eq!(
+        encode_entry(&[("MESSAGE", "hello"), ("PRIORITY", "6")]),
+        b"MESSAGE=hello\nPRIORITY=6\n"
+    );
+    assert_eq!(
+        encode_entry(&[("MESSAGE", "two\nlines")]),
+        b"MESSAGE\n\x09\0\0\0\0\0\0\0two\nlines\n"
+    );
+}
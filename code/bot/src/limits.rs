@@ -0,0 +1,153 @@
+//! Travel limits, to keep the bot from driving off the end of the cable
+//!
+//! there are two kinds of limit: soft limits (positions along the cable, from config) which the drivetrain
+//! starts slowing down for early based on its speed, and limit switches, which stop it as soon as they are hit.
+
+use aareocams_net::{LimitSource, TravelLimit};
+use anyhow::Result;
+
+use crate::{
+    config::SafetyConf,
+    hal::{DigitalInput, GpioBackend},
+};
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct LimitSwitch {
+    #[derivative(Debug = "ignore")]
+    input: Box<dyn DigitalInput + Send>,
+    /// if the input reads low when the switch is pressed
+    active_low: bool,
+}
+
+impl LimitSwitch {
+    pub fn new(input: Box<dyn DigitalInput + Send>, active_low: bool) -> Self {
+        Self { input, active_low }
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.input.is_high() != self.active_low
+    }
+}
+
+/// a change in which travel limits have been reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitEvent {
+    pub limit: TravelLimit,
+    /// the limit that is now reached, or `None` if it was cleared
+    pub source: Option<LimitSource>,
+}
+
+#[derive(Debug, Default)]
+pub struct TravelLimits {
+    /// meters along the cable
+    pub min_position: Option<f64>,
+    /// meters along the cable
+    pub max_position: Option<f64>,
+    pub min_switch: Option<LimitSwitch>,
+    pub max_switch: Option<LimitSwitch>,
+    /// limits reached as of the last call to `update` (min, max)
+    reached: [Option<LimitSource>; 2],
+}
+
+impl TravelLimits {
+    /// sets up the travel limits from config, opening the GPIO pins of any limit switches
+    pub fn from_config(cfg: &SafetyConf, gpio: &dyn GpioBackend) -> Result<Self> {
+        let open_switch = |pin: Option<u8>| -> Result<Option<LimitSwitch>> {
+            match pin {
+                Some(pin) => {
+                    info!("Using limit switch on GPIO pin {}", pin);
+                    Ok(Some(LimitSwitch::new(
+                        gpio.input(pin)?,
+                        cfg.limit_switch_active_low,
+                    )))
+                }
+                None => Ok(None),
+            }
+        };
+        Ok(Self {
+            min_position: cfg.min_position,
+            max_position: cfg.max_position,
+            min_switch: open_switch(cfg.limit_switch_min_pin)?,
+            max_switch: open_switch(cfg.limit_switch_max_pin)?,
+            reached: [None, None],
+        })
+    }
+
+    /// checks if `limit` is reached at `position`. limit switches take priority over soft limits
+    pub fn check(&self, limit: TravelLimit, position: f64) -> Option<LimitSource> {
+        let (switch, soft_reached) = match limit {
+            TravelLimit::Min => (
+                &self.min_switch,
+                self.min_position.map_or(false, |min| position <= min),
+            ),
+            TravelLimit::Max => (
+                &self.max_switch,
+                self.max_position.map_or(false, |max| position >= max),
+            ),
+        };
+        if switch.as_ref().map_or(false, LimitSwitch::is_tripped) {
+            Some(LimitSource::Switch)
+        } else if soft_reached {
+            Some(LimitSource::Soft)
+        } else {
+            None
+        }
+    }
+
+    /// checks all limits, returning the ones that changed since the last update
+    pub fn update(&mut self, position: f64) -> Vec<LimitEvent> {
+        let mut events = vec![];
+        for (i, limit) in [TravelLimit::Min, TravelLimit::Max].into_iter().enumerate() {
+            let source = self.check(limit, position);
+            if source != self.reached[i] {
+                self.reached[i] = source;
+                events.push(LimitEvent { limit, source });
+            }
+        }
+        events
+    }
+
+    /// the limit that would be reached by moving in the direction of `velocity`, if it is already reached
+    /// (as of the last update)
+    pub fn blocking(&self, velocity: f64) -> Option<TravelLimit> {
+        if velocity > 0.0 && self.reached[1].is_some() {
+            Some(TravelLimit::Max)
+        } else if velocity < 0.0 && self.reached[0].is_some() {
+            Some(TravelLimit::Min)
+        } else {
+            None
+        }
+    }
+
+    /// the range of velocities (min, max) that can still stop before the soft limits when decelerating at
+    /// `max_acceleration`, and that do not move toward a limit that is already reached
+    pub fn velocity_range(&self, position: f64, max_acceleration: f64) -> (f64, f64) {
+        let min = if self.reached[0].is_some() {
+            0.0
+        } else {
+            self.min_position.map_or(f64::NEG_INFINITY, |min| {
+                -(2.0 * max_acceleration * (position - min).max(0.0)).sqrt()
+            })
+        };
+        let max = if self.reached[1].is_some() {
+            0.0
+        } else {
+            self.max_position.map_or(f64::INFINITY, |max| {
+                (2.0 * max_acceleration * (max - position).max(0.0)).sqrt()
+            })
+        };
+        (min, max)
+    }
+
+    /// the soft limit that `position` is outside of, if any
+    pub fn outside(&self, position: f64) -> Option<TravelLimit> {
+        if self.min_position.map_or(false, |min| position < min) {
+            Some(TravelLimit::Min)
+        } else if self.max_position.map_or(false, |max| position > max) {
+            Some(TravelLimit::Max)
+        } else {
+            None
+        }
+    }
+}
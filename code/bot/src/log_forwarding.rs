@@ -0,0 +1,246 @@
+//! Forwarding log records to the dashboard
+//!
+//! the bot logs as configured in its log4rs config, and every record at the level the dashboard asked for is also
+//! queued to be sent to it. the queue holds the records logged before the dashboard connects as well, so it gets
+//! those once it does
+
+use std::{
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use aareocams_net::{LogLevel, LogRecord, Message};
+use anyhow::{bail, Context, Result};
+use flume::{Receiver, Sender, TrySendError};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use log4rs::config::{Config, Deserializers, RawConfig};
+
+use crate::{config::LoggingConf, journald::JournaldAppenderDeserializer};
+
+/// records queued for the dashboard before the oldest ones are dropped
+const RECORD_BUFFER: usize = 1000;
+/// records from the libraries the bot uses are only forwarded at this level or more severe, since some are chatty
+const LIBRARY_LEVEL: LevelFilter = LevelFilter::Warn;
+/// records with targets starting with this are from the bot itself
+const OWN_TARGET_PREFIX: &str = "aareocams";
+
+struct ForwardingLogger {
+    local: Box<dyn Log>,
+    /// the most verbose level forwarded, as a [`LevelFilter`] cast to `usize`
+    remote_level: Arc<AtomicUsize>,
+    records: Sender<Message>,
+    /// to drop the oldest record once the queue is full
+    overflow: Receiver<Message>,
+}
+
+impl ForwardingLogger {
+    fn forwards(&self, metadata: &Metadata) -> bool {
+        let mut level = self.remote_level.load(Ordering::Relaxed);
+        if !metadata.target().starts_with(OWN_TARGET_PREFIX) {
+            level = level.min(LIBRARY_LEVEL as usize);
+        }
+        metadata.level() as usize <= level
+    }
+}
+
+impl Log for ForwardingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.local.enabled(metadata) || self.forwards(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.local.enabled(record.metadata()) {
+            self.local.log(record);
+        }
+        if !self.forwards(record.metadata()) {
+            return;
+        }
+        let mut message = Message::Log(LogRecord {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            level: to_net_level(record.level()),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        });
+        // this can not log anything itself, or it would be forwarding its own records forever
+        while let Err(TrySendError::Full(unsent)) = self.records.try_send(message) {
+            let _ = self.overflow.try_recv();
+            message = unsent;
+        }
+    }
+
+    fn flush(&self) {
+        self.local.flush();
+    }
+}
+
+/// controls what is forwarded, after logging is set up with [`init`]
+#[derive(Debug, Clone)]
+pub struct LogForwarding {
+    local_level: LevelFilter,
+    remote_level: Arc<AtomicUsize>,
+    records: Receiver<Message>,
+}
+
+impl LogForwarding {
+    /// the records to send to the dashboard, as [`Message::Log`]
+    pub fn receiver(&self) -> Receiver<Message> {
+        self.records.clone()
+    }
+
+    /// forwards records at `level` or more severe from now on
+    pub fn set_level(&self, level: LogLevel) {
+        let level = to_level_filter(level);
+        self.remote_level.store(level as usize, Ordering::Relaxed);
+        log::set_max_level(self.local_level.max(level));
+    }
+}
+
+/// sets up logging as configured in the log4rs config at `cfg.config`, and forwarding to the dashboard at
+/// `cfg.remote_level`. the log4rs config is only read once, `refresh_rate` is ignored
+pub fn init(cfg: &LoggingConf) -> Result<LogForwarding> {
+    let config_path = &cfg.config;
+    let config = log4rs::config::load_config_file(config_path, deserializers())
+        .with_context(|| format!("Failed to load the logging config {:?}", config_path))?;
+    let local = log4rs::Logger::new(config);
+    let local_level = local.max_log_level();
+    let (records_send, records) = flume::bounded(RECORD_BUFFER);
+    let forwarding = LogForwarding {
+        local_level,
+        remote_level: Arc::new(AtomicUsize::new(0)),
+        records,
+    };
+    log::set_boxed_logger(Box::new(ForwardingLogger {
+        local: Box::new(local),
+        remote_level: forwarding.remote_level.clone(),
+        records: records_send,
+        overflow: forwarding.records.clone(),
+    }))?;
+    forwarding.set_level(cfg.remote_level);
+    Ok(forwarding)
+}
+
+/// checks that the appenders and loggers of the log4rs config at `config_path` can all be set up, without setting up
+/// logging. the files the appenders log to are created
+pub fn check_config(config_path: &Path) -> Result<()> {
+    let yaml = fs::read_to_string(config_path)
+        .with_context(|| format!("Failed to read the logging config {:?}", config_path))?;
+    let raw = serde_yaml::from_str::<RawConfig>(&yaml)
+        .with_context(|| format!("Invalid logging config {:?}", config_path))?;
+    // `init` leaves out the appenders that fail to be set up, printing why, so they are an error here instead
+    let (appenders, mut errors) = raw.appenders_lossy(&deserializers());
+    if !errors.is_empty() {
+        errors.handle();
+        bail!("Invalid appenders in the logging config {:?}", config_path);
+    }
+    if let Err(errors) = Config::builder()
+        .appenders(appenders)
+        .loggers(raw.loggers())
+        .build(raw.root())
+    {
+        let errors = errors
+            .errors()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        bail!(
+            "Invalid logging config {:?}: {}",
+            config_path,
+            errors.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// the kinds of appenders the log4rs config can use
+fn deserializers() -> Deserializers {
+    let mut deserializers = Deserializers::default();
+    deserializers.insert("journald", JournaldAppenderDeserializer);
+    deserializers
+}
+
+fn to_net_level(level: Level) -> LogLevel {
+    match level {
+        Level::Error => LogLevel::Error,
+        Level::Warn => LogLevel::Warn,
+        Level::Info => LogLevel::Info,
+        Level::Debug => LogLevel::Debug,
+        Level::Trace => LogLevel::Trace,
+    }
+}
+
+fn to_level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Error => LevelFilter::Error,
+        LogLevel::Warn => LevelFilter::Warn,
+        LogLevel::Info => LevelFilter::Info,
+        LogLevel::Debug => LevelFilter::Debug,
+        LogLevel::Trace => LevelFilter::Trace,
+    }
+}
+
+#[test]
+fn test_forwarding() {
+    struct Discard;
+    impl Log for Discard {
+        fn enabled(&self, _: &Metadata) -> bool {
+            false
+        }
+        fn log(&self, _: &Record) {}
+        fn flush(&self) {}
+    }
+
+    let (records_send, records) = flume::bounded(2);
+    let logger = ForwardingLogger {
+        local: Box::new(Discard),
+        remote_level: Arc::new(AtomicUsize::new(LevelFilter::Info as usize)),
+        records: records_send,
+        overflow: records.clone(),
+    };
+    let log = |level, target, message| {
+        logger.log(
+            &Record::builder()
+                .level(level)
+                .target(target)
+                .args(format_args!("{}", message))
+                .build(),
+        )
+    };
+    let forwarded = || {
+        records
+            .try_iter()
+            .map(|message| match message {
+                Message::Log(record) => (record.level, record.message),
+                other => panic!("unexpected message {:?}", other),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    log(Level::Error, "aareocams_bot::hal", "dropped");
+    log(Level::Info, "aareocams_bot::camera_server", "opened");
+    log(Level::Debug, "aareocams_bot::camera_server", "too verbose");
+    log(Level::Info, "nokhwa", "chatty library");
+    log(Level::Warn, "nokhwa", "library warning");
+    // the queue only holds two, so the oldest was dropped
+    assert_eq!(
+        forwarded(),
+        [
+            (LogLevel::Info, "opened".to_string()),
+            (LogLevel::Warn, "library warning".to_string())
+        ]
+    );
+
+    logger
+        .remote_level
+        .store(LevelFilter::Trace as usize, Ordering::Relaxed);
+    log(Level::Debug, "aareocams_bot::hal", "verbose");
+    log(Level::Info, "nokhwa", "still chatty");
+    assert_eq!(forwarded(), [(LogLevel::Debug, "verbose".to_string())]);
+}
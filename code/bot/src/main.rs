@@ -1,5 +1,6 @@
 #![feature(drain_filter)]
 
+extern crate aareocams_config;
 extern crate aareocams_core;
 extern crate aareocams_net;
 extern crate aareocams_scomm;
@@ -11,73 +12,119 @@ extern crate bincode;
 extern crate dabus;
 extern crate flume;
 extern crate image;
+extern crate log4rs;
 extern crate lvenc;
+#[cfg(feature = "v4l")]
 extern crate nokhwa;
 extern crate parking_lot;
-extern crate pretty_env_logger;
 extern crate serde;
+extern crate serde_yaml;
 extern crate tokio;
 extern crate uuid;
-extern crate yaml_rust;
 // other raspberry pi GPIO things
+#[cfg(feature = "rppi")]
 extern crate rppal;
 #[macro_use]
 extern crate derivative;
 #[macro_use]
 extern crate log;
 
+pub mod adaptive;
+pub mod battery;
 pub mod camera_server;
 mod config;
+pub mod control;
+pub mod encoder;
+pub mod hal;
+pub mod journald;
+pub mod limits;
+pub mod log_forwarding;
+pub mod recorder;
+pub mod recovery;
+pub mod snapshot;
+pub mod stream_encoder;
+pub mod subscribers;
+pub mod system_stats;
 mod systems;
 
-use aareocams_net::Message;
-use aareocams_scomm::Stream;
+use aareocams_net::{DriveAction, Message, TelemetryAction, VideoStreamInfo};
+use aareocams_scomm::{connection::StreamUpdateErr, Stream};
 // use adafruit_motorkit::dc::DcMotor;
 use anyhow::Result;
 use dabus::DABus;
-use nokhwa::CameraInfo;
+use std::{env, future, sync::atomic::Ordering, time::Duration};
 use tokio::{net::TcpListener, select};
 
-use systems::camera;//, drivetrain};
+use hal::{CameraBackend, CameraInfo, Hal};
+use systems::{camera, drivetrain, logs, power, telemetry};
 
-pub fn get_camera_cfgs() -> Result<Vec<CameraInfo>> {
+pub fn get_camera_cfgs(cameras: &dyn CameraBackend) -> Result<Vec<CameraInfo>> {
     info!("Searching for cameras");
-    let mut cam_cfgs = nokhwa::query()?;
-    cam_cfgs.sort_by(|a, b| a.index().cmp(&b.index()));
+    let cam_cfgs = cameras.list()?;
     debug!("Found cameras:");
     for cfg in &cam_cfgs {
-        debug!(
-            "{}: {} -- {}",
-            cfg.index(),
-            cfg.human_name(),
-            cfg.description()
-        );
+        debug!("{}: {} -- {}", cfg.index, cfg.human_name, cfg.description);
     }
     Ok(cam_cfgs)
 }
 
+const CONFIG_PATH: &str = "config/bot.yml";
+/// how often the send backlog is checked when nothing else is happening, so that streams that only send their
+/// latest frame notice promptly once it has been sent
+const BACKLOG_REFRESH_INTERVAL: Duration = Duration::from_millis(10);
+
 #[tokio::main]
 async fn main() -> Result<()> {
     println!("Reading configuration");
-    let cfg = config::load_config("config/bot.yml")?;
+    let cfg = config::load_config(CONFIG_PATH)?;
+    if env::args().skip(1).any(|arg| arg == "--check-config") {
+        log_forwarding::check_config(&cfg.logging.config)?;
+        println!("{} and {:?} are valid", CONFIG_PATH, cfg.logging.config);
+        return Ok(());
+    }
 
     println!("Initializing logging");
-    pretty_env_logger::formatted_builder()
-        .filter_level(log::LevelFilter::Debug)
-        .init();
+    let log_forwarding = log_forwarding::init(&cfg.logging)?;
     info!("Initialized logging");
     info!("Read configuration {:#?}", cfg);
 
     info!("Initializing application bus");
     let mut bus = DABus::new();
+    bus.register(logs::LogSystem::new(log_forwarding));
+    let log_update_channel = bus.fire(logs::GET_RECEIVER, ()).await?;
 
-    let _ = get_camera_cfgs()?;
+    info!("Initializing hardware");
+    let hal = Hal::from_config(&cfg.hal, &cfg.safety)?;
+
+    let _ = get_camera_cfgs(hal.cameras.as_ref())?;
     info!("Starting camera server");
-    bus.register(camera::CameraSystem::new());
+    bus.register(camera::CameraSystem::new(
+        hal.cameras.clone(),
+        cfg.cameras.recording.clone(),
+        cfg.cameras.recovery,
+    ));
     let camera_update_channel = bus.fire(camera::GET_RECEIVER, ()).await?;
+    let download_channel = bus.fire(camera::GET_DOWNLOAD_RECEIVER, ()).await?;
+    let link_backlog = bus.fire(camera::GET_LINK_BACKLOG, ()).await?;
 
     info!("Starting drivetrain");
-    // let mut drive = drivetrain::SuperHackyStepperDriver::new();
+    let limits = limits::TravelLimits::from_config(&cfg.safety, hal.gpio.as_ref())?;
+    bus.register(drivetrain::DrivetrainSystem::new(
+        hal.motor,
+        hal.encoder,
+        limits,
+        &cfg,
+        CONFIG_PATH.into(),
+    ));
+    let drive_update_channel = bus.fire(drivetrain::GET_RECEIVER, ()).await?;
+
+    info!("Starting power monitoring");
+    bus.register(power::PowerSystem::new(hal.power, cfg.power.clone()));
+    let power_update_channel = bus.fire(power::GET_RECEIVER, ()).await?;
+
+    info!("Starting telemetry");
+    bus.register(telemetry::TelemetrySystem::new());
+    let telemetry_update_channel = bus.fire(telemetry::GET_RECEIVER, ()).await?;
 
     // info!("Starting motor controller subsystem");
     // let mut motor_controller = adafruit_motorkit::init_pwm(None)?;
@@ -96,85 +143,146 @@ async fn main() -> Result<()> {
     // motor0.stop(&mut motor_controller)?;
     //* end tmp code
 
+    // only one dashboard is served at a time, every message from the systems goes to it. the bot keeps running
+    // without one, waiting for the next
+    let listener = TcpListener::bind(cfg.network.addr).await?;
     info!("Listening for a new connection");
-    let listener = TcpListener::bind(cfg.addr).await?;
-    let (raw_conn, port) = listener.accept().await?;
-    info!("Connected to {}", port);
-    let mut conn = Stream::<Message, _>::new(raw_conn, bincode::DefaultOptions::new());
+    let mut conn: Option<Connection> = None;
+    let mut backlog_refresh = tokio::time::interval(BACKLOG_REFRESH_INTERVAL);
+    let mut telemetry_tick = tokio::time::interval(cfg.telemetry.period);
+    // the battery is checked whether or not a dashboard is connected
+    let mut power_tick = tokio::time::interval(cfg.power.period);
 
     loop {
+        // so that adaptive video streams can tell when the link is congested
+        if let Some(conn) = &conn {
+            link_backlog.store(conn.queued_bytes(), Ordering::Relaxed);
+        }
+        // messages from the systems while there is no dashboard are dropped, except for the logs which are kept
+        // for the next one
         select! {
-            update_res = conn.update_loop() => {
-                if let Err(e) = update_res {
-                    error!("{:?}", e);
-                    break;
-                }
-                info!(
-                    "received: {:?}",
-                    match conn.get() {
+            accepted = listener.accept(), if conn.is_none() => {
+                let (raw_conn, port) = accepted?;
+                info!("Connected to {}", port);
+                conn = Some(Stream::new(raw_conn, bincode::DefaultOptions::new()));
+            }
+            update_res = update_conn(&mut conn) => {
+                let connected = match update_res {
+                    Ok(_) => match conn.as_mut().and_then(|conn| conn.get()) {
                         Some(Message::DashboardDisconnect) => {
                             info!("Dashboard disconnected");
-                            break;
+                            false
                         }
                         Some(m) => {
-                            match m.clone() {
+                            info!("received: {:?}", m);
+                            match m {
                                 Message::VideoStreamCtl { id, action } => {
                                     bus.fire(camera::FEED_CTRL_MSG, (id, action)).await?;
                                 }
-                                Message::Drive(_action) => {
-                                    // drive.set_dir(match action {
-                                    //     DriveAction::Fwd => drivetrain::MotorAction::Fwd,
-                                    //     DriveAction::Rev => drivetrain::MotorAction::Back,
-                                    //     DriveAction::Stop => drivetrain::MotorAction::Stop,
-                                    // });
+                                Message::ListCameras => {
+                                    bus.fire(camera::LIST_CAMERAS, ()).await?;
+                                }
+                                Message::ListCameraFormats { index } => {
+                                    bus.fire(camera::LIST_CAMERA_FORMATS, index).await?;
+                                }
+                                Message::RecordingCtl(action) => {
+                                    bus.fire(camera::RECORDING_CTRL_MSG, action).await?;
+                                }
+                                Message::Drive(action) => {
+                                    bus.fire(drivetrain::DRIVE_CTRL_MSG, action).await?;
+                                }
+                                Message::WaypointCtl(action) => {
+                                    bus.fire(drivetrain::WAYPOINT_CTRL_MSG, action).await?;
+                                }
+                                Message::TelemetryCtl(action) => {
+                                    bus.fire(telemetry::TELEMETRY_CTRL_MSG, action).await?;
+                                }
+                                Message::LogCtl(action) => {
+                                    bus.fire(logs::LOG_CTRL_MSG, action).await?;
                                 }
                                 other => {
                                     error!("Unhandled message:\n{:#?}", other);
                                 }
                             }
-                            m
+                            true
                         }
-                        None => continue,
+                        None => true,
+                    },
+                    Err(e) => {
+                        error!("{:?}", e);
+                        false
                     }
-                );
+                };
+                if !connected {
+                    conn = None;
+                    // nothing the dashboard started is left going without it
+                    bus.fire(camera::CLOSE_ALL, ()).await?;
+                    bus.fire(drivetrain::DRIVE_CTRL_MSG, DriveAction::Stop).await?;
+                    bus.fire(telemetry::TELEMETRY_CTRL_MSG, TelemetryAction::Unsubscribe).await?;
+                    link_backlog.store(0, Ordering::Relaxed);
+                    info!("Listening for a new connection");
+                }
             }
             to_send = camera_update_channel.recv_async() => {
-                conn.queue(&to_send?)?;
+                let mut to_send = to_send?;
+                if let Message::VideoStreamInfo { action: VideoStreamInfo::Snapshot(snapshot), .. } = &mut to_send {
+                    // cameras do not know where the bot is, so it is filled in on the way out
+                    snapshot.position = Some(bus.fire(drivetrain::GET_POSITION, ()).await?);
+                }
+                send(&mut conn, &to_send)?;
+            }
+            // recordings are only sent while the link keeps up, so they do not pile up ahead of the video
+            to_send = download_channel.recv_async(),
+                if conn.as_ref().map_or(true, |conn| conn.queued_bytes() < camera_server::DOWNLOAD_BACKLOG_LIMIT) => {
+                send(&mut conn, &to_send?)?;
             }
+            to_send = drive_update_channel.recv_async() => {
+                send(&mut conn, &to_send?)?;
+            }
+            to_send = telemetry_update_channel.recv_async() => {
+                send(&mut conn, &to_send?)?;
+            }
+            to_send = power_update_channel.recv_async() => {
+                send(&mut conn, &to_send?)?;
+            }
+            to_send = log_update_channel.recv_async(), if conn.is_some() => {
+                send(&mut conn, &to_send?)?;
+            }
+            _ = telemetry_tick.tick() => {
+                if bus.fire(telemetry::WANTED, ()).await? {
+                    // the telemetry system does not know about the others, so their state is gathered here
+                    let systems = telemetry::SystemsState {
+                        drivetrain: bus.fire(drivetrain::GET_STATUS, ()).await?,
+                        cameras: bus.fire(camera::GET_TELEMETRY, ()).await?,
+                        power: bus.fire(power::GET_STATUS, ()).await?,
+                    };
+                    bus.fire(telemetry::PUBLISH, systems).await?;
+                }
+            }
+            _ = power_tick.tick() => {
+                if let Some(action) = bus.fire(power::SAMPLE, ()).await? {
+                    bus.fire(drivetrain::POWER_ACTION, action).await?;
+                }
+            }
+            _ = backlog_refresh.tick() => {}
         };
     }
-
-    Ok(())
-}
-
-/// interface to the hardware of a quadrature encoder (with index)
-pub trait QIEncoderInterface {
-    /// get the encoder resolution in PPR
-    fn get_res(&mut self) -> usize;
-
-    // rotational progression:
-    // NOTE: i could be 1 at any point throughout, but it can only be 1 at ONE PLACE and that place stays constant
-    //
-    // a b i
-    // 0 0 1
-    // 1 0 0
-    // 1 1 0
-    // 0 1 0
-    //
-    /// reads the three channels of the encoder (a, b, index)
-    fn get_raw_vals(&self) -> [bool; 3];
 }
 
-/// a simple motor controller implementation.
-///
-/// minimum speed is 0, maximum speed is 100, and can be made negative to reverse the motor
-pub trait MotorController {
-    /// inverse the motor direction
-    fn inverse(&mut self);
+type Connection = Stream<Message, bincode::DefaultOptions>;
 
-    /// sets the number of seconds it takes to go to maximmum speed (open loop ramp rate)
-    fn set_ol_ramp_rate(&mut self, rate: f32);
+/// updates the connection to the dashboard, or waits forever if there is none
+async fn update_conn(conn: &mut Option<Connection>) -> Result<bool, StreamUpdateErr> {
+    match conn {
+        Some(conn) => conn.update_loop().await,
+        None => future::pending().await,
+    }
+}
 
-    /// sets the motors current speed (-100 to 100) (reversed full speed to full speed)
-    fn set_speed(&mut self, speed: f32);
+/// sends `message` to the dashboard, or drops it if there is none
+fn send(conn: &mut Option<Connection>, message: &Message) -> Result<()> {
+    if let Some(conn) = conn {
+        conn.queue(message)?;
+    }
+    Ok(())
 }
@@ -0,0 +1,439 @@
+//! Recording camera streams to files on the bot, so that footage is kept even if the link to the dashboard drops
+//!
+//! recordings are encoded separately from the stream, at full quality, and split into multiple files
+//! once they get too large or long. the oldest files are deleted to make room for new ones (see [`RecordingConf`])
+
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use aareocams_net::{
+    record::{RecordEntry, RecordWriter, EXTENSION},
+    RecordingListing,
+};
+use anyhow::{anyhow, Result};
+use image::RgbImage;
+use parking_lot::Mutex;
+use uuid::Uuid;
+
+use crate::config::RecordingConf;
+
+/// time between keyframes, which are where playback can start from
+const KEYFRAME_INTERVAL: Duration = Duration::from_secs(5);
+
+/// the files being recorded to, shared by every recorder so that none of them deletes a file another is still
+/// writing
+#[derive(Debug, Clone, Default)]
+pub struct ActiveRecordings(Arc<Mutex<HashSet<String>>>);
+
+impl ActiveRecordings {
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.lock().contains(name)
+    }
+
+    fn insert(&self, name: &str) {
+        self.0.lock().insert(name.to_string());
+    }
+
+    fn remove(&self, name: &str) {
+        self.0.lock().remove(name);
+    }
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct StreamRecorder {
+    cfg: RecordingConf,
+    active: ActiveRecordings,
+    stream: Uuid,
+    width: u32,
+    height: u32,
+    #[derivative(Debug = "ignore")]
+    encoder: lvenc::Encoder,
+    writer: RecordWriter<BufWriter<File>>,
+    /// name of the file being written to
+    name: String,
+    /// number of the file being written to, counting from 0
+    part: u32,
+    started: Instant,
+    last_keyframe: Instant,
+}
+
+impl StreamRecorder {
+    /// starts recording a stream with frames of `width`x`height`, adding its files to `active` while they are
+    /// recorded to
+    pub fn new(
+        cfg: RecordingConf,
+        active: ActiveRecordings,
+        stream: Uuid,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let (writer, name) = create_file(&cfg.dir, stream, 0)?;
+        active.insert(&name);
+        delete_old(&cfg, &active);
+        let now = Instant::now();
+        let mut recorder = Self {
+            cfg,
+            active,
+            stream,
+            width,
+            height,
+            encoder: lvenc::Encoder::new(width, height),
+            writer,
+            name,
+            part: 0,
+            started: now,
+            last_keyframe: now,
+        };
+        recorder.writer.write(&RecordEntry::Keyframe { time: 0 })?;
+        Ok(recorder)
+    }
+
+    /// name of the file being recorded to
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// records `frame`, returning the name of the new file if the recording was split
+    pub fn record(&mut self, frame: &RgbImage) -> Result<Option<String>> {
+        let mut new_file = None;
+        if self.writer.bytes_written() >= self.cfg.max_file_size
+            || self.started.elapsed() >= self.cfg.max_file_duration
+        {
+            // each file starts with a new encoder, so that they can be played back on their own
+            self.part += 1;
+            let (writer, name) = create_file(&self.cfg.dir, self.stream, self.part)?;
+            self.active.insert(&name);
+            let old_name = std::mem::replace(&mut self.name, name.clone());
+            let mut old = std::mem::replace(&mut self.writer, writer);
+            let flushed = old.flush();
+            // the old file is finished, so it can be deleted like any other from now on
+            self.active.remove(&old_name);
+            flushed?;
+            delete_old(&self.cfg, &self.active);
+            info!("Recording {} continues in {}", old_name, name);
+            self.started = Instant::now();
+            self.restart_encoder()?;
+            new_file = Some(name);
+        } else if self.last_keyframe.elapsed() >= KEYFRAME_INTERVAL {
+            self.restart_encoder()?;
+        }
+
+        self.encoder.encode_frame(frame.clone());
+        let time = self.started.elapsed().as_millis() as u64;
+        for packet in self.encoder.packets() {
+            self.writer.write(&RecordEntry::Packet { time, packet })?;
+        }
+        Ok(new_file)
+    }
+
+    /// flushes everything recorded so far to disk
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn restart_encoder(&mut self) -> Result<()> {
+        self.encoder = lvenc::Encoder::new(self.width, self.height);
+        self.last_keyframe = Instant::now();
+        let time = self.started.elapsed().as_millis() as u64;
+        self.writer.write(&RecordEntry::Keyframe { time })?;
+        Ok(())
+    }
+}
+
+impl Drop for StreamRecorder {
+    fn drop(&mut self) {
+        self.active.remove(&self.name);
+    }
+}
+
+/// creates a new recording file for part `part` of `stream` in `dir`, returning it and its name
+fn create_file(
+    dir: &Path,
+    stream: Uuid,
+    part: u32,
+) -> Result<(RecordWriter<BufWriter<File>>, String)> {
+    fs::create_dir_all(dir)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let name = format!(
+        "{}-{}-{:03}.{}",
+        now,
+        &stream.to_simple().to_string()[..8],
+        part,
+        EXTENSION
+    );
+    let file = File::create(dir.join(&name))?;
+    Ok((RecordWriter::new(BufWriter::new(file))?, name))
+}
+
+/// deletes the oldest recordings until they are within the limits in `cfg`, leaving the ones in `active` that are
+/// still being recorded to. failing to is only logged, so that it does not stop the recording
+fn delete_old(cfg: &RecordingConf, active: &ActiveRecordings) {
+    if cfg.max_total_size == 0 && cfg.max_files == 0 {
+        return;
+    }
+    let recordings = match list_recordings(&cfg.dir) {
+        Ok(recordings) => recordings,
+        Err(list_error) => {
+            warn!("Failed to list recordings to delete:\n{:#?}", list_error);
+            return;
+        }
+    };
+    let (active_files, old_files) = recordings
+        .iter()
+        .partition::<Vec<_>, _>(|recording| active.contains(&recording.name));
+    let mut files = recordings.len();
+    // the files being recorded to will grow up to the maximum size
+    let mut total_size = old_files
+        .iter()
+        .map(|recording| recording.size)
+        .sum::<u64>()
+        + cfg.max_file_size * active_files.len() as u64;
+    for recording in old_files {
+        let too_many = cfg.max_files != 0 && files > cfg.max_files;
+        let too_large = cfg.max_total_size != 0 && total_size > cfg.max_total_size;
+        if !too_many && !too_large {
+            break;
+        }
+        match fs::remove_file(cfg.dir.join(&recording.name)) {
+            Ok(()) => info!(
+                "Deleted recording {} to make room for new ones",
+                recording.name
+            ),
+            Err(delete_error) => {
+                warn!(
+                    "Failed to delete recording {}:\n{:#?}",
+                    recording.name, delete_error
+                );
+                continue;
+            }
+        }
+        files -= 1;
+        total_size -= recording.size;
+    }
+}
+
+/// lists the recordings in `dir`, oldest first
+pub fn list_recordings(dir: &Path) -> Result<Vec<RecordingListing>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut recordings = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path
+            .extension()
+            .map_or(true, |extension| extension != EXTENSION)
+        {
+            continue;
+        }
+        let meta = entry.metadata()?;
+        recordings.push(RecordingListing {
+            name: entry.file_name().to_string_lossy().into(),
+            size: meta.len(),
+            modified: meta
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+        });
+    }
+    recordings.sort_by(|a, b| (a.modified, &a.name).cmp(&(b.modified, &b.name)));
+    Ok(recordings)
+}
+
+/// path of the recording called `name` in `dir`, making sure that it does not point outside of `dir`
+pub fn recording_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    let path = Path::new(name);
+    if path.file_name().map_or(true, |file_name| file_name != name)
+        || path
+            .extension()
+            .map_or(true, |extension| extension != EXTENSION)
+    {
+        return Err(anyhow!("`{}` is not the name of a recording", name));
+    }
+    Ok(dir.join(path))
+}
+
+#[test]
+fn test_recording_path() {
+    let dir = Path::new("recordings");
+    assert_eq!(
+        recording_path(dir, "1-abc.lvr").unwrap(),
+        dir.join("1-abc.lvr")
+    );
+    assert!(recording_path(dir, "../config/bot.yml").is_err());
+    assert!(recording_path(dir, "../1-abc.lvr").is_err());
+    assert!(recording_path(dir, "/1-abc.lvr").is_err());
+    assert!(recording_path(dir, "bot.yml").is_err());
+}
+
+#[test]
+fn test_record_and_split() {
+    use aareocams_net::record::RecordReader;
+
+    let dir = std::env::temp_dir().join(format!("aareocams-test-{}", Uuid::new_v4()));
+    let cfg = RecordingConf {
+        dir: dir.clone(),
+        max_file_size: 2048,
+        max_file_duration: Duration::from_secs(60),
+        max_total_size: 0,
+        max_files: 0,
+    };
+    let frame = RgbImage::from_fn(32, 32, |x, y| image::Rgb([x as u8 * 8, y as u8 * 8, 0]));
+    let mut recorder =
+        StreamRecorder::new(cfg, ActiveRecordings::default(), Uuid::new_v4(), 32, 32).unwrap();
+    let mut names = vec![recorder.name().to_string()];
+    for _ in 0..20 {
+        if let Some(name) = recorder.record(&frame).unwrap() {
+            names.push(name);
+        }
+    }
+    recorder.finish().unwrap();
+    assert!(names.len() > 1);
+
+    let recordings = list_recordings(&dir).unwrap();
+    assert_eq!(recordings.len(), names.len());
+    for recording in recordings {
+        let file = File::open(recording_path(&dir, &recording.name).unwrap()).unwrap();
+        let entries = RecordReader::new(file)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        // every file can be played back on its own
+        assert!(matches!(entries[0], RecordEntry::Keyframe { .. }));
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_delete_old() {
+    let dir = std::env::temp_dir().join(format!("aareocams-test-{}", Uuid::new_v4()));
+    let cfg = RecordingConf {
+        dir: dir.clone(),
+        max_file_size: 2048,
+        max_file_duration: Duration::from_secs(60),
+        max_total_size: 0,
+        max_files: 3,
+    };
+    let frame = RgbImage::from_fn(32, 32, |x, y| image::Rgb([x as u8 * 8, y as u8 * 8, 0]));
+    let mut recorder = StreamRecorder::new(
+        cfg.clone(),
+        ActiveRecordings::default(),
+        Uuid::new_v4(),
+        32,
+        32,
+    )
+    .unwrap();
+    let mut names = vec![recorder.name().to_string()];
+    for _ in 0..40 {
+        if let Some(name) = recorder.record(&frame).unwrap() {
+            names.push(name);
+        }
+    }
+    recorder.finish().unwrap();
+    assert!(names.len() > 3);
+    // the newest files are kept
+    let kept = list_recordings(&dir)
+        .unwrap()
+        .into_iter()
+        .map(|recording| recording.name)
+        .collect::<Vec<_>>();
+    assert_eq!(kept, names[names.len() - 3..]);
+
+    // with room for the files that are there, and a new one
+    let total_size = list_recordings(&dir)
+        .unwrap()
+        .iter()
+        .map(|recording| recording.size)
+        .sum::<u64>();
+    let recorder = StreamRecorder::new(
+        RecordingConf {
+            max_total_size: total_size + cfg.max_file_size,
+            max_files: 0,
+            ..cfg.clone()
+        },
+        ActiveRecordings::default(),
+        Uuid::new_v4(),
+        32,
+        32,
+    )
+    .unwrap();
+    recorder.finish().unwrap();
+    assert_eq!(list_recordings(&dir).unwrap().len(), 4);
+    // without room for the oldest one
+    let recorder = StreamRecorder::new(
+        RecordingConf {
+            max_total_size: total_size + cfg.max_file_size,
+            max_files: 0,
+            ..cfg
+        },
+        ActiveRecordings::default(),
+        Uuid::new_v4(),
+        32,
+        32,
+    )
+    .unwrap();
+    recorder.finish().unwrap();
+    let recordings = list_recordings(&dir).unwrap();
+    assert_eq!(recordings.len(), 4);
+    assert!(recordings
+        .iter()
+        .all(|recording| recording.name != names[names.len() - 3]));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_delete_old_active() {
+    let dir = std::env::temp_dir().join(format!("aareocams-test-{}", Uuid::new_v4()));
+    let cfg = RecordingConf {
+        dir: dir.clone(),
+        max_file_size: 2048,
+        max_file_duration: Duration::from_secs(60),
+        max_total_size: 0,
+        max_files: 1,
+    };
+    let frame = RgbImage::from_fn(32, 32, |x, y| image::Rgb([x as u8 * 8, y as u8 * 8, 0]));
+    let names = || {
+        list_recordings(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|recording| recording.name)
+            .collect::<Vec<_>>()
+    };
+    let active = ActiveRecordings::default();
+    let mut first =
+        StreamRecorder::new(cfg.clone(), active.clone(), Uuid::new_v4(), 32, 32).unwrap();
+    let first_name = first.name().to_string();
+
+    // another stream recording at the same time does not delete the file the first one is writing
+    let mut second =
+        StreamRecorder::new(cfg.clone(), active.clone(), Uuid::new_v4(), 32, 32).unwrap();
+    let mut split = false;
+    for _ in 0..40 {
+        split |= second.record(&frame).unwrap().is_some();
+    }
+    assert!(split);
+    assert_eq!(names(), [first_name.clone(), second.name().to_string()]);
+    first.record(&frame).unwrap();
+    first.finish().unwrap();
+    second.finish().unwrap();
+    assert!(!active.contains(&first_name));
+
+    // once they are finished, they are deleted like any other
+    let third = StreamRecorder::new(cfg, active, Uuid::new_v4(), 32, 32).unwrap();
+    assert_eq!(names(), [third.name().to_string()]);
+    third.finish().unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,160 @@
+//! Keeping track of read errors on a camera stream, and of reopening the camera once it is lost
+//!
+//! this only decides what to do and when, the camera worker does the actual reopening
+
+use std::time::{Duration, Instant};
+
+use crate::config::CameraRecoveryConf;
+
+/// read errors are reported to the dashboard at most this often, so that a broken camera does not flood the link
+pub const ERROR_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// what to do about a failed read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadFailure {
+    /// nothing, it was reported recently enough
+    Suppress,
+    /// report it, along with how many errors were suppressed since the last report
+    Report { suppressed: u32 },
+    /// the camera is lost, and has to be reopened
+    Lost,
+}
+
+#[derive(Debug)]
+pub struct CameraRecovery {
+    cfg: CameraRecoveryConf,
+    /// read errors in a row
+    consecutive_errors: u32,
+    /// errors that were not reported because of [`ERROR_REPORT_INTERVAL`]
+    suppressed_errors: u32,
+    last_report: Option<Instant>,
+    /// failed attempts to reopen the camera, and when to try next. `None` if the camera is not lost
+    lost: Option<(u32, Instant)>,
+}
+
+impl CameraRecovery {
+    pub fn new(cfg: CameraRecoveryConf) -> Self {
+        Self {
+            cfg,
+            consecutive_errors: 0,
+            suppressed_errors: 0,
+            last_report: None,
+            lost: None,
+        }
+    }
+
+    pub fn read_ok(&mut self) {
+        self.consecutive_errors = 0;
+    }
+
+    pub fn read_failed(&mut self, now: Instant) -> ReadFailure {
+        self.consecutive_errors += 1;
+        if self.consecutive_errors >= self.cfg.lost_after_errors {
+            self.lost(now);
+            return ReadFailure::Lost;
+        }
+        match self.last_report {
+            Some(last_report)
+                if now.saturating_duration_since(last_report) < ERROR_REPORT_INTERVAL =>
+            {
+                self.suppressed_errors += 1;
+                ReadFailure::Suppress
+            }
+            _ => {
+                self.last_report = Some(now);
+                ReadFailure::Report {
+                    suppressed: std::mem::take(&mut self.suppressed_errors),
+                }
+            }
+        }
+    }
+
+    /// declares the camera lost at `now` without waiting for read errors, because it could not be opened again
+    pub fn lost(&mut self, now: Instant) {
+        self.consecutive_errors = 0;
+        self.lost = Some((0, now + self.cfg.reopen_delay));
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.lost.is_some()
+    }
+
+    /// when to try reopening the camera next, if it is lost
+    pub fn next_attempt(&self) -> Option<Instant> {
+        self.lost.map(|(_, next_attempt)| next_attempt)
+    }
+
+    /// failed attempts to reopen the camera since it was lost
+    pub fn attempts(&self) -> u32 {
+        self.lost.map_or(0, |(attempts, _)| attempts)
+    }
+
+    /// records a failed attempt to reopen the camera at `now`, returning `false` if the stream should give up
+    pub fn reopen_failed(&mut self, now: Instant) -> bool {
+        let attempts = self.attempts() + 1;
+        if attempts >= self.cfg.max_reopen_attempts {
+            return false;
+        }
+        let delay = self
+            .cfg
+            .reopen_delay
+            .saturating_mul(1 << attempts.min(16))
+            .min(self.cfg.max_reopen_delay);
+        self.lost = Some((attempts, now + delay));
+        true
+    }
+
+    pub fn reopened(&mut self) {
+        self.lost = None;
+        self.consecutive_errors = 0;
+    }
+}
+
+#[test]
+fn test_recovery() {
+    let mut recovery = CameraRecovery::new(CameraRecoveryConf {
+        lost_after_errors: 3,
+        max_reopen_attempts: 4,
+        reopen_delay: Duration::from_secs(1),
+        max_reopen_delay: Duration::from_secs(3),
+    });
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+
+    // errors are rate limited, and only count towards losing the camera when they are in a row
+    assert_eq!(
+        recovery.read_failed(at(0)),
+        ReadFailure::Report { suppressed: 0 }
+    );
+    assert_eq!(recovery.read_failed(at(10)), ReadFailure::Suppress);
+    recovery.read_ok();
+    assert_eq!(recovery.read_failed(at(20)), ReadFailure::Suppress);
+    assert_eq!(
+        recovery.read_failed(at(1000)),
+        ReadFailure::Report { suppressed: 2 }
+    );
+    assert!(!recovery.is_lost());
+    assert_eq!(recovery.read_failed(at(1010)), ReadFailure::Lost);
+    assert_eq!(recovery.next_attempt(), Some(at(2010)));
+
+    // the delay between attempts doubles, up to the maximum
+    assert!(recovery.reopen_failed(at(2010)));
+    assert_eq!(recovery.next_attempt(), Some(at(4010)));
+    assert!(recovery.reopen_failed(at(4010)));
+    assert_eq!(recovery.next_attempt(), Some(at(7010)));
+    recovery.reopened();
+    assert!(!recovery.is_lost());
+
+    assert_eq!(
+        recovery.read_failed(at(8000)),
+        ReadFailure::Report { suppressed: 0 }
+    );
+    recovery.read_failed(at(8001));
+    assert_eq!(recovery.read_failed(at(8002)), ReadFailure::Lost);
+    for attempt in 1..4 {
+        assert!(recovery.reopen_failed(at(9000)));
+        assert_eq!(recovery.attempts(), attempt);
+    }
+    // giving up on the fourth failed attempt
+    assert!(!recovery.reopen_failed(at(9000)));
+}
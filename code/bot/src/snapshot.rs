@@ -0,0 +1,55 @@
+//! Still images, taken at full quality instead of through a video stream
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aareocams_net::{ImageEncoding, Snapshot};
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, ImageOutputFormat, RgbImage};
+
+/// encodes `frame` as a snapshot taken now. the position is left for whatever knows where the bot is to fill in
+pub fn encode_snapshot(frame: RgbImage, encoding: ImageEncoding) -> Result<Snapshot> {
+    let taken_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let output_format = match encoding {
+        ImageEncoding::Png => ImageOutputFormat::Png,
+        ImageEncoding::Jpeg { quality } if (1..=100).contains(&quality) => {
+            ImageOutputFormat::Jpeg(quality)
+        }
+        ImageEncoding::Jpeg { quality } => {
+            return Err(anyhow!(
+                "jpeg quality must be between 1 and 100, not {}",
+                quality
+            ))
+        }
+    };
+    let (width, height) = frame.dimensions();
+    let mut data = vec![];
+    DynamicImage::ImageRgb8(frame).write_to(&mut data, output_format)?;
+    Ok(Snapshot {
+        width,
+        height,
+        encoding,
+        data,
+        taken_at,
+        position: None,
+    })
+}
+
+#[test]
+fn test_encode_snapshot() {
+    let frame = RgbImage::from_fn(32, 16, |x, y| image::Rgb([x as u8 * 8, y as u8 * 16, 0]));
+
+    let png = encode_snapshot(frame.clone(), ImageEncoding::Png).unwrap();
+    assert_eq!((png.width, png.height), (32, 16));
+    // png is lossless
+    let decoded = image::load_from_memory(&png.data).unwrap().into_rgb8();
+    assert_eq!(decoded, frame);
+
+    let jpeg = encode_snapshot(frame, ImageEncoding::Jpeg { quality: 90 }).unwrap();
+    let decoded = image::load_from_memory(&jpeg.data).unwrap().into_rgb8();
+    assert_eq!(decoded.dimensions(), (32, 16));
+
+    assert!(encode_snapshot(RgbImage::new(1, 1), ImageEncoding::Jpeg { quality: 0 }).is_err());
+}
@@ -0,0 +1,457 @@
+//! Encoding of camera frames into the messages of a video stream, following the [`EncoderSettings`] of the stream
+
+use std::time::{Duration, Instant};
+
+use aareocams_net::{EncoderSettings, Message, OperatingPoint, VideoStreamInfo};
+use anyhow::{anyhow, Result};
+use bincode::Options;
+use image::{imageops::FilterType, RgbImage};
+use uuid::Uuid;
+
+use crate::adaptive::AdaptiveController;
+
+/// smallest allowed [`EncoderSettings::max_packet_size`], so that the overhead of each message stays small
+const MIN_PACKET_SIZE: usize = 512;
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct StreamEncoder {
+    id: Uuid,
+    width: u32,
+    height: u32,
+    #[derivative(Debug = "ignore")]
+    encoder: lvenc::Encoder,
+    /// resolution the encoder was made for, after downscaling
+    encoder_size: (u32, u32),
+    settings: EncoderSettings,
+    /// frames encoded since the encoder was last restarted
+    frames_since_keyframe: u32,
+    /// if the encoder should be restarted with the next frame, whatever the keyframe interval says
+    keyframe_requested: bool,
+    /// frames received from the camera, including skipped ones
+    frames: u64,
+    /// when the next frame may be sent, if [`EncoderSettings::max_fps`] is set
+    next_frame_due: Option<Instant>,
+    /// `None` if the stream is not adaptive
+    adaptive: Option<AdaptiveController>,
+    /// the last operating point sent to the dashboard
+    operating_point: OperatingPoint,
+}
+
+impl StreamEncoder {
+    /// `now` is when the stream starts, which adaptive streams wait a moment after before adjusting themselves
+    pub fn new(
+        id: Uuid,
+        width: u32,
+        height: u32,
+        settings: EncoderSettings,
+        now: Instant,
+    ) -> Result<Self> {
+        check_settings(&settings)?;
+        Ok(Self {
+            id,
+            width,
+            height,
+            encoder: lvenc::Encoder::new(width, height),
+            encoder_size: (width, height),
+            adaptive: settings.adaptive.then(|| AdaptiveController::new(now)),
+            settings,
+            frames_since_keyframe: 0,
+            keyframe_requested: false,
+            frames: 0,
+            next_frame_due: None,
+            operating_point: OperatingPoint::FULL,
+        })
+    }
+
+    /// changes the settings at `now`, which apply from the next frame on
+    pub fn reconfigure(&mut self, settings: EncoderSettings, now: Instant) -> Result<()> {
+        check_settings(&settings)?;
+        if !settings.adaptive {
+            self.adaptive = None;
+        } else if self.adaptive.is_none() {
+            self.adaptive = Some(AdaptiveController::new(now));
+        }
+        self.settings = settings;
+        Ok(())
+    }
+
+    /// makes the next frame that is sent start with a keyframe
+    pub fn request_keyframe(&mut self) {
+        self.keyframe_requested = true;
+    }
+
+    pub fn settings(&self) -> &EncoderSettings {
+        &self.settings
+    }
+
+    /// if [`EncoderSettings::max_fps`] allows a frame to be sent at `now`, counting it as sent if so
+    fn frame_rate_allows(&mut self, now: Instant) -> bool {
+        let period = match self.settings.max_fps {
+            Some(max_fps) => Duration::from_secs(1) / max_fps,
+            None => {
+                self.next_frame_due = None;
+                return true;
+            }
+        };
+        match self.next_frame_due {
+            Some(due) if now < due => false,
+            due => {
+                // scheduled from when the last frame was due rather than when it was sent, so that the frame rate
+                // averages out to the limit instead of being rounded down to a fraction of the camera's
+                self.next_frame_due = Some((due.unwrap_or(now) + period).max(now));
+                true
+            }
+        }
+    }
+
+    /// encodes `frame`, captured at `now`, returning the messages to send for it. `backlog` is the number of bytes
+    /// waiting to be sent to the dashboard, which adaptive streams adjust themselves to
+    pub fn encode(
+        &mut self,
+        frame: RgbImage,
+        backlog: usize,
+        now: Instant,
+    ) -> Result<Vec<Message>> {
+        let mut messages = vec![];
+        if let Some(adaptive) = &mut self.adaptive {
+            adaptive.update(backlog, now);
+        }
+        let point = self
+            .adaptive
+            .as_ref()
+            .map_or(OperatingPoint::FULL, AdaptiveController::operating_point);
+        if point != self.operating_point {
+            info!("video stream {}: adapting to {}", self.id, point);
+            self.operating_point = point;
+            messages.push(Message::VideoStreamInfo {
+                id: self.id,
+                action: VideoStreamInfo::OperatingPoint(point),
+            });
+        }
+
+        self.frames += 1;
+        if (self.frames - 1) % point.frame_interval as u64 != 0 {
+            return Ok(messages);
+        }
+        if self.settings.latest_frame_only && backlog > 0 {
+            // the frame would only be more out of date by the time it got there
+            return Ok(messages);
+        }
+        if !self.frame_rate_allows(now) {
+            return Ok(messages);
+        }
+
+        let size = (
+            (self.width / point.downscale).max(1),
+            (self.height / point.downscale).max(1),
+        );
+        let keyframe_due = self.keyframe_requested
+            || self
+                .settings
+                .keyframe_interval
+                .map_or(false, |interval| self.frames_since_keyframe >= interval);
+        if size != self.encoder_size || keyframe_due {
+            // lvenc has no keyframes of its own, so start over with a new encoder (and decoder)
+            self.encoder = lvenc::Encoder::new(size.0, size.1);
+            self.encoder_size = size;
+            self.frames_since_keyframe = 0;
+            self.keyframe_requested = false;
+            messages.push(Message::VideoStreamInfo {
+                id: self.id,
+                action: VideoStreamInfo::Keyframe,
+            });
+        }
+        self.frames_since_keyframe += 1;
+
+        let mut frame = if size == frame.dimensions() {
+            frame
+        } else {
+            image::imageops::resize(&frame, size.0, size.1, FilterType::Triangle)
+        };
+        quantize(&mut frame, self.settings.quality.min(point.max_quality));
+        self.encoder.encode_frame(frame);
+        for packet in self.encoder.packets() {
+            let max_packet_size = match self.settings.max_packet_size {
+                Some(max_packet_size)
+                    if bincode::DefaultOptions::new().serialized_size(&packet)?
+                        > max_packet_size as u64 =>
+                {
+                    max_packet_size
+                }
+                _ => {
+                    messages.push(Message::VideoStreamData {
+                        id: self.id,
+                        packet,
+                    });
+                    continue;
+                }
+            };
+            let data = bincode::DefaultOptions::new().serialize(&packet)?;
+            let fragments = data.chunks(max_packet_size).count();
+            messages.extend(
+                data.chunks(max_packet_size)
+                    .enumerate()
+                    .map(|(i, fragment)| Message::VideoStreamFragment {
+                        id: self.id,
+                        data: fragment.to_vec(),
+                        last: i + 1 == fragments,
+                    }),
+            );
+        }
+        Ok(messages)
+    }
+}
+
+fn check_settings(settings: &EncoderSettings) -> Result<()> {
+    if !(1..=8).contains(&settings.quality) {
+        return Err(anyhow!(
+            "quality must be between 1 and 8, not {}",
+            settings.quality
+        ));
+    }
+    if let Some(max_packet_size) = settings.max_packet_size {
+        if max_packet_size < MIN_PACKET_SIZE {
+            return Err(anyhow!(
+                "max packet size must be at least {} bytes, not {}",
+                MIN_PACKET_SIZE,
+                max_packet_size
+            ));
+        }
+    }
+    if settings.keyframe_interval == Some(0) {
+        return Err(anyhow!("keyframe interval must be at least 1 frame"));
+    }
+    if settings.max_fps == Some(0) {
+        return Err(anyhow!("max frame rate must be at least 1 fps"));
+    }
+    Ok(())
+}
+
+/// keeps only the top `bits` bits of each color channel
+fn quantize(frame: &mut RgbImage, bits: u8) {
+    if bits >= 8 {
+        return;
+    }
+    let mask = !(u8::MAX >> bits);
+    for channel in frame.iter_mut() {
+        *channel &= mask;
+    }
+}
+
+#[test]
+fn test_check_settings() {
+    let settings = EncoderSettings::default();
+    assert!(check_settings(&settings).is_ok());
+    assert!(check_settings(&EncoderSettings {
+        quality: 0,
+        ..settings
+    })
+    .is_err());
+    assert!(check_settings(&EncoderSettings {
+        quality: 9,
+        ..settings
+    })
+    .is_err());
+    assert!(check_settings(&EncoderSettings {
+        max_packet_size: Some(MIN_PACKET_SIZE - 1),
+        ..settings
+    })
+    .is_err());
+    assert!(check_settings(&EncoderSettings {
+        keyframe_interval: Some(0),
+        ..settings
+    })
+    .is_err());
+    assert!(check_settings(&EncoderSettings {
+        max_fps: Some(0),
+        ..settings
+    })
+    .is_err());
+}
+
+#[test]
+fn test_quantize() {
+    let mut frame = RgbImage::from_pixel(2, 2, image::Rgb([0xff, 0x81, 0x7f]));
+    quantize(&mut frame, 8);
+    assert_eq!(frame.get_pixel(0, 0), &image::Rgb([0xff, 0x81, 0x7f]));
+    quantize(&mut frame, 1);
+    assert_eq!(frame.get_pixel(1, 1), &image::Rgb([0x80, 0x80, 0x00]));
+}
+
+#[test]
+fn test_keyframes_and_fragments() {
+    let frame = || RgbImage::from_fn(64, 64, |x, y| image::Rgb([x as u8 * 4, y as u8 * 4, 0]));
+    let is_keyframe = |message: &Message| {
+        matches!(
+            message,
+            Message::VideoStreamInfo {
+                action: VideoStreamInfo::Keyframe,
+                ..
+            }
+        )
+    };
+
+    let now = Instant::now();
+    let mut encoder = StreamEncoder::new(
+        Uuid::new_v4(),
+        64,
+        64,
+        EncoderSettings {
+            keyframe_interval: Some(2),
+            ..Default::default()
+        },
+        now,
+    )
+    .unwrap();
+    let keyframes = (0..6)
+        .map(|_| {
+            encoder
+                .encode(frame(), 0, now)
+                .unwrap()
+                .iter()
+                .any(is_keyframe)
+        })
+        .collect::<Vec<_>>();
+    // the first frame does not need one, since it is the start of the stream
+    assert_eq!(keyframes, [false, false, true, false, true, false]);
+
+    encoder
+        .reconfigure(
+            EncoderSettings {
+                max_packet_size: Some(MIN_PACKET_SIZE),
+                ..Default::default()
+            },
+            now,
+        )
+        .unwrap();
+    let mut data = vec![];
+    let mut packets = vec![];
+    for message in encoder.encode(frame(), 0, now).unwrap() {
+        match message {
+            Message::VideoStreamData { packet, .. } => packets.push(packet),
+            Message::VideoStreamFragment {
+                data: fragment,
+                last,
+                ..
+            } => {
+                assert!(fragment.len() <= MIN_PACKET_SIZE);
+                data.extend(fragment);
+                if last {
+                    packets.push(bincode::DefaultOptions::new().deserialize(&data).unwrap());
+                    data.clear();
+                }
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+    assert!(data.is_empty());
+    assert!(!packets.is_empty());
+
+    // without a keyframe interval, there are only keyframes when they are asked for
+    assert!(!encoder
+        .encode(frame(), 0, now)
+        .unwrap()
+        .iter()
+        .any(is_keyframe));
+    encoder.request_keyframe();
+    assert!(encoder
+        .encode(frame(), 0, now)
+        .unwrap()
+        .iter()
+        .any(is_keyframe));
+    assert!(!encoder
+        .encode(frame(), 0, now)
+        .unwrap()
+        .iter()
+        .any(is_keyframe));
+}
+
+#[test]
+fn test_adaptive() {
+    use crate::adaptive::CONGESTED_BACKLOG;
+
+    let frame = || RgbImage::from_pixel(64, 64, image::Rgb([0x80, 0x40, 0x20]));
+    let operating_points = |messages: Vec<Message>| {
+        messages
+            .into_iter()
+            .filter_map(|message| match message {
+                Message::VideoStreamInfo {
+                    action: VideoStreamInfo::OperatingPoint(point),
+                    ..
+                } => Some(point),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let start = Instant::now();
+    let mut adaptive =
+        StreamEncoder::new(Uuid::new_v4(), 64, 64, EncoderSettings::default(), start).unwrap();
+    let mut fixed = StreamEncoder::new(
+        Uuid::new_v4(),
+        64,
+        64,
+        EncoderSettings {
+            adaptive: false,
+            ..Default::default()
+        },
+        start,
+    )
+    .unwrap();
+    // a stream that just started is not turned down straight away
+    assert!(
+        operating_points(adaptive.encode(frame(), CONGESTED_BACKLOG, start).unwrap()).is_empty()
+    );
+    let now = start + Duration::from_millis(600);
+    let points = operating_points(adaptive.encode(frame(), CONGESTED_BACKLOG, now).unwrap());
+    assert_eq!(points.len(), 1);
+    assert!(points[0].max_quality < OperatingPoint::FULL.max_quality);
+    assert!(operating_points(fixed.encode(frame(), CONGESTED_BACKLOG, now).unwrap()).is_empty());
+
+    // turning adaptation off goes back to the full stream
+    adaptive
+        .reconfigure(
+            EncoderSettings {
+                adaptive: false,
+                ..Default::default()
+            },
+            now,
+        )
+        .unwrap();
+    assert_eq!(
+        operating_points(adaptive.encode(frame(), CONGESTED_BACKLOG, now).unwrap()),
+        [OperatingPoint::FULL]
+    );
+}
+
+#[test]
+fn test_frame_skipping() {
+    let settings = EncoderSettings {
+        max_fps: Some(20),
+        ..Default::default()
+    };
+    let start = Instant::now();
+    let mut encoder = StreamEncoder::new(Uuid::new_v4(), 16, 16, settings, start).unwrap();
+    // a 30 fps camera for a second
+    let sent = (0..30)
+        .filter(|&i| encoder.frame_rate_allows(start + Duration::from_secs(1) * i / 30))
+        .count();
+    assert_eq!(sent, 20);
+
+    let mut encoder = StreamEncoder::new(
+        Uuid::new_v4(),
+        16,
+        16,
+        EncoderSettings {
+            latest_frame_only: true,
+            adaptive: false,
+            ..Default::default()
+        },
+        start,
+    )
+    .unwrap();
+    let frame = || RgbImage::new(16, 16);
+    assert!(encoder.encode(frame(), 1, start).unwrap().is_empty());
+    assert!(!encoder.encode(frame(), 0, start).unwrap().is_empty());
+}
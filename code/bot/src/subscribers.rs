@@ -0,0 +1,200 @@
+//! Sharing one camera between any number of video streams
+//!
+//! a camera can only be opened once, so every stream from it gets its packets from the same worker and encoder.
+//! the streams are subscribers of the worker, which stops once the last one is closed
+
+use aareocams_net::{Message, VideoStreamInfo};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Subscriber {
+    id: Uuid,
+    paused: bool,
+    /// if the decoder of the stream is in step with the encoder. packets are only sent from a keyframe on,
+    /// since they could not be decoded before
+    synced: bool,
+}
+
+#[derive(Debug)]
+pub struct Subscribers {
+    subscribers: Vec<Subscriber>,
+}
+
+impl Subscribers {
+    /// the subscribers of a new worker, starting with the stream that opened it
+    pub fn new(id: Uuid) -> Self {
+        Self {
+            subscribers: vec![Subscriber {
+                id,
+                paused: false,
+                // the encoder is new as well
+                synced: true,
+            }],
+        }
+    }
+
+    /// adds a stream, returning `false` if it is already subscribed.
+    /// the encoder should be asked for a keyframe, since nothing is sent to the stream until the next one
+    pub fn subscribe(&mut self, id: Uuid) -> bool {
+        if self.contains(id) {
+            return false;
+        }
+        self.subscribers.push(Subscriber {
+            id,
+            paused: false,
+            synced: false,
+        });
+        true
+    }
+
+    /// removes a stream, returning `false` if it was not subscribed
+    pub fn unsubscribe(&mut self, id: Uuid) -> bool {
+        let len = self.subscribers.len();
+        self.subscribers.retain(|subscriber| subscriber.id != id);
+        self.subscribers.len() != len
+    }
+
+    pub fn contains(&self, id: Uuid) -> bool {
+        self.subscribers.iter().any(|subscriber| subscriber.id == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    /// pauses or resumes a stream. a paused stream misses packets, so once it is resumed it waits for
+    /// a keyframe like a new one (which the encoder should be asked for)
+    pub fn set_paused(&mut self, id: Uuid, paused: bool) {
+        for subscriber in self.subscribers.iter_mut().filter(|subscriber| subscriber.id == id) {
+            subscriber.paused = paused;
+            subscriber.synced = false;
+        }
+    }
+
+    /// if no stream wants frames, so the camera does not have to be read
+    pub fn all_paused(&self) -> bool {
+        self.subscribers.iter().all(|subscriber| subscriber.paused)
+    }
+
+    /// streams that are not paused
+    pub fn streaming(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.subscribers
+            .iter()
+            .filter(|subscriber| !subscriber.paused)
+            .map(|subscriber| subscriber.id)
+    }
+
+    /// tells every stream about something that happened to all of them
+    pub fn broadcast(&self, action: VideoStreamInfo) -> Vec<Message> {
+        self.subscribers
+            .iter()
+            .map(|subscriber| Message::VideoStreamInfo {
+                id: subscriber.id,
+                action: action.clone(),
+            })
+            .collect()
+    }
+
+    /// addresses a message from the encoder to every stream that should get it
+    pub fn fan_out(&mut self, message: &Message) -> Vec<Message> {
+        match message {
+            Message::VideoStreamInfo {
+                action: VideoStreamInfo::Keyframe,
+                ..
+            } => self
+                .subscribers
+                .iter_mut()
+                .filter(|subscriber| !subscriber.paused)
+                .map(|subscriber| {
+                    subscriber.synced = true;
+                    Message::VideoStreamInfo {
+                        id: subscriber.id,
+                        action: VideoStreamInfo::Keyframe,
+                    }
+                })
+                .collect(),
+            Message::VideoStreamInfo { action, .. } => self.broadcast(action.clone()),
+            Message::VideoStreamData { packet, .. } => self
+                .synced()
+                .map(|id| Message::VideoStreamData {
+                    id,
+                    packet: packet.clone(),
+                })
+                .collect(),
+            Message::VideoStreamFragment { data, last, .. } => self
+                .synced()
+                .map(|id| Message::VideoStreamFragment {
+                    id,
+                    data: data.clone(),
+                    last: *last,
+                })
+                .collect(),
+            other => vec![other.clone()],
+        }
+    }
+
+    /// streams that can decode the packets sent now
+    fn synced(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.subscribers
+            .iter()
+            .filter(|subscriber| subscriber.synced && !subscriber.paused)
+            .map(|subscriber| subscriber.id)
+    }
+}
+
+#[test]
+fn test_subscribers() {
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+    let worker = Uuid::new_v4();
+    let mut subscribers = Subscribers::new(first);
+    let data = Message::VideoStreamFragment {
+        id: worker,
+        data: vec![1, 2, 3],
+        last: true,
+    };
+    let keyframe = Message::VideoStreamInfo {
+        id: worker,
+        action: VideoStreamInfo::Keyframe,
+    };
+    let receivers = |messages: Vec<Message>| {
+        messages
+            .into_iter()
+            .map(|message| match message {
+                Message::VideoStreamFragment { id, .. } | Message::VideoStreamInfo { id, .. } => id,
+                other => panic!("unexpected message {:?}", other),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(receivers(subscribers.fan_out(&data)), [first]);
+    assert!(subscribers.subscribe(second));
+    assert!(!subscribers.subscribe(second));
+    // the second stream joined between keyframes, so it waits for the next one
+    assert_eq!(receivers(subscribers.fan_out(&data)), [first]);
+    assert_eq!(receivers(subscribers.fan_out(&keyframe)), [first, second]);
+    assert_eq!(receivers(subscribers.fan_out(&data)), [first, second]);
+
+    subscribers.set_paused(first, true);
+    assert!(!subscribers.all_paused());
+    assert_eq!(receivers(subscribers.fan_out(&data)), [second]);
+    assert_eq!(subscribers.streaming().collect::<Vec<_>>(), [second]);
+    subscribers.set_paused(first, false);
+    assert_eq!(receivers(subscribers.fan_out(&data)), [second]);
+    assert_eq!(receivers(subscribers.fan_out(&keyframe)), [first, second]);
+    assert_eq!(
+        receivers(subscribers.broadcast(VideoStreamInfo::Paused)),
+        [first, second]
+    );
+
+    assert!(subscribers.unsubscribe(first));
+    assert!(!subscribers.unsubscribe(first));
+    assert_eq!(subscribers.len(), 1);
+    subscribers.set_paused(second, true);
+    assert!(subscribers.all_paused());
+    assert!(subscribers.unsubscribe(second));
+    assert!(subscribers.is_empty());
+}
@@ -0,0 +1,138 @@
+//! Sampling the state of the computer the bot runs on, for [`aareocams_net::Telemetry`]
+//!
+//! everything is read from procfs and sysfs, so anything that is not available (such as the CPU temperature
+//! when not running on the pi) is left out
+
+use std::{fs, path::Path};
+
+use aareocams_net::MemoryUsage;
+
+const PROC_STAT: &str = "/proc/stat";
+const PROC_MEMINFO: &str = "/proc/meminfo";
+const PROC_UPTIME: &str = "/proc/uptime";
+const PROC_WIRELESS: &str = "/proc/net/wireless";
+/// the SoC temperature on the pi
+const CPU_THERMAL_ZONE: &str = "/sys/class/thermal/thermal_zone0/temp";
+
+/// reads the state of the computer, remembering what it needs to between samples
+#[derive(Debug, Default)]
+pub struct SystemSampler {
+    /// (busy, total) CPU time at the last sample, to tell how busy it was since then
+    last_cpu_times: Option<(u64, u64)>,
+}
+
+impl SystemSampler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// fraction of the time the CPU was busy since the last call, or `None` on the first call
+    pub fn cpu_load(&mut self) -> Option<f32> {
+        let times = parse_cpu_times(&read(PROC_STAT)?)?;
+        let load = self
+            .last_cpu_times
+            .and_then(|last| cpu_load_between(last, times));
+        self.last_cpu_times = Some(times);
+        load
+    }
+
+    /// degrees Celsius
+    pub fn cpu_temperature(&self) -> Option<f32> {
+        let millidegrees = read(CPU_THERMAL_ZONE)?.trim().parse::<f32>().ok()?;
+        Some(millidegrees / 1000.0)
+    }
+
+    pub fn memory(&self) -> Option<MemoryUsage> {
+        parse_meminfo(&read(PROC_MEMINFO)?)
+    }
+
+    /// signal level of the first wireless interface, in dBm
+    pub fn wifi_signal(&self) -> Option<f32> {
+        parse_wireless(&read(PROC_WIRELESS)?)
+    }
+
+    /// seconds since boot
+    pub fn uptime(&self) -> Option<u64> {
+        let uptime = read(PROC_UPTIME)?;
+        let seconds = uptime.split_whitespace().next()?.parse::<f64>().ok()?;
+        Some(seconds as u64)
+    }
+}
+
+fn read(path: impl AsRef<Path>) -> Option<String> {
+    fs::read_to_string(path).ok()
+}
+
+/// (busy, total) time of all CPUs, from the `cpu` line of /proc/stat
+fn parse_cpu_times(stat: &str) -> Option<(u64, u64)> {
+    let line = stat.lines().find(|line| line.starts_with("cpu "))?;
+    let times = line
+        .split_whitespace()
+        .skip(1)
+        .map(|time| time.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    // idle and iowait
+    let idle = times.get(3)? + times.get(4).unwrap_or(&0);
+    let total = times.iter().sum::<u64>();
+    Some((total - idle, total))
+}
+
+fn cpu_load_between((last_busy, last_total): (u64, u64), (busy, total): (u64, u64)) -> Option<f32> {
+    let total = total.checked_sub(last_total).filter(|total| *total > 0)?;
+    let busy = busy.saturating_sub(last_busy);
+    Some(busy as f32 / total as f32)
+}
+
+fn parse_meminfo(meminfo: &str) -> Option<MemoryUsage> {
+    let kilobytes = |key: &str| {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))?
+            .split_whitespace()
+            .next()?
+            .parse::<u64>()
+            .ok()
+            .map(|kilobytes| kilobytes * 1024)
+    };
+    let total = kilobytes("MemTotal")?;
+    let available = kilobytes("MemAvailable")?;
+    Some(MemoryUsage {
+        used: total.saturating_sub(available),
+        total,
+    })
+}
+
+/// the signal level of the first interface in /proc/net/wireless
+fn parse_wireless(wireless: &str) -> Option<f32> {
+    // two header lines, then `iface: status link level noise ...`
+    let line = wireless.lines().nth(2)?;
+    let level = line.split_whitespace().nth(3)?;
+    level.trim_end_matches('.').parse().ok()
+}
+
+#[test]
+fn test_parse_system_state() {
+    let stat = "cpu  100 0 100 700 100 0 0 0 0 0\ncpu0 50 0 50 350 50 0 0 0 0 0\nintr 1234\n";
+    assert_eq!(parse_cpu_times(stat), Some((200, 1000)));
+    assert_eq!(cpu_load_between((200, 1000), (300, 1200)), Some(0.5));
+    // no time passed
+    assert_eq!(cpu_load_between((200, 1000), (200, 1000)), None);
+
+    let meminfo =
+        "MemTotal:        3884328 kB\nMemFree:          136128 kB\nMemAvailable:    2836180 kB\n";
+    assert_eq!(
+        parse_meminfo(meminfo),
+        Some(MemoryUsage {
+            used: (3884328 - 2836180) * 1024,
+            total: 3884328 * 1024,
+        })
+    );
+
+    let wireless = "Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE\n face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22\nwlan0: 0000   54.  -56.  -256        0      0      0      0      0        0\n";
+    assert_eq!(parse_wireless(wireless), Some(-56.0));
+    // no wireless interfaces
+    assert_eq!(
+        parse_wireless(&wireless[..wireless.find("wlan0").unwrap()]),
+        None
+    );
+}
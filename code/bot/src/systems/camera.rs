@@ -1,4 +1,4 @@
-use aareocams_net::{Message, VideoStreamAction};
+use aareocams_net::{CameraTelemetry, Message, RecordingAction, VideoStreamAction};
 
 use dabus::{
     EventRegister,
@@ -6,14 +6,26 @@ use dabus::{
     event,
 };
 use flume::Receiver;
+use std::sync::{atomic::AtomicUsize, Arc};
 use uuid::Uuid;
 
-use crate::camera_server::CameraServer;
+use crate::{
+    camera_server::CameraServer,
+    config::{CameraRecoveryConf, RecordingConf},
+    hal::CameraBackend,
+};
 
 // decl_event!(pub, FEED_CTRL_MSG, CameraAction, FeedCtrlMessage, (Uuid, VideoStreamAction), (),                       Some(()), EventType::Send);
 // decl_event!(pub, GET_RECEIVER,  CameraAction, GetReceiver,     (),                        flume::Receiver<Message>, None,     EventType::Query);
 event!(FEED_CTRL_MSG, (Uuid, VideoStreamAction), ());
+event!(CLOSE_ALL, (), ());
 event!(GET_RECEIVER, (), Receiver<Message>);
+event!(GET_DOWNLOAD_RECEIVER, (), Receiver<Message>);
+event!(LIST_CAMERAS, (), ());
+event!(LIST_CAMERA_FORMATS, usize, ());
+event!(GET_LINK_BACKLOG, (), Arc<AtomicUsize>);
+event!(RECORDING_CTRL_MSG, RecordingAction, ());
+event!(GET_TELEMETRY, (), Vec<CameraTelemetry>);
 
 
 #[derive(Debug)]
@@ -22,9 +34,13 @@ pub struct CameraSystem {
 }
 
 impl CameraSystem {
-    pub fn new() -> Self {
+    pub fn new(
+        cameras: Arc<dyn CameraBackend>,
+        recording: RecordingConf,
+        camera_recovery: CameraRecoveryConf,
+    ) -> Self {
         Self {
-            server: CameraServer::new(),
+            server: CameraServer::new(cameras, recording, camera_recovery),
         }
     }
 
@@ -36,6 +52,38 @@ impl CameraSystem {
         self.server.feed_ctrl_msg(msg.0, msg.1);
     }
 
+    async fn close_all(
+        &mut self,
+        _: (),
+        _bus: BusInterface,
+    ) {
+        self.server.close_all();
+    }
+
+    async fn recording_ctrl(
+        &mut self,
+        action: RecordingAction,
+        _bus: BusInterface,
+    ) {
+        self.server.recording_ctrl_msg(action);
+    }
+
+    async fn list_cameras(
+        &mut self,
+        _: (),
+        _bus: BusInterface,
+    ) {
+        self.server.send_camera_list();
+    }
+
+    async fn list_camera_formats(
+        &mut self,
+        index: usize,
+        _bus: BusInterface,
+    ) {
+        self.server.send_camera_formats(index);
+    }
+
     async fn get_receiver(
         &mut self,
         _: (),
@@ -43,12 +91,43 @@ impl CameraSystem {
     ) -> Receiver<Message> {
         self.server.get_receiver()
     }
+
+    async fn get_download_receiver(
+        &mut self,
+        _: (),
+        _bus: BusInterface,
+    ) -> Receiver<Message> {
+        self.server.get_download_receiver()
+    }
+
+    async fn get_link_backlog(
+        &mut self,
+        _: (),
+        _bus: BusInterface,
+    ) -> Arc<AtomicUsize> {
+        self.server.link_backlog()
+    }
+
+    async fn get_telemetry(
+        &mut self,
+        _: (),
+        _bus: BusInterface,
+    ) -> Vec<CameraTelemetry> {
+        self.server.telemetry()
+    }
 }
 
 impl BusStop for CameraSystem {
     fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
         h
             .handler(FEED_CTRL_MSG, Self::ctrl_msg)
+            .handler(CLOSE_ALL, Self::close_all)
             .handler(GET_RECEIVER, Self::get_receiver)
+            .handler(GET_DOWNLOAD_RECEIVER, Self::get_download_receiver)
+            .handler(LIST_CAMERAS, Self::list_cameras)
+            .handler(LIST_CAMERA_FORMATS, Self::list_camera_formats)
+            .handler(GET_LINK_BACKLOG, Self::get_link_backlog)
+            .handler(RECORDING_CTRL_MSG, Self::recording_ctrl)
+            .handler(GET_TELEMETRY, Self::get_telemetry)
     }
 }
\ No newline at end of file
@@ -1,66 +1,345 @@
-// use std::{thread::{self, JoinHandle}, time::Duration};
-
-// use adafruit_motorkit::{init_pwm, Motor, stepper::{StepperMotor, StepDirection, StepStyle}};
-// use flume::Sender;
-// use tokio::sync::oneshot;
-
-
-// pub const DRIVE_M0: Motor = Motor::Stepper1;
-
-// #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-// pub enum MotorAction {
-//     Fwd,
-//     Back,
-//     Stop,
-// }
-
-// pub struct SuperHackyStepperDriver {
-//     busyloop_thread: JoinHandle<()>,
-//     kill_channel: oneshot::Sender<()>,
-//     action_channel: Sender<MotorAction>,
-// }
-
-// impl SuperHackyStepperDriver {
-//     pub fn new() -> Self {
-//         let (kill_channel, mut kill_sig) = oneshot::channel();
-//         let (action_channel, action_recv) = flume::unbounded();
-//         let busyloop_thread = thread::spawn(move || {
-//             let mut driver = init_pwm(None).unwrap();
-//             let mut drive_m = StepperMotor::try_new(&mut driver, DRIVE_M0, None).unwrap();
-//             let mut drive_state = MotorAction::Stop;
-//             while let Err(oneshot::error::TryRecvError::Empty) = kill_sig.try_recv() {
-//                 if let Ok(action) = action_recv.try_recv() {
-//                     if action == MotorAction::Stop {
-//                         drive_m.stop(&mut driver).unwrap();
-//                     }
-//                     drive_state = action;
-//                 }
-//                 match drive_state {
-//                     MotorAction::Stop => {
-//                         std::thread::sleep(Duration::from_millis(50));
-//                     }
-//                     MotorAction::Fwd => {
-//                         drive_m.step_once(&mut driver, StepDirection::Forward, StepStyle::Single).unwrap();
-//                     }
-//                     MotorAction::Back => {
-//                         drive_m.step_once(&mut driver, StepDirection::Backward, StepStyle::Single).unwrap();
-//                     }
-//                 }
-//             }
-//         });
-//         Self {
-//             busyloop_thread,
-//             kill_channel,
-//             action_channel,
-//         }
-//     }
-
-//     pub fn set_dir(&mut self, dir: MotorAction) {
-//         self.action_channel.send(dir).unwrap();
-//     }
-
-//     pub fn close(self) {
-//         self.kill_channel.send(()).unwrap();
-//         self.busyloop_thread.join().unwrap();
-//     }
-// }
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use aareocams_net::{
+    DriveAction, DriveInfo, DrivetrainTelemetry, LimitSource, Message, TravelLimit, Waypoint,
+    WaypointAction, WaypointTarget,
+};
+use dabus::{event, BusInterface, BusStop, EventRegister};
+use flume::{Receiver, Sender};
+use parking_lot::Mutex;
+
+use crate::{
+    config::{self, Conf, CriticalPowerAction, DrivetrainConf},
+    control::{ControlMode, Drivetrain},
+    hal::{MotorController, QIEncoderInterface},
+    limits::TravelLimits,
+};
+
+event!(DRIVE_CTRL_MSG, DriveAction, ());
+// drives as the power system asks when the battery is critically low, stopping if the waypoint it asks for is gone
+event!(POWER_ACTION, DriveAction, ());
+event!(WAYPOINT_CTRL_MSG, WaypointAction, ());
+event!(GET_RECEIVER, (), Receiver<Message>);
+event!(GET_POSITION, (), f64);
+event!(GET_STATUS, (), DrivetrainTelemetry);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DriveCommand {
+    Mode(ControlMode),
+    GoTo { position: f64, speed: Option<f64> },
+    SetPosition(f64),
+    ArmHoming,
+    Shutdown,
+}
+
+#[derive(Debug)]
+pub struct DrivetrainSystem {
+    worker: Option<JoinHandle<()>>,
+    commands: Sender<DriveCommand>,
+    messages_send: Sender<Message>,
+    message_queue: Receiver<Message>,
+    manual_speed: f64,
+    /// kept up to date by the worker thread
+    status: Arc<Mutex<DrivetrainTelemetry>>,
+    waypoints: Vec<Waypoint>,
+    /// the waypoint the bot returns home to when the battery is critically low, which can not be removed
+    home: Option<String>,
+    /// where the config file is, so waypoints can be saved
+    config_path: PathBuf,
+}
+
+impl DrivetrainSystem {
+    pub fn new<M, E>(
+        motor: M,
+        encoder: E,
+        limits: TravelLimits,
+        cfg: &Conf,
+        config_path: PathBuf,
+    ) -> Self
+    where
+        M: MotorController + Send + 'static,
+        E: QIEncoderInterface + Send + 'static,
+    {
+        let (messages_send, message_queue) = flume::unbounded();
+        let (commands, command_queue) = flume::unbounded();
+        let drivetrain = Drivetrain::new(motor, encoder, &cfg.drivetrain, limits);
+        let drivetrain_cfg = cfg.drivetrain.clone();
+        let worker_messages = messages_send.clone();
+        let status = Arc::new(Mutex::new(DrivetrainTelemetry {
+            position: drivetrain.position(),
+            velocity: 0.0,
+            target: None,
+            tracking_error: 0.0,
+            homing: drivetrain.homing(),
+        }));
+        let worker_status = status.clone();
+        let worker = thread::spawn(move || {
+            run_drivetrain(
+                drivetrain,
+                drivetrain_cfg,
+                command_queue,
+                worker_messages,
+                worker_status,
+            )
+        });
+
+        Self {
+            worker: Some(worker),
+            commands,
+            messages_send,
+            message_queue,
+            manual_speed: cfg.drivetrain.manual_speed,
+            status,
+            waypoints: cfg.waypoints.clone(),
+            home: match &cfg.power.critical_action {
+                CriticalPowerAction::GoTo(home) => Some(home.clone()),
+                _ => None,
+            },
+            config_path,
+        }
+    }
+
+    async fn drive_ctrl(&mut self, action: DriveAction, _bus: BusInterface) {
+        let command = match action {
+            DriveAction::Fwd => DriveCommand::Mode(ControlMode::Velocity(self.manual_speed)),
+            DriveAction::Rev => DriveCommand::Mode(ControlMode::Velocity(-self.manual_speed)),
+            DriveAction::Stop => DriveCommand::Mode(ControlMode::Velocity(0.0)),
+            DriveAction::GoTo { target, speed } => {
+                let position = match target {
+                    WaypointTarget::Position(position) => position,
+                    WaypointTarget::Named(name) => match self.waypoint(&name) {
+                        Some(waypoint) => waypoint.position,
+                        None => {
+                            self.messages_send
+                                .send(Message::DriveInfo(DriveInfo::GoToError {
+                                    message: format!("No waypoint named {:?}", name),
+                                }))
+                                .unwrap();
+                            return;
+                        }
+                    },
+                };
+                DriveCommand::GoTo { position, speed }
+            }
+            DriveAction::SetPosition(position) => DriveCommand::SetPosition(position),
+            DriveAction::ArmHoming => DriveCommand::ArmHoming,
+        };
+        self.commands.send(command).unwrap();
+    }
+
+    async fn power_action(&mut self, action: DriveAction, bus: BusInterface) {
+        if let DriveAction::GoTo {
+            target: WaypointTarget::Named(name),
+            ..
+        } = &action
+        {
+            if self.waypoint(name).is_none() {
+                // the waypoint was removed after the config was checked, and the bot must not keep going
+                error!(
+                    "The battery is critically low, but there is no waypoint named {:?} to return home to, stopping instead",
+                    name
+                );
+                return self.drive_ctrl(DriveAction::Stop, bus).await;
+            }
+        }
+        self.drive_ctrl(action, bus).await
+    }
+
+    fn waypoint(&self, name: &str) -> Option<&Waypoint> {
+        self.waypoints.iter().find(|waypoint| waypoint.name == name)
+    }
+
+    async fn waypoint_ctrl(&mut self, action: WaypointAction, _bus: BusInterface) {
+        match action {
+            WaypointAction::List => {}
+            WaypointAction::Set(new) => {
+                self.waypoints.retain(|waypoint| waypoint.name != new.name);
+                self.waypoints.push(new);
+                self.waypoints
+                    .sort_by(|a, b| a.position.total_cmp(&b.position));
+                self.save_waypoints();
+            }
+            WaypointAction::Remove { name } if self.home.as_ref() == Some(&name) => {
+                // the config would not be valid anymore
+                self.send_waypoint_error(format!(
+                    "{:?} is where the bot returns home to when the battery is critically low, \
+                     change power.critical_action in the config to remove it",
+                    name
+                ));
+            }
+            WaypointAction::Remove { name } => {
+                self.waypoints.retain(|waypoint| waypoint.name != name);
+                self.save_waypoints();
+            }
+        }
+        self.messages_send
+            .send(Message::Waypoints(self.waypoints.clone()))
+            .unwrap();
+    }
+
+    async fn get_receiver(&mut self, _: (), _bus: BusInterface) -> Receiver<Message> {
+        self.message_queue.clone()
+    }
+
+    /// meters along the cable
+    async fn get_position(&mut self, _: (), _bus: BusInterface) -> f64 {
+        self.status.lock().position
+    }
+
+    async fn get_status(&mut self, _: (), _bus: BusInterface) -> DrivetrainTelemetry {
+        *self.status.lock()
+    }
+
+    fn save_waypoints(&self) {
+        if let Err(save_error) = config::save_waypoints(&self.config_path, &self.waypoints) {
+            error!("Failed to save waypoints:\n{:#?}", save_error);
+            self.send_waypoint_error(format!("failed to save waypoints: {:#}", save_error));
+        }
+    }
+
+    fn send_waypoint_error(&self, message: String) {
+        self.messages_send
+            .send(Message::DriveInfo(DriveInfo::WaypointError { message }))
+            .unwrap();
+    }
+}
+
+impl BusStop for DrivetrainSystem {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(DRIVE_CTRL_MSG, Self::drive_ctrl)
+            .handler(POWER_ACTION, Self::power_action)
+            .handler(WAYPOINT_CTRL_MSG, Self::waypoint_ctrl)
+            .handler(GET_RECEIVER, Self::get_receiver)
+            .handler(GET_POSITION, Self::get_position)
+            .handler(GET_STATUS, Self::get_status)
+    }
+}
+
+impl Drop for DrivetrainSystem {
+    fn drop(&mut self) {
+        let _ = self.commands.send(DriveCommand::Shutdown);
+        if let Some(worker) = self.worker.take() {
+            if let Err(thread_err) = worker.join() {
+                error!(
+                    "drivetrain worker thread did not exit gracefully:\n{:#?}",
+                    thread_err
+                );
+            }
+        }
+    }
+}
+
+/// runs the drivetrain until a shutdown command is received.
+///
+/// this wakes up every `control_period` to run the control loop. the encoder records its edges as they happen, so
+/// none are missed in between
+fn run_drivetrain<M: MotorController, E: QIEncoderInterface>(
+    mut drivetrain: Drivetrain<M, E>,
+    cfg: DrivetrainConf,
+    commands: Receiver<DriveCommand>,
+    messages: Sender<Message>,
+    shared_status: Arc<Mutex<DrivetrainTelemetry>>,
+) {
+    let mut going_to = None;
+    let mut last_status = Instant::now();
+    loop {
+        for command in commands.try_iter() {
+            let result = match command {
+                DriveCommand::Mode(mode) => {
+                    going_to = None;
+                    drivetrain.set_mode(mode)
+                }
+                DriveCommand::GoTo { position, speed } => {
+                    let result = drivetrain.go_to(position, speed);
+                    if result.is_ok() {
+                        going_to = Some(position);
+                    }
+                    result
+                }
+                DriveCommand::SetPosition(position) => {
+                    drivetrain.set_position(position);
+                    Ok(())
+                }
+                DriveCommand::ArmHoming => {
+                    drivetrain.arm_homing();
+                    Ok(())
+                }
+                DriveCommand::Shutdown => {
+                    let _ = drivetrain.set_mode(ControlMode::Stopped);
+                    return;
+                }
+            };
+            if let Err(limit) = result {
+                warn!(
+                    "Refused drive command {:?}, it would pass limit {:?}",
+                    command, limit
+                );
+                messages
+                    .send(Message::DriveInfo(DriveInfo::MotionRefused { limit }))
+                    .unwrap();
+            }
+        }
+
+        let now = Instant::now();
+        drivetrain.tick(now);
+
+        for event in drivetrain.take_limit_events() {
+            send_limit_event(&messages, event.limit, event.source);
+        }
+        if going_to.is_some() && !matches!(drivetrain.status().mode, ControlMode::Position(..)) {
+            // stopped by a travel limit
+            going_to = None;
+        }
+
+        if going_to.is_some() && drivetrain.at_target(cfg.position_tolerance) {
+            going_to = None;
+            messages
+                .send(Message::DriveInfo(DriveInfo::Arrived {
+                    position: drivetrain.position(),
+                }))
+                .unwrap();
+        }
+
+        let status = drivetrain.status();
+        *shared_status.lock() = DrivetrainTelemetry {
+            position: status.position,
+            velocity: status.velocity,
+            target: going_to,
+            tracking_error: status.tracking_error,
+            homing: drivetrain.homing(),
+        };
+
+        if now.saturating_duration_since(last_status) >= cfg.status_period {
+            last_status = now;
+            messages
+                .send(Message::DriveInfo(DriveInfo::Status {
+                    position: status.position,
+                    velocity: status.velocity,
+                    target: going_to,
+                    tracking_error: status.tracking_error,
+                }))
+                .unwrap();
+        }
+
+        thread::sleep((now + cfg.control_period).saturating_duration_since(Instant::now()));
+    }
+}
+
+fn send_limit_event(messages: &Sender<Message>, limit: TravelLimit, source: Option<LimitSource>) {
+    let info = match source {
+        Some(source) => {
+            info!("Reached travel limit {:?} ({:?})", limit, source);
+            DriveInfo::LimitReached { limit, source }
+        }
+        None => {
+            info!("Cleared travel limit {:?}", limit);
+            DriveInfo::LimitCleared { limit }
+        }
+    };
+    messages.send(Message::DriveInfo(info)).unwrap();
+}
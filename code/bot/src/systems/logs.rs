@@ -0,0 +1,39 @@
+use aareocams_net::{LogAction, Message};
+use dabus::{event, BusInterface, BusStop, EventRegister};
+use flume::Receiver;
+
+use crate::log_forwarding::LogForwarding;
+
+event!(LOG_CTRL_MSG, LogAction, ());
+event!(GET_RECEIVER, (), Receiver<Message>);
+
+#[derive(Debug)]
+pub struct LogSystem {
+    forwarding: LogForwarding,
+}
+
+impl LogSystem {
+    pub fn new(forwarding: LogForwarding) -> Self {
+        Self { forwarding }
+    }
+
+    async fn log_ctrl(&mut self, action: LogAction, _bus: BusInterface) {
+        match action {
+            LogAction::SetLevel(level) => {
+                info!("Forwarding {} logs to the dashboard", level);
+                self.forwarding.set_level(level);
+            }
+        }
+    }
+
+    async fn get_receiver(&mut self, _: (), _bus: BusInterface) -> Receiver<Message> {
+        self.forwarding.receiver()
+    }
+}
+
+impl BusStop for LogSystem {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(LOG_CTRL_MSG, Self::log_ctrl)
+            .handler(GET_RECEIVER, Self::get_receiver)
+    }
+}
@@ -1,2 +1,5 @@
 pub mod camera;
 pub mod drivetrain;
+pub mod logs;
+pub mod power;
+pub mod telemetry;
@@ -0,0 +1,165 @@
+use std::time::Instant;
+
+use aareocams_net::{DriveAction, Message, PowerInfo, PowerLevel, PowerStatus, WaypointTarget};
+use dabus::{event, BusInterface, BusStop, EventRegister};
+use flume::{Receiver, Sender};
+
+use crate::{
+    battery::BatteryMonitor,
+    config::{CriticalPowerAction, PowerConf},
+    hal::PowerSensor,
+};
+
+event!(GET_RECEIVER, (), Receiver<Message>);
+// reads the power sensor, returning what the drivetrain should do if the battery just became critically low
+event!(SAMPLE, (), Option<DriveAction>);
+// `None` if there is no power sensor, or it was not read yet
+event!(GET_STATUS, (), Option<PowerStatus>);
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct PowerSystem {
+    /// `None` if the bot has no power sensor, then this does nothing
+    #[derivative(Debug = "ignore")]
+    sensor: Option<Box<dyn PowerSensor + Send>>,
+    monitor: BatteryMonitor,
+    critical_action: CriticalPowerAction,
+    /// if the last read failed, so that a broken sensor is only reported once
+    sensor_failed: bool,
+    messages_send: Sender<Message>,
+    message_queue: Receiver<Message>,
+}
+
+impl PowerSystem {
+    pub fn new(sensor: Option<Box<dyn PowerSensor + Send>>, cfg: PowerConf) -> Self {
+        let (messages_send, message_queue) = flume::unbounded();
+        if sensor.is_none() {
+            warn!("There is no power sensor, the battery will not be monitored");
+        }
+        Self {
+            sensor,
+            critical_action: cfg.critical_action.clone(),
+            monitor: BatteryMonitor::new(cfg),
+            sensor_failed: false,
+            messages_send,
+            message_queue,
+        }
+    }
+
+    async fn get_receiver(&mut self, _: (), _bus: BusInterface) -> Receiver<Message> {
+        self.message_queue.clone()
+    }
+
+    async fn sample(&mut self, _: (), _bus: BusInterface) -> Option<DriveAction> {
+        self.read_sensor(Instant::now())
+    }
+
+    /// reads the sensor at `now`, returning what the drivetrain should do if the battery just became critically low
+    fn read_sensor(&mut self, now: Instant) -> Option<DriveAction> {
+        let reading = match self.sensor.as_mut()?.read() {
+            Ok(reading) => {
+                self.sensor_failed = false;
+                reading
+            }
+            Err(read_error) => {
+                if !self.sensor_failed {
+                    error!("Failed to read the power sensor:\n{:#?}", read_error);
+                    self.send(PowerInfo::SensorError {
+                        message: format!("{:#}", read_error),
+                    });
+                }
+                self.sensor_failed = true;
+                return None;
+            }
+        };
+        let level = self.monitor.update(reading, now)?;
+        let status = self.monitor.status()?;
+        match level {
+            PowerLevel::Normal => info!("Battery is back to normal: {:?}", status),
+            PowerLevel::Low => warn!("Battery is low: {:?}", status),
+            PowerLevel::Critical => error!("Battery is critically low: {:?}", status),
+        }
+        self.send(PowerInfo::LevelChanged(status));
+        if level != PowerLevel::Critical {
+            return None;
+        }
+
+        let action = match &self.critical_action {
+            CriticalPowerAction::Nothing => return None,
+            CriticalPowerAction::Stop => DriveAction::Stop,
+            CriticalPowerAction::GoTo(home) => DriveAction::GoTo {
+                target: WaypointTarget::Named(home.clone()),
+                speed: None,
+            },
+        };
+        warn!("Battery is critically low, {:?}", action);
+        self.send(PowerInfo::CriticalAction(action.clone()));
+        Some(action)
+    }
+
+    async fn get_status(&mut self, _: (), _bus: BusInterface) -> Option<PowerStatus> {
+        self.monitor.status()
+    }
+
+    fn send(&self, info: PowerInfo) {
+        self.messages_send.send(Message::PowerInfo(info)).unwrap();
+    }
+}
+
+impl BusStop for PowerSystem {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(GET_RECEIVER, Self::get_receiver)
+            .handler(SAMPLE, Self::sample)
+            .handler(GET_STATUS, Self::get_status)
+    }
+}
+
+#[test]
+fn test_critical_action() {
+    use crate::hal::sim::{SimulatedBattery, SimulatedEncoder};
+    use std::time::Duration;
+
+    let start = Instant::now();
+    // a power system reading a simulated battery that was just emptied
+    let empty = |critical_action| {
+        let battery = SimulatedBattery::new(SimulatedEncoder::new(100).handle(), 1000.0);
+        battery.handle().set_charge(0.0);
+        PowerSystem::new(
+            Some(Box::new(battery)),
+            PowerConf {
+                // the voltages of the simulated battery
+                full_voltage: 12.6,
+                empty_voltage: 9.9,
+                internal_resistance: 0.1,
+                low_charge: 0.25,
+                critical_charge: 0.1,
+                critical_action,
+                period: Duration::from_secs(1),
+            },
+        )
+    };
+
+    let mut power = empty(CriticalPowerAction::Stop);
+    assert!(matches!(power.read_sensor(start), Some(DriveAction::Stop)));
+    // it only acts when the battery becomes critically low, not on every reading after that
+    assert!(power.read_sensor(start + Duration::from_secs(1)).is_none());
+
+    let mut power = empty(CriticalPowerAction::GoTo("home".to_string()));
+    assert!(matches!(
+        power.read_sensor(start),
+        Some(DriveAction::GoTo {
+            target: WaypointTarget::Named(home),
+            speed: None,
+        }) if home == "home"
+    ));
+    assert!(matches!(
+        power.message_queue.try_iter().last(),
+        Some(Message::PowerInfo(PowerInfo::CriticalAction(
+            DriveAction::GoTo { .. }
+        )))
+    ));
+
+    let mut power = empty(CriticalPowerAction::Nothing);
+    assert!(power.read_sensor(start).is_none());
+    assert_eq!(power.monitor.level(), PowerLevel::Critical);
+}
@@ -0,0 +1,109 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aareocams_net::{
+    CameraTelemetry, DrivetrainTelemetry, Message, PowerStatus, Telemetry, TelemetryAction,
+    TelemetryFields,
+};
+use dabus::{event, BusInterface, BusStop, EventRegister};
+use flume::{Receiver, Sender};
+
+use crate::system_stats::SystemSampler;
+
+event!(TELEMETRY_CTRL_MSG, TelemetryAction, ());
+event!(GET_RECEIVER, (), Receiver<Message>);
+// if the dashboard subscribed to telemetry, and `PUBLISH` should be fired
+event!(WANTED, (), bool);
+// samples the state of the bot and sends it to the dashboard, with the state of the other systems
+event!(PUBLISH, SystemsState, ());
+
+/// the state of the other systems, which the telemetry system can not ask them for itself
+#[derive(Debug, Clone)]
+pub struct SystemsState {
+    pub drivetrain: DrivetrainTelemetry,
+    pub cameras: Vec<CameraTelemetry>,
+    /// `None` if there is no power sensor
+    pub power: Option<PowerStatus>,
+}
+
+#[derive(Debug)]
+pub struct TelemetrySystem {
+    sampler: SystemSampler,
+    /// what the dashboard asked for, `None` if it did not subscribe
+    fields: Option<TelemetryFields>,
+    messages_send: Sender<Message>,
+    message_queue: Receiver<Message>,
+}
+
+impl TelemetrySystem {
+    pub fn new() -> Self {
+        let (messages_send, message_queue) = flume::unbounded();
+        Self {
+            sampler: SystemSampler::new(),
+            fields: None,
+            messages_send,
+            message_queue,
+        }
+    }
+
+    async fn telemetry_ctrl(&mut self, action: TelemetryAction, _bus: BusInterface) {
+        self.fields = match action {
+            TelemetryAction::Subscribe(fields) => {
+                info!("Sending telemetry with {:?}", fields);
+                Some(fields)
+            }
+            TelemetryAction::Unsubscribe => {
+                info!("Stopped sending telemetry");
+                None
+            }
+        };
+    }
+
+    async fn get_receiver(&mut self, _: (), _bus: BusInterface) -> Receiver<Message> {
+        self.message_queue.clone()
+    }
+
+    async fn wanted(&mut self, _: (), _bus: BusInterface) -> bool {
+        self.fields.is_some()
+    }
+
+    async fn publish(&mut self, systems: SystemsState, _bus: BusInterface) {
+        let fields = match self.fields {
+            Some(fields) => fields,
+            None => return,
+        };
+        // sampled even when it is not sent, so that it is the load since the last sample once it is asked for
+        let cpu_load = self.sampler.cpu_load();
+        let telemetry = Telemetry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            cpu_load: cpu_load.filter(|_| fields.cpu_load),
+            cpu_temperature: fields
+                .cpu_temperature
+                .then(|| self.sampler.cpu_temperature())
+                .flatten(),
+            memory: fields.memory.then(|| self.sampler.memory()).flatten(),
+            wifi_signal: fields
+                .wifi_signal
+                .then(|| self.sampler.wifi_signal())
+                .flatten(),
+            uptime: fields.uptime.then(|| self.sampler.uptime()).flatten(),
+            drivetrain: fields.drivetrain.then(|| systems.drivetrain),
+            cameras: fields.cameras.then(|| systems.cameras),
+            power: systems.power.filter(|_| fields.power),
+        };
+        self.messages_send
+            .send(Message::Telemetry(telemetry))
+            .unwrap();
+    }
+}
+
+impl BusStop for TelemetrySystem {
+    fn registered_handlers(h: EventRegister<Self>) -> EventRegister<Self> {
+        h.handler(TELEMETRY_CTRL_MSG, Self::telemetry_ctrl)
+            .handler(GET_RECEIVER, Self::get_receiver)
+            .handler(WANTED, Self::wanted)
+            .handler(PUBLISH, Self::publish)
+    }
+}
@@ -0,0 +1,468 @@
+//! Configuration of the bot (`config/bot.yml`)
+//!
+//! the config file is deserialized straight into [`Conf`]. every section and most keys have defaults, unknown keys are
+//! errors, and values with units (durations, rates, sizes and percentages) are parsed with [`aareocams_core::units`]
+
+use aareocams_core::{units, yaml};
+use aareocams_net::{LogLevel, Waypoint};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    net::SocketAddrV4,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+pub fn load_config<P: AsRef<Path>>(path: P) -> Result<Conf> {
+    let path = path.as_ref();
+    let yaml = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the config file {:?}", path))?;
+    parse_config(&yaml).with_context(|| format!("Invalid config file {:?}", path))
+}
+
+/// parses and validates the contents of a config file. errors start with the key they are about, like
+/// `drivetrain.control_rate: must be more than zero`
+pub fn parse_config(yaml: &str) -> Result<Conf> {
+    let cfg: Conf = yaml::parse(yaml)?;
+    cfg.validate()?;
+    Ok(cfg)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Conf {
+    pub network: NetworkConf,
+    #[serde(default)]
+    pub cameras: CamerasConf,
+    #[serde(default)]
+    pub drivetrain: DrivetrainConf,
+    #[serde(default)]
+    pub safety: SafetyConf,
+    #[serde(default)]
+    pub hal: HalConf,
+    #[serde(default)]
+    pub power: PowerConf,
+    #[serde(default)]
+    pub telemetry: TelemetryConf,
+    #[serde(default)]
+    pub logging: LoggingConf,
+    #[serde(default)]
+    pub waypoints: Vec<Waypoint>,
+}
+
+impl Conf {
+    /// checks what can not be checked while parsing, because it involves more than one key
+    fn validate(&self) -> Result<()> {
+        if let (Some(min), Some(max)) = (self.safety.min_position, self.safety.max_position) {
+            if min >= max {
+                bail!("safety.min_position: must be less than safety.max_position");
+            }
+        }
+        // these are divided by, or are the rates the drivetrain moves at
+        let drivetrain = &self.drivetrain;
+        for (value, key) in [
+            (drivetrain.wheel_diameter, "wheel_diameter"),
+            (drivetrain.max_velocity, "max_velocity"),
+            (drivetrain.max_acceleration, "max_acceleration"),
+            (drivetrain.manual_speed, "manual_speed"),
+            (drivetrain.position_tolerance, "position_tolerance"),
+        ] {
+            if !(value.is_finite() && value > 0.0) {
+                bail!("drivetrain.{}: must be more than 0", key);
+            }
+        }
+        if self.hal.encoder_ppr == 0 {
+            bail!("hal.encoder_ppr: must be at least 1");
+        }
+        if self.hal.backend == HalBackend::Hardware && self.hal.pins.is_none() {
+            bail!("hal.pins: must be set to use the hardware backend");
+        }
+        let recording = &self.cameras.recording;
+        if recording.max_total_size != 0 && recording.max_total_size < recording.max_file_size {
+            bail!(
+                "cameras.recording.max_total_size: must be at least cameras.recording.max_file_size, \
+                 or 0 to keep every recording"
+            );
+        }
+        if self.cameras.recovery.lost_after_errors == 0 {
+            bail!("cameras.recovery.lost_after_errors: must be at least 1");
+        }
+        if self.power.full_voltage <= self.power.empty_voltage {
+            bail!("power.full_voltage: must be more than power.empty_voltage");
+        }
+        if self.power.critical_charge > self.power.low_charge {
+            bail!("power.critical_charge: must not be more than power.low_charge");
+        }
+        if let CriticalPowerAction::GoTo(home) = &self.power.critical_action {
+            if !self.waypoints.iter().any(|waypoint| waypoint.name == *home) {
+                bail!(
+                    "power.critical_action.return_home: there is no waypoint named {:?} in waypoints",
+                    home
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConf {
+    /// address to host the bot on
+    pub addr: SocketAddrV4,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CamerasConf {
+    pub recording: RecordingConf,
+    pub recovery: CameraRecoveryConf,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DrivetrainConf {
+    /// diameter of the drive wheel, in meters
+    pub wheel_diameter: f64,
+    /// m/s
+    pub max_velocity: f64,
+    /// m/s^2
+    pub max_acceleration: f64,
+    #[serde(rename = "control_rate", with = "units::rate")]
+    pub control_period: Duration,
+    #[serde(rename = "velocity_pid")]
+    pub velocity_gains: PidGains,
+    #[serde(rename = "position_pid")]
+    pub position_gains: PidGains,
+    /// speed used when driving manually, in m/s
+    pub manual_speed: f64,
+    /// how close to a target position counts as arriving, in meters
+    pub position_tolerance: f64,
+    /// how often the position is sent to the dashboard
+    #[serde(rename = "status_rate", with = "units::rate")]
+    pub status_period: Duration,
+}
+
+impl Default for DrivetrainConf {
+    fn default() -> Self {
+        Self {
+            wheel_diameter: 0.05,
+            max_velocity: 0.5,
+            max_acceleration: 0.5,
+            control_period: Duration::from_millis(10),
+            velocity_gains: PidGains {
+                kp: 40.0,
+                ki: 100.0,
+                kd: 0.0,
+                kf: 125.0,
+            },
+            position_gains: PidGains {
+                kp: 5.0,
+                ki: 0.0,
+                kd: 0.0,
+                kf: 0.0,
+            },
+            manual_speed: 0.2,
+            position_tolerance: 0.01,
+            status_period: Duration::from_millis(100),
+        }
+    }
+}
+
+/// in config files, any gain that is not given is zero
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PidGains {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    /// feed forward, multiplied by the setpoint
+    pub kf: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SafetyConf {
+    /// soft limit, in meters along the cable
+    pub min_position: Option<f64>,
+    /// soft limit, in meters along the cable
+    pub max_position: Option<f64>,
+    /// BCM GPIO pin of the limit switch at the minimum end of the cable
+    pub limit_switch_min_pin: Option<u8>,
+    /// BCM GPIO pin of the limit switch at the maximum end of the cable
+    pub limit_switch_max_pin: Option<u8>,
+    /// if the limit switches read low when pressed
+    pub limit_switch_active_low: bool,
+}
+
+impl Default for SafetyConf {
+    fn default() -> Self {
+        Self {
+            min_position: None,
+            max_position: None,
+            limit_switch_min_pin: None,
+            limit_switch_max_pin: None,
+            limit_switch_active_low: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HalBackend {
+    /// the motor, encoder and limit switches on the raspberry pi GPIO, and V4L cameras
+    Hardware,
+    /// simulated hardware: a motor and encoder, limit switches, a battery and cameras showing a test pattern
+    Simulated,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HalConf {
+    pub backend: HalBackend,
+    /// pulses per revolution of the drive encoder
+    pub encoder_ppr: usize,
+    /// only needed for the hardware backend
+    pub pins: Option<HardwarePins>,
+    /// `None` if the bot has no power sensor. the simulated backend always has a simulated battery
+    pub power_sensor: Option<PowerSensorConf>,
+}
+
+impl Default for HalConf {
+    fn default() -> Self {
+        Self {
+            // the simulation, so that nothing moves unless it is asked to
+            backend: HalBackend::Simulated,
+            encoder_ppr: 100,
+            pins: None,
+            power_sensor: None,
+        }
+    }
+}
+
+/// BCM GPIO pin numbers of the drivetrain hardware
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HardwarePins {
+    pub motor_pwm: u8,
+    pub motor_dir: u8,
+    pub encoder_a: u8,
+    pub encoder_b: u8,
+    pub encoder_index: u8,
+}
+
+/// an INA219 current and voltage sensor on the battery
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PowerSensorConf {
+    /// I2C bus number (`/dev/i2c-N`)
+    pub bus: u8,
+    pub address: u16,
+    /// resistance of the current shunt, in ohms
+    pub shunt_resistance: f64,
+}
+
+/// where and how camera streams are recorded on the bot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecordingConf {
+    pub dir: PathBuf,
+    /// a new file is started once a recording is this large, in bytes
+    #[serde(with = "units::size")]
+    pub max_file_size: u64,
+    /// a new file is started once a recording is this long
+    #[serde(with = "units::duration")]
+    pub max_file_duration: Duration,
+    /// the oldest recordings are deleted when a new file is started, so that they stay below this many bytes with
+    /// the new file at its largest. 0 keeps every recording
+    #[serde(with = "units::size")]
+    pub max_total_size: u64,
+    /// the oldest recordings are deleted when a new file is started, so that there are at most this many files.
+    /// 0 keeps every recording
+    pub max_files: usize,
+}
+
+impl Default for RecordingConf {
+    fn default() -> Self {
+        Self {
+            dir: "recordings".into(),
+            max_file_size: 100 * 1024 * 1024,
+            max_file_duration: Duration::from_secs(600),
+            max_total_size: 0,
+            max_files: 0,
+        }
+    }
+}
+
+/// how camera streams recover from cameras that stop working, such as when they are unplugged
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraRecoveryConf {
+    /// the camera is considered lost after this many read errors in a row
+    pub lost_after_errors: u32,
+    /// the stream is closed after this many failed attempts to reopen a lost camera
+    pub max_reopen_attempts: u32,
+    /// time before the first attempt to reopen a lost camera, which doubles after each failed attempt
+    #[serde(with = "units::duration")]
+    pub reopen_delay: Duration,
+    #[serde(with = "units::duration")]
+    pub max_reopen_delay: Duration,
+}
+
+impl Default for CameraRecoveryConf {
+    fn default() -> Self {
+        Self {
+            lost_after_errors: 3,
+            max_reopen_attempts: 10,
+            reopen_delay: Duration::from_millis(500),
+            max_reopen_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+/// how the state of the bot is sent to dashboards that subscribe to it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConf {
+    /// how often telemetry is sent
+    #[serde(rename = "rate", with = "units::rate")]
+    pub period: Duration,
+}
+
+impl Default for TelemetryConf {
+    fn default() -> Self {
+        Self {
+            period: Duration::from_secs(1),
+        }
+    }
+}
+
+/// how the battery is monitored
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PowerConf {
+    /// battery voltage when full, in volts
+    pub full_voltage: f64,
+    /// battery voltage when empty, in volts
+    pub empty_voltage: f64,
+    /// in ohms, to correct for the voltage dropping while the motor draws current
+    pub internal_resistance: f64,
+    /// state of charge (0 to 1) below which the battery is low
+    #[serde(with = "units::percent")]
+    pub low_charge: f64,
+    /// state of charge (0 to 1) below which the bot takes `critical_action`
+    #[serde(with = "units::percent")]
+    pub critical_charge: f64,
+    pub critical_action: CriticalPowerAction,
+    /// how often the battery is read
+    #[serde(rename = "rate", with = "units::rate")]
+    pub period: Duration,
+}
+
+impl Default for PowerConf {
+    fn default() -> Self {
+        Self {
+            full_voltage: 12.6,
+            empty_voltage: 9.9,
+            internal_resistance: 0.0,
+            low_charge: 0.25,
+            critical_charge: 0.1,
+            critical_action: CriticalPowerAction::Stop,
+            period: Duration::from_secs(1),
+        }
+    }
+}
+
+/// what the bot does on its own once the battery is critically low
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CriticalPowerAction {
+    /// only warn the dashboard
+    Nothing,
+    Stop,
+    /// drive to the waypoint with this name
+    #[serde(rename = "return_home")]
+    GoTo(String),
+}
+
+/// how the bot logs, and what it forwards to the dashboard
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConf {
+    /// the log4rs config file
+    pub config: PathBuf,
+    /// the level records are forwarded to the dashboard at, until it asks for another
+    pub remote_level: LogLevel,
+}
+
+impl Default for LoggingConf {
+    fn default() -> Self {
+        Self {
+            config: "config/bot-log4rs.yml".into(),
+            remote_level: LogLevel::Info,
+        }
+    }
+}
+
+#[test]
+fn test_parse_config() {
+    let cfg = parse_config(include_str!("../../../config/bot.yml.template")).unwrap();
+    assert_eq!(cfg.drivetrain.control_period, Duration::from_millis(10));
+    assert_eq!(cfg.cameras.recording.max_file_size, 100 * 1024 * 1024);
+    assert_eq!(
+        cfg.cameras.recording.max_file_duration,
+        Duration::from_secs(600)
+    );
+    assert_eq!(cfg.cameras.recording.max_total_size, 4 * 1024 * 1024 * 1024);
+    assert_eq!(cfg.power.low_charge, 0.25);
+    assert_eq!(cfg.power.critical_action, CriticalPowerAction::Stop);
+
+    let with_network = |yaml: &str| format!("network:\n  addr: \"0.0.0.0:6440\"\n{}", yaml);
+    let minimal = parse_config(&with_network("")).unwrap();
+    assert_eq!(minimal.hal, HalConf::default());
+    assert_eq!(minimal.drivetrain.position_gains.kf, 0.0);
+    let home = parse_config(&with_network(
+        "power:\n  critical_action:\n    return_home: start\n\
+         waypoints:\n  - name: start\n    position: 0.0\n",
+    ))
+    .unwrap();
+    assert_eq!(
+        home.power.critical_action,
+        CriticalPowerAction::GoTo("start".to_string())
+    );
+
+    let error = |yaml: &str| parse_config(yaml).unwrap_err().to_string();
+    assert!(error("drivetrain: {}\n").contains("missing field `network`"));
+    assert!(error(&with_network("drivetrain:\n  control_rate: 0 Hz\n"))
+        .starts_with("drivetrain.control_rate: must be more than zero"));
+    assert!(error(&with_network(
+        "cameras:\n  recovery:\n    reopen_delay: 5 parsecs\n"
+    ))
+    .starts_with("cameras.recovery.reopen_delay: unknown unit `parsecs`"));
+    assert!(
+        error(&with_network("refresh_rate: 60 seconds\n")).contains("unknown field `refresh_rate`")
+    );
+    assert!(error(&with_network("power:\n  low_charge: 5%\n"))
+        .starts_with("power.critical_charge: must not be more than power.low_charge"));
+    assert!(error(&with_network(
+        "power:\n  critical_action:\n    return_home: dock\n"
+    ))
+    .starts_with("power.critical_action.return_home: there is no waypoint named \"dock\""));
+    assert!(error(&with_network(
+        "cameras:\n  recording:\n    max_total_size: 10 MB\n"
+    ))
+    .starts_with("cameras.recording.max_total_size: must be at least"));
+    assert!(error(&with_network("drivetrain:\n  wheel_diameter: 0.0\n"))
+        .starts_with("drivetrain.wheel_diameter: must be more than 0"));
+    assert!(
+        error(&with_network("drivetrain:\n  max_acceleration: -0.5\n"))
+            .starts_with("drivetrain.max_acceleration: must be more than 0")
+    );
+    assert!(
+        error(&with_network("drivetrain:\n  position_tolerance: .nan\n"))
+            .starts_with("drivetrain.position_tolerance: must be more than 0")
+    );
+    assert!(error(&with_network("hal:\n  encoder_ppr: 0\n"))
+        .starts_with("hal.encoder_ppr: must be at least 1"));
+}
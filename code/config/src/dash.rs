@@ -0,0 +1,563 @@
+//! Configuration of the dashboard (`config/dash.yml`)
+//!
+//! every key has a default, so the dashboard starts without a config file, connecting to a bot on the same computer.
+//! unknown keys are errors
+
+use aareocams_core::yaml;
+use aareocams_net::{DriveAction, EncoderSettings, ImageEncoding, LogLevel, WaypointTarget};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    net::{Ipv4Addr, SocketAddrV4},
+    path::{Path, PathBuf},
+};
+
+/// the defaults if there is no config file at `path`
+pub fn load_config<P: AsRef<Path>>(path: P) -> Result<Conf> {
+    let path = path.as_ref();
+    let yaml = match fs::read_to_string(path) {
+        Ok(yaml) => yaml,
+        Err(read_error) if read_error.kind() == io::ErrorKind::NotFound => {
+            warn!("There is no config file at {:?}, using the defaults", path);
+            return Ok(Conf::default());
+        }
+        Err(read_error) => {
+            return Err(read_error)
+                .with_context(|| format!("Failed to read the config file {:?}", path))
+        }
+    };
+    parse_config(&yaml).with_context(|| format!("Invalid config file {:?}", path))
+}
+
+/// parses and validates the contents of a config file. errors start with the key they are about, like
+/// `profiles.bot.addr: invalid IPv4 socket address syntax`
+pub fn parse_config(yaml: &str) -> Result<Conf> {
+    let cfg: Conf = yaml::parse(yaml)?;
+    cfg.validate()?;
+    Ok(cfg)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Conf {
+    /// the profile used unless another is picked with `--profile`
+    pub default_profile: String,
+    /// the bots the dashboard can connect to, by name
+    pub profiles: BTreeMap<String, Profile>,
+    /// where snapshots from the bot are saved
+    pub snapshot_dir: PathBuf,
+    /// where recordings downloaded from the bot are saved
+    pub recording_dir: PathBuf,
+    pub ui: UiConf,
+    pub streams: StreamDefaults,
+    pub keybindings: BTreeMap<KeyBinding, InputAction>,
+    pub gamepad: GamepadConf,
+}
+
+impl Default for Conf {
+    fn default() -> Self {
+        Self {
+            default_profile: "local".to_string(),
+            profiles: BTreeMap::from([(
+                "local".to_string(),
+                Profile {
+                    addr: SocketAddrV4::new(Ipv4Addr::LOCALHOST, 6440),
+                },
+            )]),
+            snapshot_dir: "snapshots".into(),
+            recording_dir: "recordings".into(),
+            ui: UiConf::default(),
+            streams: StreamDefaults::default(),
+            keybindings: BTreeMap::from([
+                (KeyBinding::key("w"), InputAction::Forward),
+                (KeyBinding::key("s"), InputAction::Reverse),
+                (KeyBinding::key("a"), InputAction::Stop),
+            ]),
+            gamepad: GamepadConf::default(),
+        }
+    }
+}
+
+impl Conf {
+    /// checks what can not be checked while parsing, because it involves more than one key
+    fn validate(&self) -> Result<()> {
+        if !self.profiles.contains_key(&self.default_profile) {
+            bail!(
+                "default_profile: there is no profile named `{}`",
+                self.default_profile
+            );
+        }
+        if !(1..=8).contains(&self.streams.quality) {
+            bail!("streams.quality: must be from 1 to 8");
+        }
+        if let SnapshotEncoding::Jpeg(quality) = self.streams.snapshot_encoding {
+            if !(1..=100).contains(&quality) {
+                bail!("streams.snapshot_encoding.jpeg: must be from 1 to 100");
+            }
+        }
+        Ok(())
+    }
+
+    /// the profile named `name`, or the default one if that is `None`
+    pub fn profile(&self, name: Option<&str>) -> Result<&Profile> {
+        let name = name.unwrap_or(&self.default_profile);
+        self.profiles.get(name).ok_or_else(|| {
+            anyhow!(
+                "There is no profile named `{}`, the profiles are: {}",
+                name,
+                self.profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+            )
+        })
+    }
+}
+
+/// a bot the dashboard can connect to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub addr: SocketAddrV4,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UiConf {
+    /// size of the window when it opens, in pixels
+    pub width: u32,
+    pub height: u32,
+    pub text_size: u16,
+    /// log records kept in the log panel before the oldest ones are dropped
+    pub log_records: usize,
+    /// the level the bot is asked to forward log records at when connecting to it
+    pub log_level: LogLevel,
+}
+
+impl Default for UiConf {
+    fn default() -> Self {
+        Self {
+            width: 1024,
+            height: 768,
+            text_size: 20,
+            log_records: 2000,
+            log_level: LogLevel::Info,
+        }
+    }
+}
+
+/// what new camera streams start out with, these can be changed before connecting each stream
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamDefaults {
+    /// see [`EncoderSettings`]
+    pub max_packet_size: Option<usize>,
+    pub quality: u8,
+    pub keyframe_interval: Option<u32>,
+    pub adaptive: bool,
+    pub max_fps: Option<u32>,
+    pub latest_frame_only: bool,
+    pub snapshot_encoding: SnapshotEncoding,
+}
+
+impl Default for StreamDefaults {
+    fn default() -> Self {
+        let encoder = EncoderSettings::default();
+        Self {
+            max_packet_size: encoder.max_packet_size,
+            quality: encoder.quality,
+            keyframe_interval: encoder.keyframe_interval,
+            adaptive: encoder.adaptive,
+            max_fps: encoder.max_fps,
+            latest_frame_only: encoder.latest_frame_only,
+            snapshot_encoding: SnapshotEncoding::Png,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotEncoding {
+    Png,
+    /// with a quality from 1 to 100
+    Jpeg(u8),
+}
+
+impl From<SnapshotEncoding> for ImageEncoding {
+    fn from(encoding: SnapshotEncoding) -> Self {
+        match encoding {
+            SnapshotEncoding::Png => Self::Png,
+            SnapshotEncoding::Jpeg(quality) => Self::Jpeg { quality },
+        }
+    }
+}
+
+/// the names of the variants of iced's `KeyCode`, lowercase, which are the names of keys in keybindings
+pub const KEY_NAMES: &[&str] = &[
+    "key1",
+    "key2",
+    "key3",
+    "key4",
+    "key5",
+    "key6",
+    "key7",
+    "key8",
+    "key9",
+    "key0",
+    "a",
+    "b",
+    "c",
+    "d",
+    "e",
+    "f",
+    "g",
+    "h",
+    "i",
+    "j",
+    "k",
+    "l",
+    "m",
+    "n",
+    "o",
+    "p",
+    "q",
+    "r",
+    "s",
+    "t",
+    "u",
+    "v",
+    "w",
+    "x",
+    "y",
+    "z",
+    "escape",
+    "f1",
+    "f2",
+    "f3",
+    "f4",
+    "f5",
+    "f6",
+    "f7",
+    "f8",
+    "f9",
+    "f10",
+    "f11",
+    "f12",
+    "f13",
+    "f14",
+    "f15",
+    "f16",
+    "f17",
+    "f18",
+    "f19",
+    "f20",
+    "f21",
+    "f22",
+    "f23",
+    "f24",
+    "snapshot",
+    "scroll",
+    "pause",
+    "insert",
+    "home",
+    "delete",
+    "end",
+    "pagedown",
+    "pageup",
+    "left",
+    "up",
+    "right",
+    "down",
+    "backspace",
+    "enter",
+    "space",
+    "compose",
+    "caret",
+    "numlock",
+    "numpad0",
+    "numpad1",
+    "numpad2",
+    "numpad3",
+    "numpad4",
+    "numpad5",
+    "numpad6",
+    "numpad7",
+    "numpad8",
+    "numpad9",
+    "numpadadd",
+    "numpaddivide",
+    "numpaddecimal",
+    "numpadcomma",
+    "numpadenter",
+    "numpadequals",
+    "numpadmultiply",
+    "numpadsubtract",
+    "abntc1",
+    "abntc2",
+    "apostrophe",
+    "apps",
+    "asterisk",
+    "at",
+    "ax",
+    "backslash",
+    "calculator",
+    "capital",
+    "colon",
+    "comma",
+    "convert",
+    "equals",
+    "grave",
+    "kana",
+    "kanji",
+    "lalt",
+    "lbracket",
+    "lcontrol",
+    "lshift",
+    "lwin",
+    "mail",
+    "mediaselect",
+    "mediastop",
+    "minus",
+    "mute",
+    "mycomputer",
+    "navigateforward",
+    "navigatebackward",
+    "nexttrack",
+    "noconvert",
+    "oem102",
+    "period",
+    "playpause",
+    "plus",
+    "power",
+    "prevtrack",
+    "ralt",
+    "rbracket",
+    "rcontrol",
+    "rshift",
+    "rwin",
+    "semicolon",
+    "slash",
+    "sleep",
+    "stop",
+    "sysrq",
+    "tab",
+    "underline",
+    "unlabeled",
+    "volumedown",
+    "volumeup",
+    "wake",
+    "webback",
+    "webfavorites",
+    "webforward",
+    "webhome",
+    "webrefresh",
+    "websearch",
+    "webstop",
+    "yen",
+    "copy",
+    "paste",
+    "cut",
+];
+
+/// a key, and the modifiers held with it. written like `w` or `ctrl+shift+up`, where the names of keys are those of
+/// iced's `KeyCode` (not case sensitive)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyBinding {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    /// lowercase
+    pub key: String,
+}
+
+impl KeyBinding {
+    /// the key without any modifiers
+    pub fn key(key: &str) -> Self {
+        Self {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            key: key.to_lowercase(),
+        }
+    }
+}
+
+impl TryFrom<String> for KeyBinding {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        let mut parts = text.split('+').map(str::trim).collect::<Vec<_>>();
+        let key = match parts.pop() {
+            Some(key) if !key.is_empty() => key,
+            _ => return Err(format!("`{}` does not end with a key", text)),
+        };
+        if !KEY_NAMES.contains(&key.to_lowercase().as_str()) {
+            return Err(format!(
+                "unknown key `{}`, expected the name of one of iced's `KeyCode`s like `w`, `key1` or `up`",
+                key
+            ));
+        }
+        let mut binding = Self::key(key);
+        for modifier in parts {
+            *match modifier.to_lowercase().as_str() {
+                "ctrl" => &mut binding.ctrl,
+                "alt" => &mut binding.alt,
+                "shift" => &mut binding.shift,
+                other => {
+                    return Err(format!(
+                        "unknown modifier `{}`, expected ctrl, alt or shift",
+                        other
+                    ))
+                }
+            } = true;
+        }
+        Ok(binding)
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, modifier) in [
+            (self.ctrl, "ctrl+"),
+            (self.alt, "alt+"),
+            (self.shift, "shift+"),
+        ] {
+            if held {
+                f.write_str(modifier)?;
+            }
+        }
+        f.write_str(&self.key)
+    }
+}
+
+impl From<KeyBinding> for String {
+    fn from(binding: KeyBinding) -> Self {
+        binding.to_string()
+    }
+}
+
+/// what a key or gamepad button does when it is pressed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputAction {
+    Forward,
+    Reverse,
+    Stop,
+    /// drive to the waypoint with this name
+    GoTo(String),
+}
+
+impl From<InputAction> for DriveAction {
+    fn from(action: InputAction) -> Self {
+        match action {
+            InputAction::Forward => Self::Fwd,
+            InputAction::Reverse => Self::Rev,
+            InputAction::Stop => Self::Stop,
+            InputAction::GoTo(name) => Self::GoTo {
+                target: WaypointTarget::Named(name),
+                speed: None,
+            },
+        }
+    }
+}
+
+/// a button on the SN30 pro gamepad
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GamepadButton {
+    A,
+    B,
+    X,
+    Y,
+    L1,
+    L2,
+    R1,
+    R2,
+    Start,
+    Select,
+    Heart,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl GamepadButton {
+    pub const ALL: [Self; 15] = [
+        Self::A,
+        Self::B,
+        Self::X,
+        Self::Y,
+        Self::L1,
+        Self::L2,
+        Self::R1,
+        Self::R2,
+        Self::Start,
+        Self::Select,
+        Self::Heart,
+        Self::Up,
+        Self::Down,
+        Self::Left,
+        Self::Right,
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamepadConf {
+    /// the gamepad at `/dev/input/js<port>`, or `None` to not use one
+    pub port: Option<usize>,
+    pub buttons: BTreeMap<GamepadButton, InputAction>,
+}
+
+impl Default for GamepadConf {
+    fn default() -> Self {
+        Self {
+            port: None,
+            buttons: BTreeMap::from([
+                (GamepadButton::Up, InputAction::Forward),
+                (GamepadButton::Down, InputAction::Reverse),
+                (GamepadButton::B, InputAction::Stop),
+            ]),
+        }
+    }
+}
+
+#[test]
+fn test_parse_config() {
+    let cfg = parse_config(include_str!("../../../config/dash.yml.template")).unwrap();
+    assert_eq!(cfg, Conf::default());
+    assert_eq!(parse_config("").unwrap(), Conf::default());
+
+    let bindings =
+        parse_config("keybindings:\n  Ctrl+Shift+Up: stop\n  h:\n    go_to: start\n").unwrap();
+    assert_eq!(
+        bindings.keybindings[&KeyBinding {
+            ctrl: true,
+            alt: false,
+            shift: true,
+            key: "up".to_string()
+        }],
+        InputAction::Stop
+    );
+    assert_eq!(
+        bindings.keybindings[&KeyBinding::key("h")],
+        InputAction::GoTo("start".to_string())
+    );
+    assert_eq!(
+        KeyBinding::try_from("ctrl+alt+w".to_string())
+            .unwrap()
+            .to_string(),
+        "ctrl+alt+w"
+    );
+
+    let error = |yaml: &str| parse_config(yaml).unwrap_err().to_string();
+    assert!(error("keybindings:\n  meta+w: stop\n")
+        .starts_with("keybindings.meta+w: unknown modifier `meta`"));
+    assert!(
+        error("keybindings:\n  ctrl+1: stop\n").starts_with("keybindings.ctrl+1: unknown key `1`")
+    );
+    assert!(error("default_profile: bot\n")
+        .starts_with("default_profile: there is no profile named `bot`"));
+    assert!(error("streams:\n  quality: 9\n").starts_with("streams.quality: must be from 1 to 8"));
+}
@@ -0,0 +1,12 @@
+//! The config files of the bot and the dashboard, in their own crate so that the setup tool checks them with
+//! the same parsers the bot and dashboard use
+
+extern crate aareocams_core;
+extern crate aareocams_net;
+extern crate anyhow;
+extern crate serde;
+#[macro_use]
+extern crate log;
+
+pub mod bot;
+pub mod dash;
@@ -1,5 +1,8 @@
 use std::ops::Deref;
 
+pub mod units;
+pub mod yaml;
+
 
 /// A [`bool`] that can be set to true only **once**
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -0,0 +1,235 @@
+//! Values with units in config files
+//!
+//! these are used with `#[serde(with = "...")]`, and accept either a number in the base unit (seconds, Hz, bytes or
+//! percent), or a string with a unit like `"60 seconds"`, `"100 Hz"` or `"100 MB"`. they are written as strings with
+//! units, so generated config files are readable.
+
+use std::{fmt, time::Duration};
+
+use serde::{de, Deserializer, Serializer};
+
+const DURATION_UNITS: &[(&str, f64)] = &[
+    ("ms", 0.001),
+    ("millisecond", 0.001),
+    ("milliseconds", 0.001),
+    ("s", 1.0),
+    ("sec", 1.0),
+    ("second", 1.0),
+    ("seconds", 1.0),
+    ("min", 60.0),
+    ("minute", 60.0),
+    ("minutes", 60.0),
+    ("h", 3600.0),
+    ("hour", 3600.0),
+    ("hours", 3600.0),
+];
+const RATE_UNITS: &[(&str, f64)] = &[("hz", 1.0), ("khz", 1000.0)];
+const SIZE_UNITS: &[(&str, f64)] = &[
+    ("b", 1.0),
+    ("kb", 1024.0),
+    ("kib", 1024.0),
+    ("mb", 1024.0 * 1024.0),
+    ("mib", 1024.0 * 1024.0),
+    ("gb", 1024.0 * 1024.0 * 1024.0),
+    ("gib", 1024.0 * 1024.0 * 1024.0),
+];
+const PERCENT_UNITS: &[(&str, f64)] = &[("%", 1.0)];
+
+/// parses `"<number> <unit>"` (the space is optional, and units are not case sensitive) into the base unit
+fn parse_quantity(text: &str, units: &[(&str, f64)]) -> Result<f64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("`{}` does not start with a number", text))?;
+    let unit = unit.trim().to_lowercase();
+    if unit.is_empty() {
+        return Ok(number);
+    }
+    units
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, scale)| number * scale)
+        .ok_or_else(|| {
+            format!(
+                "unknown unit `{}`, expected one of {}",
+                unit,
+                units
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
+/// `f64` formatted without float noise like `0.30000000000000004`
+fn round(value: f64) -> f64 {
+    (value * 1e6).round() / 1e6
+}
+
+struct QuantityVisitor {
+    expecting: &'static str,
+    units: &'static [(&'static str, f64)],
+}
+
+impl<'de> de::Visitor<'de> for QuantityVisitor {
+    type Value = f64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.expecting)
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<f64, E> {
+        Ok(value)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<f64, E> {
+        Ok(value as f64)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<f64, E> {
+        Ok(value as f64)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<f64, E> {
+        parse_quantity(value, self.units).map_err(E::custom)
+    }
+}
+
+/// a non negative quantity in the base unit of `units`
+fn deserialize_quantity<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    expecting: &'static str,
+    units: &'static [(&'static str, f64)],
+) -> Result<f64, D::Error> {
+    let value = deserializer.deserialize_any(QuantityVisitor { expecting, units })?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(de::Error::custom(format!(
+            "must not be negative, got {}",
+            value
+        )));
+    }
+    Ok(value)
+}
+
+/// a [`Duration`], as seconds or with a unit like `"500 ms"` or `"10 minutes"`
+pub mod duration {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.as_secs() == 0 && !value.is_zero() {
+            serializer.serialize_str(&format!("{} ms", round(value.as_secs_f64() * 1000.0)))
+        } else {
+            serializer.serialize_str(&format!("{} s", round(value.as_secs_f64())))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        deserialize_quantity(
+            deserializer,
+            "a duration, like `0.5`, `500 ms` or `10 minutes`",
+            DURATION_UNITS,
+        )
+        .and_then(|secs| Duration::try_from_secs_f64(secs).map_err(de::Error::custom))
+    }
+}
+
+/// the period of something that happens at a rate, given in Hz like `"100 Hz"`
+pub mod rate {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(period: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{} Hz", round(1.0 / period.as_secs_f64())))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let rate = deserialize_quantity(deserializer, "a rate, like `10` or `10 Hz`", RATE_UNITS)?;
+        if rate == 0.0 {
+            return Err(de::Error::custom("must be more than zero"));
+        }
+        Duration::try_from_secs_f64(1.0 / rate).map_err(de::Error::custom)
+    }
+}
+
+/// a size in bytes, or with a unit like `"100 MB"` (units are powers of 1024)
+pub mod size {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        const UNITS: [(&str, u64); 3] = [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10)];
+        match UNITS
+            .iter()
+            .find(|(_, scale)| *bytes != 0 && *bytes % scale == 0)
+        {
+            Some((unit, scale)) => {
+                serializer.serialize_str(&format!("{} {}", *bytes / scale, unit))
+            }
+            None => serializer.serialize_u64(*bytes),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        deserialize_quantity(
+            deserializer,
+            "a size, like `1048576` or `100 MB`",
+            SIZE_UNITS,
+        )
+        .map(|bytes| bytes as u64)
+    }
+}
+
+/// a fraction (0 to 1), given in percent like `25` or `"25%"`
+pub mod percent {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(fraction: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{}%", round(fraction * 100.0)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        let percent = deserialize_quantity(
+            deserializer,
+            "a percentage, like `25` or `25%`",
+            PERCENT_UNITS,
+        )?;
+        if percent > 100.0 {
+            return Err(de::Error::custom(format!(
+                "must not be more than 100%, got {}%",
+                percent
+            )));
+        }
+        Ok(percent / 100.0)
+    }
+}
+
+#[test]
+fn test_parse_quantity() {
+    assert_eq!(parse_quantity("60 seconds", DURATION_UNITS), Ok(60.0));
+    assert_eq!(parse_quantity("500ms", DURATION_UNITS), Ok(0.5));
+    assert_eq!(parse_quantity(" 1.5 Min ", DURATION_UNITS), Ok(90.0));
+    assert_eq!(parse_quantity("12", DURATION_UNITS), Ok(12.0));
+    assert_eq!(parse_quantity("10 kHz", RATE_UNITS), Ok(10000.0));
+    assert_eq!(
+        parse_quantity("100 MB", SIZE_UNITS),
+        Ok(100.0 * 1024.0 * 1024.0)
+    );
+    assert_eq!(parse_quantity("25%", PERCENT_UNITS), Ok(25.0));
+    assert!(parse_quantity("ten seconds", DURATION_UNITS).is_err());
+    assert!(parse_quantity("10 parsecs", DURATION_UNITS).is_err());
+}
+
+#[test]
+fn test_out_of_range() {
+    use serde_yaml::Value;
+    assert!(duration::deserialize(Value::from(1e300)).is_err());
+    assert!(duration::deserialize(Value::from(-1.0)).is_err());
+    assert!(rate::deserialize(Value::from(1e-300)).is_err());
+    assert_eq!(
+        rate::deserialize(Value::from("100 Hz")).unwrap(),
+        Duration::from_millis(10)
+    );
+}
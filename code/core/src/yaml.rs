@@ -0,0 +1,48 @@
+//! Parsing yaml config files, with errors that say which key they are about
+
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// the key the error is about, like `drivetrain.control_rate`, or `None` if it is about the whole file
+    pub key: Option<String>,
+    /// what is wrong, and where in the file
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.key {
+            Some(key) => write!(f, "{}: {}", key, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn parse<T: DeserializeOwned>(yaml: &str) -> Result<T, ParseError> {
+    // serde_yaml can not parse a file with nothing but comments in it, which should be the same as an empty map
+    let is_empty = yaml.lines().all(|line| {
+        let line = line.trim();
+        line.is_empty() || line.starts_with('#')
+    });
+    let yaml = if is_empty { "{}" } else { yaml };
+    serde_path_to_error::deserialize(serde_yaml::Deserializer::from_str(yaml)).map_err(|error| {
+        let message = error.inner().to_string();
+        // serde_yaml puts its own path in front of some errors, but it is not always the whole path to the key
+        let message = match message.split_once(": ") {
+            Some((path, rest)) if !path.contains(char::is_whitespace) => rest.to_string(),
+            _ => message,
+        };
+        ParseError {
+            key: match error.path().to_string().as_str() {
+                "." => None,
+                key => Some(key.to_string()),
+            },
+            message,
+        }
+    })
+}
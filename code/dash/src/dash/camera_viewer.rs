@@ -1,23 +1,79 @@
-use aareocams_net::{Message, VideoStreamAction};
+use std::{fmt, path::PathBuf, time::Instant};
+
+use aareocams_net::{
+    CameraDevice, CameraFormat, CameraListing, CloseReason, EncoderSettings, ImageEncoding,
+    Message, OperatingPoint, SnapshotRequest, StreamFormat, TestPattern, TestPatternKind,
+    VideoStreamAction,
+};
+use bincode::Options;
 use iced::{
     button,
     image::{Handle as IcedImageHandle, Image as IcedImage},
-    text_input, Alignment, Button, Column, Length, Row, Text, TextInput,
+    pick_list, text_input, Alignment, Button, Checkbox, Column, Length, PickList, Row, Text,
+    TextInput,
 };
-use image::DynamicImage;
+use image::{DynamicImage, RgbImage};
 use lvenc::Decoder;
 use lvenc::Packet;
 use uuid::Uuid;
 
+use super::player::{Player, PlayerEvent};
+use crate::{config::StreamDefaults, recording::StreamRecording};
+
+/// the state of a stream, as last reported by the bot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamState {
+    Opening,
+    Streaming(StreamFormat),
+    Paused,
+    Closed(CloseReason),
+}
+
+impl fmt::Display for StreamState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Opening => write!(f, "opening the camera"),
+            Self::Streaming(format) => write!(f, "streaming {}", format),
+            Self::Paused => write!(f, "paused"),
+            Self::Closed(reason) => write!(f, "closed: {}", reason),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct VideoStream {
     pub decoder: Decoder,
     pub stream_id: Uuid,
+    /// where the stream gets its frames from
+    pub dev: CameraDevice,
     pub image_handle: IcedImageHandle,
     pub pause_btn: button::State,
     pub resume_btn: button::State,
     pub close_btn: button::State,
+    pub reconfigure_btn: button::State,
+    pub snapshot_btn: button::State,
+    pub record_btn: button::State,
+    pub stop_recording_btn: button::State,
+    pub record_locally_btn: button::State,
+    pub stop_local_recording_btn: button::State,
     pub paused: bool,
+    pub state: StreamState,
+    /// the last error the bot reported about the stream, cleared once it is streaming again
+    pub error: Option<String>,
+    /// data of a packet that is being sent in fragments
+    pub fragments: Vec<u8>,
+    /// how far the bot has turned the stream down to keep up with the link
+    pub operating_point: OperatingPoint,
+    /// set while the bot's camera for this stream is lost, or after the bot gave up on it
+    pub camera_status: Option<String>,
+    /// what happened to the last snapshot taken from this stream
+    pub snapshot_status: Option<String>,
+    /// file the bot is recording this stream to, or what went wrong with the recording
+    pub recording_status: Option<String>,
+    /// recording of the stream as it is received, to be played back later
+    pub local_recording: Option<StreamRecording>,
+    /// what went wrong with the last local recording
+    pub local_recording_error: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -28,16 +84,111 @@ pub enum CameraViewerEvent {
     Resume(Uuid),
     /// close stream with some id
     Close(Uuid),
-    StreamIDInputChange(String),
+    /// apply the encoder settings to the stream with some id
+    Reconfigure(Uuid),
+    /// take a snapshot from the camera of the stream with some id
+    Snapshot(Uuid),
+    SnapshotEncodingSelected(EncodingOption),
+    /// start recording the stream with some id on the bot
+    Record(Uuid),
+    /// stop recording the stream with some id on the bot
+    StopRecording(Uuid),
+    /// start recording the stream with some id on the dashboard, as it is received
+    RecordLocally(Uuid),
+    StopLocalRecording(Uuid),
+    Player(PlayerEvent),
+    PacketSizeInputChange(String),
+    QualityInputChange(String),
+    KeyframeIntervalInputChange(String),
+    MaxFpsInputChange(String),
+    AdaptiveToggled(bool),
+    LatestFrameOnlyToggled(bool),
+    CameraSelected(CameraOption),
+    FormatSelected(FormatOption),
+    /// ask the bot for the list of cameras again
+    RefreshCameras,
     CreateStream,
+    /// stream a test pattern generated on the bot, instead of a camera
+    CreateTestPattern,
+}
+
+/// a camera on the bot, as shown in the camera picker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CameraOption(pub CameraListing);
+
+impl fmt::Display for CameraOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.0.index, self.0.human_name)?;
+        if self.0.in_use {
+            write!(f, " (in use)")?;
+        }
+        Ok(())
+    }
+}
+
+/// a format to open a camera in, as shown in the format picker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOption(pub Option<CameraFormat>);
+
+impl fmt::Display for FormatOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(format) => write!(f, "{}", format),
+            None => write!(f, "default format"),
+        }
+    }
+}
+
+/// how to encode snapshots, as shown in the snapshot encoding picker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodingOption(pub ImageEncoding);
+
+impl EncodingOption {
+    const ALL: [Self; 2] = [
+        Self(ImageEncoding::Png),
+        Self(ImageEncoding::Jpeg { quality: 95 }),
+    ];
+}
+
+impl fmt::Display for EncodingOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            ImageEncoding::Png => write!(f, "PNG (lossless)"),
+            ImageEncoding::Jpeg { quality } => write!(f, "JPEG (quality {})", quality),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct CameraViewer {
-    new_stream_input_state: text_input::State,
-    new_stream_input_text: String,
+    cameras: Vec<CameraOption>,
+    selected_camera: Option<CameraOption>,
+    camera_pick_state: pick_list::State<CameraOption>,
+    /// formats supported by the selected camera
+    formats: Vec<FormatOption>,
+    selected_format: FormatOption,
+    format_pick_state: pick_list::State<FormatOption>,
+    /// why the formats of the selected camera could not be read
+    formats_error: Option<String>,
+    refresh_btn_state: button::State,
+    packet_size_input_state: text_input::State,
+    packet_size_input_text: String,
+    quality_input_state: text_input::State,
+    quality_input_text: String,
+    keyframe_interval_input_state: text_input::State,
+    keyframe_interval_input_text: String,
+    max_fps_input_state: text_input::State,
+    max_fps_input_text: String,
+    adaptive: bool,
+    latest_frame_only: bool,
+    snapshot_encoding: EncodingOption,
+    snapshot_encoding_pick_state: pick_list::State<EncodingOption>,
     new_stream_btn_state: button::State,
+    test_pattern_btn_state: button::State,
     streams: Vec<VideoStream>,
+    /// where local recordings are saved
+    recording_dir: PathBuf,
+    player: Player,
     messages: Vec<Message>,
 }
 
@@ -51,30 +202,89 @@ impl CameraViewer {
         None
     }
 
-    pub fn new() -> Self {
+    pub fn new(recording_dir: PathBuf, defaults: StreamDefaults) -> Self {
         Self {
-            new_stream_input_state: text_input::State::new(),
-            new_stream_input_text: String::new(),
+            cameras: vec![],
+            selected_camera: None,
+            camera_pick_state: pick_list::State::default(),
+            formats: vec![FormatOption(None)],
+            selected_format: FormatOption(None),
+            format_pick_state: pick_list::State::default(),
+            formats_error: None,
+            refresh_btn_state: button::State::new(),
+            packet_size_input_state: text_input::State::new(),
+            packet_size_input_text: optional_text(defaults.max_packet_size),
+            quality_input_state: text_input::State::new(),
+            quality_input_text: defaults.quality.to_string(),
+            keyframe_interval_input_state: text_input::State::new(),
+            keyframe_interval_input_text: optional_text(defaults.keyframe_interval),
+            max_fps_input_state: text_input::State::new(),
+            max_fps_input_text: optional_text(defaults.max_fps),
+            adaptive: defaults.adaptive,
+            latest_frame_only: defaults.latest_frame_only,
+            snapshot_encoding: EncodingOption(defaults.snapshot_encoding.into()),
+            snapshot_encoding_pick_state: pick_list::State::default(),
             new_stream_btn_state: button::State::new(),
+            test_pattern_btn_state: button::State::new(),
             streams: vec![],
+            player: Player::new(recording_dir.clone()),
+            recording_dir,
             messages: vec![],
         }
     }
 
-    pub fn register_stream(&mut self, id: Uuid) {
+    pub fn register_stream(&mut self, id: Uuid, dev: CameraDevice) {
         self.streams.push(VideoStream {
             decoder: Decoder::new(),
             stream_id: id,
+            dev,
             image_handle: IcedImageHandle::from_pixels(0, 0, vec![]),
             pause_btn: button::State::new(),
             resume_btn: button::State::new(),
             close_btn: button::State::new(),
+            reconfigure_btn: button::State::new(),
+            snapshot_btn: button::State::new(),
+            record_btn: button::State::new(),
+            stop_recording_btn: button::State::new(),
+            record_locally_btn: button::State::new(),
+            stop_local_recording_btn: button::State::new(),
             paused: false,
+            state: StreamState::Opening,
+            error: None,
+            fragments: vec![],
+            operating_point: OperatingPoint::FULL,
+            camera_status: None,
+            snapshot_status: None,
+            recording_status: None,
+            local_recording: None,
+            local_recording_error: None,
         });
     }
 
+    /// encoder settings from the inputs, where empty inputs are left as the default.
+    /// returns `None` if any of them are invalid
+    fn encoder_settings(&self) -> Option<EncoderSettings> {
+        let defaults = EncoderSettings::default();
+        let optional = |text: &str| match text {
+            "" => Some(None),
+            text => text.parse().ok().map(Some),
+        };
+        Some(EncoderSettings {
+            max_packet_size: optional(&self.packet_size_input_text)?,
+            quality: optional(&self.quality_input_text)?.unwrap_or(defaults.quality),
+            keyframe_interval: optional(&self.keyframe_interval_input_text)?,
+            adaptive: self.adaptive,
+            max_fps: optional(&self.max_fps_input_text)?,
+            latest_frame_only: self.latest_frame_only,
+        })
+    }
+
     pub fn close_stream(&mut self, id: Uuid) {
-        self.streams.drain_filter(move |i| i.stream_id == id);
+        for stream in self.streams.drain_filter(move |i| i.stream_id == id) {
+            if let Some(recording) = stream.local_recording {
+                finish_local_recording(recording);
+            }
+        }
     }
 
     pub fn view(&mut self) -> iced::Element<CameraViewerEvent> {
@@ -82,18 +292,92 @@ impl CameraViewer {
             .height(Length::Shrink)
             .width(Length::Shrink)
             .spacing(2)
+            .push(
+                PickList::new(
+                    &mut self.camera_pick_state,
+                    &self.cameras[..],
+                    self.selected_camera.clone(),
+                    CameraViewerEvent::CameraSelected,
+                )
+                .placeholder("camera")
+                .width(Length::Units(200)),
+            )
+            .push(
+                Button::new(&mut self.refresh_btn_state, Text::new("Refresh cameras"))
+                    .on_press(CameraViewerEvent::RefreshCameras),
+            )
+            .push(
+                PickList::new(
+                    &mut self.format_pick_state,
+                    &self.formats[..],
+                    Some(self.selected_format.clone()),
+                    CameraViewerEvent::FormatSelected,
+                )
+                .width(Length::Units(200)),
+            )
+            .push(Text::new(self.formats_error.as_deref().unwrap_or_default()))
             .push(
                 TextInput::new(
-                    &mut self.new_stream_input_state,
-                    "id",
-                    &self.new_stream_input_text,
-                    |new| CameraViewerEvent::StreamIDInputChange(new),
+                    &mut self.packet_size_input_state,
+                    "max packet size (bytes)",
+                    &self.packet_size_input_text,
+                    CameraViewerEvent::PacketSizeInputChange,
                 )
-                .width(Length::Units(100)),
+                .width(Length::Units(200)),
+            )
+            .push(
+                TextInput::new(
+                    &mut self.quality_input_state,
+                    "quality (1-8)",
+                    &self.quality_input_text,
+                    CameraViewerEvent::QualityInputChange,
+                )
+                .width(Length::Units(200)),
+            )
+            .push(
+                TextInput::new(
+                    &mut self.keyframe_interval_input_state,
+                    "keyframe interval (frames)",
+                    &self.keyframe_interval_input_text,
+                    CameraViewerEvent::KeyframeIntervalInputChange,
+                )
+                .width(Length::Units(200)),
+            )
+            .push(
+                TextInput::new(
+                    &mut self.max_fps_input_state,
+                    "max fps",
+                    &self.max_fps_input_text,
+                    CameraViewerEvent::MaxFpsInputChange,
+                )
+                .width(Length::Units(200)),
+            )
+            .push(Checkbox::new(
+                self.adaptive,
+                "adapt to the link",
+                CameraViewerEvent::AdaptiveToggled,
+            ))
+            .push(Checkbox::new(
+                self.latest_frame_only,
+                "latest frame only",
+                CameraViewerEvent::LatestFrameOnlyToggled,
+            ))
+            .push(
+                PickList::new(
+                    &mut self.snapshot_encoding_pick_state,
+                    &EncodingOption::ALL[..],
+                    Some(self.snapshot_encoding),
+                    CameraViewerEvent::SnapshotEncodingSelected,
+                )
+                .width(Length::Units(200)),
             )
             .push(
                 Button::new(&mut self.new_stream_btn_state, Text::new("Connect stream"))
                     .on_press(CameraViewerEvent::CreateStream),
+            )
+            .push(
+                Button::new(&mut self.test_pattern_btn_state, Text::new("Test pattern"))
+                    .on_press(CameraViewerEvent::CreateTestPattern),
             );
 
         let mut root_children = vec![];
@@ -127,12 +411,63 @@ impl CameraViewer {
                             .push(
                                 Button::new(&mut cam.close_btn, Text::new("Close"))
                                     .on_press(CameraViewerEvent::Close(cam.stream_id)),
+                            )
+                            .push(
+                                Button::new(&mut cam.reconfigure_btn, Text::new("Apply settings"))
+                                    .on_press(CameraViewerEvent::Reconfigure(cam.stream_id)),
+                            )
+                            .push(
+                                Button::new(&mut cam.snapshot_btn, Text::new("Snapshot"))
+                                    .on_press(CameraViewerEvent::Snapshot(cam.stream_id)),
+                            )
+                            .push(
+                                Button::new(&mut cam.record_btn, Text::new("Record"))
+                                    .on_press(CameraViewerEvent::Record(cam.stream_id)),
+                            )
+                            .push(
+                                Button::new(
+                                    &mut cam.stop_recording_btn,
+                                    Text::new("Stop recording"),
+                                )
+                                .on_press(CameraViewerEvent::StopRecording(cam.stream_id)),
+                            )
+                            .push(
+                                Button::new(&mut cam.record_locally_btn, Text::new("Record here"))
+                                    .on_press(CameraViewerEvent::RecordLocally(cam.stream_id)),
+                            )
+                            .push(
+                                Button::new(
+                                    &mut cam.stop_local_recording_btn,
+                                    Text::new("Stop recording here"),
+                                )
+                                .on_press(CameraViewerEvent::StopLocalRecording(cam.stream_id)),
                             ),
                     )
+                    .push(Text::new(cam.state.to_string()))
+                    .push(Text::new(cam.error.as_deref().unwrap_or_default()))
+                    .push(Text::new(cam.operating_point.to_string()))
+                    .push(Text::new(cam.camera_status.as_deref().unwrap_or_default()))
+                    .push(Text::new(
+                        cam.snapshot_status.as_deref().unwrap_or_default(),
+                    ))
+                    .push(Text::new(
+                        cam.recording_status.as_deref().unwrap_or_default(),
+                    ))
+                    .push(Text::new(
+                        match (&cam.local_recording, &cam.local_recording_error) {
+                            (Some(recording), _) => {
+                                format!("recording here to {}", recording.path().display())
+                            }
+                            (None, Some(error)) => format!("recording here failed: {}", error),
+                            (None, None) => String::new(),
+                        },
+                    ))
                     .into(),
             );
         }
 
+        root_children.push(self.player.view().map(CameraViewerEvent::Player));
+
         let root: iced::Element<CameraViewerEvent> =
             Row::<'_, CameraViewerEvent>::with_children(root_children)
                 .align_items(Alignment::Center)
@@ -171,25 +506,168 @@ impl CameraViewer {
                 }
             }
             CameraViewerEvent::Close(id) => {
+                let closed = self.stream_by_id(id).map_or(false, |stream| {
+                    matches!(stream.state, StreamState::Closed(_))
+                });
                 self.close_stream(id);
+                // the bot has already forgotten about streams it closed itself
+                if !closed {
+                    self.messages.push(Message::VideoStreamCtl {
+                        id,
+                        action: VideoStreamAction::Close,
+                    });
+                    self.messages.push(Message::ListCameras);
+                }
+            }
+            CameraViewerEvent::Reconfigure(id) => {
+                if let Some(settings) = self.encoder_settings() {
+                    self.messages.push(Message::VideoStreamCtl {
+                        id,
+                        action: VideoStreamAction::Reconfigure(settings),
+                    });
+                }
+            }
+            CameraViewerEvent::Snapshot(id) => {
+                let selected_index = self.selected_camera.as_ref().map(|camera| camera.0.index);
+                let selected_format = self.selected_format.0;
+                let encoding = self.snapshot_encoding.0;
+                if let Some(stream) = self.stream_by_id(id) {
+                    // the format picker is for the selected camera, so only use it for streams from that camera
+                    let format = match stream.dev {
+                        CameraDevice::Index(index) if Some(index) == selected_index => {
+                            selected_format
+                        }
+                        _ => None,
+                    };
+                    stream.snapshot_status = Some("taking snapshot".into());
+                    self.messages.push(Message::VideoStreamCtl {
+                        id,
+                        action: VideoStreamAction::Snapshot(SnapshotRequest { format, encoding }),
+                    });
+                }
+            }
+            CameraViewerEvent::SnapshotEncodingSelected(encoding) => {
+                self.snapshot_encoding = encoding;
+            }
+            CameraViewerEvent::Record(id) => {
                 self.messages.push(Message::VideoStreamCtl {
                     id,
-                    action: VideoStreamAction::Close,
+                    action: VideoStreamAction::StartRecording,
                 });
             }
+            CameraViewerEvent::StopRecording(id) => {
+                self.messages.push(Message::VideoStreamCtl {
+                    id,
+                    action: VideoStreamAction::StopRecording,
+                });
+            }
+            CameraViewerEvent::RecordLocally(id) => {
+                let recording_dir = self.recording_dir.clone();
+                if let Some(stream) = self.stream_by_id(id) {
+                    if stream.local_recording.is_some() {
+                        return;
+                    }
+                    match StreamRecording::create(&recording_dir, id) {
+                        Ok(recording) => {
+                            info!("Recording stream {} to {}", id, recording.path().display());
+                            stream.local_recording = Some(recording);
+                            stream.local_recording_error = None;
+                            // the recording can only start from a keyframe
+                            self.messages.push(Message::VideoStreamCtl {
+                                id,
+                                action: VideoStreamAction::RequestKeyframe,
+                            });
+                        }
+                        Err(e) => {
+                            error!("Failed to start recording stream {}:\n{:#?}", id, e);
+                            stream.local_recording_error = Some(format!("{:#}", e));
+                        }
+                    }
+                }
+            }
+            CameraViewerEvent::StopLocalRecording(id) => {
+                if let Some(recording) = self
+                    .stream_by_id(id)
+                    .and_then(|stream| stream.local_recording.take())
+                {
+                    finish_local_recording(recording);
+                    self.player.feed_event(PlayerEvent::RefreshRecordings);
+                }
+            }
+            CameraViewerEvent::Player(event) => {
+                self.player.feed_event(event);
+            }
+            CameraViewerEvent::PacketSizeInputChange(new) => {
+                self.packet_size_input_text = new;
+            }
+            CameraViewerEvent::QualityInputChange(new) => {
+                self.quality_input_text = new;
+            }
+            CameraViewerEvent::KeyframeIntervalInputChange(new) => {
+                self.keyframe_interval_input_text = new;
+            }
+            CameraViewerEvent::MaxFpsInputChange(new) => {
+                self.max_fps_input_text = new;
+            }
+            CameraViewerEvent::AdaptiveToggled(adaptive) => {
+                self.adaptive = adaptive;
+            }
+            CameraViewerEvent::LatestFrameOnlyToggled(latest_frame_only) => {
+                self.latest_frame_only = latest_frame_only;
+            }
             CameraViewerEvent::CreateStream => {
-                if let Ok(id) = self.new_stream_input_text.parse::<usize>() {
+                if let (Some(CameraOption(camera)), Some(settings)) =
+                    (&self.selected_camera, self.encoder_settings())
+                {
                     let uuid = Uuid::new_v4();
-                    self.new_stream_input_text.clear();
-                    self.register_stream(uuid);
+                    let dev = CameraDevice::Index(camera.index);
+                    let format = self.selected_format.0;
+                    self.register_stream(uuid, dev.clone());
                     self.messages.push(Message::VideoStreamCtl {
                         id: uuid,
-                        action: VideoStreamAction::Init { dev: id },
+                        action: VideoStreamAction::Init {
+                            dev,
+                            format,
+                            settings,
+                        },
                     });
+                    self.messages.push(Message::ListCameras);
                 }
             }
-            CameraViewerEvent::StreamIDInputChange(new) => {
-                self.new_stream_input_text = new;
+            CameraViewerEvent::CreateTestPattern => {
+                let settings = match self.encoder_settings() {
+                    Some(settings) => settings,
+                    None => return,
+                };
+                let uuid = Uuid::new_v4();
+                let dev = CameraDevice::TestPattern(TestPattern {
+                    kind: TestPatternKind::ColorBars,
+                    width: 640,
+                    height: 480,
+                    fps: 30,
+                });
+                self.register_stream(uuid, dev.clone());
+                self.messages.push(Message::VideoStreamCtl {
+                    id: uuid,
+                    action: VideoStreamAction::Init {
+                        dev,
+                        format: None,
+                        settings,
+                    },
+                });
+            }
+            CameraViewerEvent::CameraSelected(camera) => {
+                self.messages.push(Message::ListCameraFormats {
+                    index: camera.0.index,
+                });
+                self.selected_camera = Some(camera);
+                self.set_formats(vec![]);
+            }
+            CameraViewerEvent::FormatSelected(format) => {
+                self.selected_format = format;
+            }
+            CameraViewerEvent::RefreshCameras => {
+                self.messages.push(Message::ListCameras);
             }
         }
     }
@@ -198,14 +676,169 @@ impl CameraViewer {
         &mut self.messages
     }
 
+    /// updates the cameras in the camera picker, keeping the selected camera if it is still there
+    pub fn set_cameras(&mut self, cameras: Vec<CameraListing>) {
+        self.cameras = cameras.into_iter().map(CameraOption).collect();
+        self.selected_camera = self
+            .selected_camera
+            .take()
+            .and_then(|CameraOption(selected)| {
+                self.cameras
+                    .iter()
+                    .find(|camera| camera.0.index == selected.index)
+                    .cloned()
+            });
+    }
+
+    /// updates the formats in the format picker, if they are for the selected camera
+    pub fn set_camera_formats(&mut self, index: usize, formats: Result<Vec<CameraFormat>, String>) {
+        if self.selected_camera.as_ref().map(|camera| camera.0.index) != Some(index) {
+            return;
+        }
+        match formats {
+            Ok(formats) => self.set_formats(formats),
+            Err(message) => {
+                warn!(
+                    "Failed to read the formats of camera {}: {}",
+                    index, message
+                );
+                self.set_formats(vec![]);
+                self.formats_error = Some(message);
+            }
+        }
+    }
+
+    fn set_formats(&mut self, formats: Vec<CameraFormat>) {
+        self.formats = std::iter::once(FormatOption(None))
+            .chain(formats.into_iter().map(Some).map(FormatOption))
+            .collect();
+        if !self.formats.contains(&self.selected_format) {
+            self.selected_format = FormatOption(None);
+        }
+        self.formats_error = None;
+    }
+
+    /// starts decoding the stream over from a keyframe
+    pub fn restart_decoder(&mut self, id: Uuid) {
+        if let Some(stream) = self.stream_by_id(id) {
+            stream.decoder = Decoder::new();
+            stream.fragments.clear();
+            stream.record_locally(StreamRecording::keyframe);
+        }
+    }
+
+    /// if a recording is being played back, and [`CameraViewer::playback_tick`] needs to be called
+    pub fn playing(&self) -> bool {
+        self.player.playing()
+    }
+
+    pub fn playback_tick(&mut self, now: Instant) {
+        self.player.tick(now);
+    }
+
+    pub fn set_operating_point(&mut self, id: Uuid, point: OperatingPoint) {
+        if let Some(stream) = self.stream_by_id(id) {
+            stream.operating_point = point;
+        }
+    }
+
+    pub fn set_state(&mut self, id: Uuid, state: StreamState) {
+        if let Some(stream) = self.stream_by_id(id) {
+            if let StreamState::Streaming(_) = state {
+                stream.error = None;
+            }
+            stream.state = state;
+        }
+    }
+
+    pub fn set_error(&mut self, id: Uuid, error: String) {
+        if let Some(stream) = self.stream_by_id(id) {
+            stream.error = Some(error);
+        }
+    }
+
+    /// sets what is shown about the camera of a stream, `None` when it is working
+    pub fn set_camera_status(&mut self, id: Uuid, status: Option<String>) {
+        if let Some(stream) = self.stream_by_id(id) {
+            stream.camera_status = status;
+        }
+    }
+
+    pub fn set_snapshot_status(&mut self, id: Uuid, status: String) {
+        if let Some(stream) = self.stream_by_id(id) {
+            stream.snapshot_status = Some(status);
+        }
+    }
+
+    /// sets what is shown about the recording of a stream, `None` when it is not being recorded
+    pub fn set_recording_status(&mut self, id: Uuid, status: Option<String>) {
+        if let Some(stream) = self.stream_by_id(id) {
+            stream.recording_status = status;
+        }
+    }
+
+    /// adds a fragment of a packet, decoding the packet once it is complete
+    pub fn feed_fragment(&mut self, id: Uuid, data: Vec<u8>, last: bool) {
+        let packet = match self.stream_by_id(id) {
+            Some(stream) => {
+                stream.fragments.extend(data);
+                if !last {
+                    return;
+                }
+                let packet = bincode::DefaultOptions::new().deserialize(&stream.fragments);
+                stream.fragments.clear();
+                packet
+            }
+            None => return,
+        };
+        match packet {
+            Ok(packet) => self.feed_message(id, packet),
+            Err(e) => warn!("Failed to reassemble video packet for stream {}: {}", id, e),
+        }
+    }
+
     pub fn feed_message(&mut self, id: Uuid, packet: Packet) {
         if let Some(stream) = self.stream_by_id(id) {
+            if stream.local_recording.is_some() {
+                let recorded = packet.clone();
+                stream.record_locally(move |recording| recording.packet(recorded));
+            }
             stream.decoder.feed_packet(packet);
             if let Some(next_frame) = stream.decoder.frames().last() {
-                let bgr = DynamicImage::ImageRgb8(next_frame).into_bgra8();
-                stream.image_handle =
-                    IcedImageHandle::from_pixels(bgr.width(), bgr.height(), bgr.to_vec());
+                stream.image_handle = frame_handle(next_frame);
             }
         }
     }
 }
+
+impl VideoStream {
+    /// writes to the local recording if there is one, stopping it if that fails
+    fn record_locally(&mut self, write: impl FnOnce(&mut StreamRecording) -> anyhow::Result<()>) {
+        if let Some(recording) = &mut self.local_recording {
+            if let Err(e) = write(recording) {
+                error!("Recording stream {} failed:\n{:#?}", self.stream_id, e);
+                self.local_recording_error = Some(format!("{:#}", e));
+                self.local_recording = None;
+            }
+        }
+    }
+}
+
+fn finish_local_recording(recording: StreamRecording) {
+    let path = recording.path().to_owned();
+    match recording.finish() {
+        Ok(()) => info!("Finished recording {}", path.display()),
+        Err(e) => error!("Failed to finish recording {}:\n{:#?}", path.display(), e),
+    }
+}
+
+/// converts a decoded frame into something that can be shown
+pub fn frame_handle(frame: RgbImage) -> IcedImageHandle {
+    let bgr = DynamicImage::ImageRgb8(frame).into_bgra8();
+    IcedImageHandle::from_pixels(bgr.width(), bgr.height(), bgr.to_vec())
+}
+
+/// the text of an input that is left empty for `None`
+fn optional_text<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
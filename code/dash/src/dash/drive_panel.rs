@@ -0,0 +1,292 @@
+use aareocams_net::{
+    DriveAction, DriveInfo, LimitSource, Message, TravelLimit, Waypoint, WaypointAction,
+    WaypointTarget,
+};
+use iced::{button, text_input, Alignment, Button, Column, Length, Row, Text, TextInput};
+
+#[derive(Debug, Clone)]
+pub enum DrivePanelEvent {
+    /// go to the waypoint with some name
+    GoToWaypoint(String),
+    /// remove the waypoint with some name
+    RemoveWaypoint(String),
+    GoToPosition,
+    SaveWaypoint,
+    /// zero the position at the next index pulse of the encoder
+    ArmHoming,
+    PositionInputChange(String),
+    SpeedInputChange(String),
+    NameInputChange(String),
+}
+
+#[derive(Debug)]
+struct WaypointEntry {
+    waypoint: Waypoint,
+    go_btn: button::State,
+    remove_btn: button::State,
+}
+
+#[derive(Debug)]
+pub struct DrivePanel {
+    /// last known position in meters along the cable
+    position: Option<f64>,
+    velocity: f64,
+    target: Option<f64>,
+    tracking_error: f64,
+    /// last arrival or error, shown below the position
+    last_event: String,
+    /// travel limits that are currently reached (min, max)
+    limits: [Option<LimitSource>; 2],
+    waypoints: Vec<WaypointEntry>,
+    position_input_state: text_input::State,
+    position_input_text: String,
+    speed_input_state: text_input::State,
+    speed_input_text: String,
+    name_input_state: text_input::State,
+    name_input_text: String,
+    go_btn_state: button::State,
+    save_btn_state: button::State,
+    home_btn_state: button::State,
+    messages: Vec<Message>,
+}
+
+impl DrivePanel {
+    pub fn new() -> Self {
+        Self {
+            position: None,
+            velocity: 0.0,
+            target: None,
+            tracking_error: 0.0,
+            last_event: String::new(),
+            limits: [None, None],
+            waypoints: vec![],
+            position_input_state: text_input::State::new(),
+            position_input_text: String::new(),
+            speed_input_state: text_input::State::new(),
+            speed_input_text: String::new(),
+            name_input_state: text_input::State::new(),
+            name_input_text: String::new(),
+            go_btn_state: button::State::new(),
+            save_btn_state: button::State::new(),
+            home_btn_state: button::State::new(),
+            messages: vec![],
+        }
+    }
+
+    /// speed from the speed input, or `None` to use the bot's default
+    fn speed(&self) -> Option<f64> {
+        self.speed_input_text.parse::<f64>().ok()
+    }
+
+    pub fn view(&mut self) -> iced::Element<DrivePanelEvent> {
+        let status = match self.position {
+            Some(position) => format!(
+                "position: {:.3} m  velocity: {:.3} m/s  target: {}  error: {:.3}",
+                position,
+                self.velocity,
+                self.target
+                    .map_or("none".to_string(), |target| format!("{:.3} m", target)),
+                self.tracking_error,
+            ),
+            None => "position: unknown".to_string(),
+        };
+        let limits = match self.limits {
+            [None, None] => String::new(),
+            [min, max] => format!("at travel limit: min {:?}, max {:?}", min, max),
+        };
+
+        let controlls = Row::<'_, DrivePanelEvent>::new()
+            .align_items(Alignment::Center)
+            .height(Length::Shrink)
+            .spacing(2)
+            .push(
+                TextInput::new(
+                    &mut self.position_input_state,
+                    "position (m)",
+                    &self.position_input_text,
+                    DrivePanelEvent::PositionInputChange,
+                )
+                .width(Length::Units(100)),
+            )
+            .push(
+                TextInput::new(
+                    &mut self.speed_input_state,
+                    "speed (m/s)",
+                    &self.speed_input_text,
+                    DrivePanelEvent::SpeedInputChange,
+                )
+                .width(Length::Units(100)),
+            )
+            .push(
+                Button::new(&mut self.go_btn_state, Text::new("Go"))
+                    .on_press(DrivePanelEvent::GoToPosition),
+            )
+            .push(
+                TextInput::new(
+                    &mut self.name_input_state,
+                    "waypoint name",
+                    &self.name_input_text,
+                    DrivePanelEvent::NameInputChange,
+                )
+                .width(Length::Units(150)),
+            )
+            .push(
+                Button::new(&mut self.save_btn_state, Text::new("Save waypoint"))
+                    .on_press(DrivePanelEvent::SaveWaypoint),
+            )
+            .push(
+                Button::new(&mut self.home_btn_state, Text::new("Home"))
+                    .on_press(DrivePanelEvent::ArmHoming),
+            );
+
+        let mut waypoints = Column::<'_, DrivePanelEvent>::new()
+            .height(Length::Shrink)
+            .spacing(2);
+        for entry in &mut self.waypoints {
+            waypoints = waypoints.push(
+                Row::new()
+                    .align_items(Alignment::Center)
+                    .height(Length::Shrink)
+                    .spacing(2)
+                    .push(Text::new(format!(
+                        "{} ({:.3} m)",
+                        entry.waypoint.name, entry.waypoint.position
+                    )))
+                    .push(
+                        Button::new(&mut entry.go_btn, Text::new("Go"))
+                            .on_press(DrivePanelEvent::GoToWaypoint(entry.waypoint.name.clone())),
+                    )
+                    .push(
+                        Button::new(&mut entry.remove_btn, Text::new("Remove"))
+                            .on_press(DrivePanelEvent::RemoveWaypoint(entry.waypoint.name.clone())),
+                    ),
+            );
+        }
+
+        Column::new()
+            .height(Length::Shrink)
+            .width(Length::Shrink)
+            .padding(5)
+            .spacing(5)
+            .push(Text::new(status))
+            .push(Text::new(limits))
+            .push(Text::new(&self.last_event))
+            .push(controlls)
+            .push(waypoints)
+            .into()
+    }
+
+    pub fn feed_event(&mut self, event: DrivePanelEvent) {
+        match event {
+            DrivePanelEvent::GoToWaypoint(name) => {
+                self.messages.push(Message::Drive(DriveAction::GoTo {
+                    target: WaypointTarget::Named(name),
+                    speed: self.speed(),
+                }));
+            }
+            DrivePanelEvent::RemoveWaypoint(name) => {
+                self.messages
+                    .push(Message::WaypointCtl(WaypointAction::Remove { name }));
+            }
+            DrivePanelEvent::GoToPosition => {
+                if let Ok(position) = self.position_input_text.parse::<f64>() {
+                    self.messages.push(Message::Drive(DriveAction::GoTo {
+                        target: WaypointTarget::Position(position),
+                        speed: self.speed(),
+                    }));
+                }
+            }
+            DrivePanelEvent::SaveWaypoint => {
+                // use the position input if there is one, otherwise where the bot is now
+                let position = match self.position_input_text.parse::<f64>() {
+                    Ok(position) => Some(position),
+                    Err(..) => self.position,
+                };
+                if let (false, Some(position)) = (self.name_input_text.is_empty(), position) {
+                    self.messages
+                        .push(Message::WaypointCtl(WaypointAction::Set(Waypoint {
+                            name: self.name_input_text.clone(),
+                            position,
+                        })));
+                    self.name_input_text.clear();
+                }
+            }
+            DrivePanelEvent::ArmHoming => {
+                self.messages.push(Message::Drive(DriveAction::ArmHoming));
+                self.last_event =
+                    "homing: drive past the index mark, the position will be zero there"
+                        .to_string();
+            }
+            DrivePanelEvent::PositionInputChange(new) => {
+                self.position_input_text = new;
+            }
+            DrivePanelEvent::SpeedInputChange(new) => {
+                self.speed_input_text = new;
+            }
+            DrivePanelEvent::NameInputChange(new) => {
+                self.name_input_text = new;
+            }
+        }
+    }
+
+    pub fn messages(&mut self) -> &mut Vec<Message> {
+        &mut self.messages
+    }
+
+    pub fn feed_info(&mut self, info: DriveInfo) {
+        match info {
+            DriveInfo::Status {
+                position,
+                velocity,
+                target,
+                tracking_error,
+            } => {
+                self.position = Some(position);
+                self.velocity = velocity;
+                self.target = target;
+                self.tracking_error = tracking_error;
+            }
+            DriveInfo::Arrived { position } => {
+                self.last_event = format!("arrived at {:.3} m", position);
+            }
+            DriveInfo::GoToError { message } => {
+                warn!("Failed to go to waypoint: {}", message);
+                self.last_event = message;
+            }
+            DriveInfo::LimitReached { limit, source } => {
+                warn!("Reached travel limit {:?} ({:?})", limit, source);
+                self.limits[limit_index(limit)] = Some(source);
+                self.last_event = format!("reached {:?} travel limit ({:?})", limit, source);
+            }
+            DriveInfo::LimitCleared { limit } => {
+                self.limits[limit_index(limit)] = None;
+                self.last_event = format!("cleared {:?} travel limit", limit);
+            }
+            DriveInfo::MotionRefused { limit } => {
+                self.last_event = format!("refused to move past {:?} travel limit", limit);
+            }
+            DriveInfo::WaypointError { message } => {
+                warn!("Failed to change the waypoints: {}", message);
+                self.last_event = message;
+            }
+        }
+    }
+
+    pub fn set_waypoints(&mut self, waypoints: Vec<Waypoint>) {
+        self.waypoints = waypoints
+            .into_iter()
+            .map(|waypoint| WaypointEntry {
+                waypoint,
+                go_btn: button::State::new(),
+                remove_btn: button::State::new(),
+            })
+            .collect();
+    }
+}
+
+fn limit_index(limit: TravelLimit) -> usize {
+    match limit {
+        TravelLimit::Min => 0,
+        TravelLimit::Max => 1,
+    }
+}
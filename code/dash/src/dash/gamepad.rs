@@ -0,0 +1,109 @@
+use std::any::TypeId;
+
+use iced_native::subscription::{self, Subscription};
+use sn30pro::Controller;
+
+use crate::config::GamepadButton;
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// a button was pressed, what it does is looked up in the gamepad config
+    Pressed(GamepadButton),
+    /// the gamepad could not be opened or read, it is not used after this
+    Error(String),
+}
+
+enum State {
+    Closed(usize),
+    Open {
+        controller: Controller,
+        /// buttons held down that were already reported
+        reported: Vec<GamepadButton>,
+    },
+    Failed,
+}
+
+fn is_pressed(controller: &mut Controller, button: GamepadButton) -> bool {
+    match button {
+        GamepadButton::A => controller.btn_pad().a.pressed(),
+        GamepadButton::B => controller.btn_pad().b.pressed(),
+        GamepadButton::X => controller.btn_pad().x.pressed(),
+        GamepadButton::Y => controller.btn_pad().y.pressed(),
+        GamepadButton::L1 => controller.triggers().l1.pressed(),
+        GamepadButton::L2 => controller.triggers().l2.pressed(),
+        GamepadButton::R1 => controller.triggers().r1.pressed(),
+        GamepadButton::R2 => controller.triggers().r2.pressed(),
+        GamepadButton::Start => controller.start().pressed(),
+        GamepadButton::Select => controller.select().pressed(),
+        GamepadButton::Heart => controller.heart().pressed(),
+        GamepadButton::Up => controller.d_pad().up(),
+        GamepadButton::Down => controller.d_pad().down(),
+        GamepadButton::Left => controller.d_pad().left(),
+        GamepadButton::Right => controller.d_pad().right(),
+    }
+}
+
+/// presses of the buttons of the gamepad at `/dev/input/js<port>`
+pub fn events(port: usize) -> Subscription<Event> {
+    struct ID;
+
+    subscription::unfold(
+        (TypeId::of::<ID>(), port),
+        State::Closed(port),
+        |state: State| async move {
+            match state {
+                State::Closed(port) => match Controller::new(port).await {
+                    Ok(controller) => {
+                        info!("Opened the gamepad at /dev/input/js{}", port);
+                        (
+                            None,
+                            State::Open {
+                                controller,
+                                reported: vec![],
+                            },
+                        )
+                    }
+                    Err(open_error) => (
+                        Some(Event::Error(format!(
+                            "Failed to open the gamepad at /dev/input/js{}: {}",
+                            port, open_error
+                        ))),
+                        State::Failed,
+                    ),
+                },
+                State::Open {
+                    mut controller,
+                    mut reported,
+                } => loop {
+                    let pressed = GamepadButton::ALL
+                        .into_iter()
+                        .filter(|&button| is_pressed(&mut controller, button))
+                        .collect::<Vec<_>>();
+                    reported.retain(|button| pressed.contains(button));
+                    // one at a time, if more than one button was pressed at once
+                    if let Some(&button) = pressed.iter().find(|button| !reported.contains(button))
+                    {
+                        reported.push(button);
+                        return (
+                            Some(Event::Pressed(button)),
+                            State::Open {
+                                controller,
+                                reported,
+                            },
+                        );
+                    }
+                    if let Err(read_error) = controller.update().await {
+                        return (
+                            Some(Event::Error(format!(
+                                "Failed to read the gamepad: {}",
+                                read_error
+                            ))),
+                            State::Failed,
+                        );
+                    }
+                },
+                State::Failed => iced::futures::future::pending().await,
+            }
+        },
+    )
+}
@@ -1,10 +1,11 @@
-use aareocams_net::DriveAction;
 use iced_native::{
     event::{Event as IcedEvent, Status},
     keyboard::{Event as KeyEvent, KeyCode, Modifiers},
     subscription, Subscription,
 };
 
+use crate::config::KeyBinding;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyChange {
     Press,
@@ -13,16 +14,21 @@ pub enum KeyChange {
 
 #[derive(Debug, Clone)]
 pub enum Event {
-    Drive(DriveAction),
+    /// a key was pressed, what it does is looked up in the keybindings from the config
+    Pressed(KeyBinding),
 }
 
 fn handle_event(code: KeyCode, mods: Modifiers, change: KeyChange) -> Option<Event> {
     println!("{:?} {:?} {:?}", code, mods, change);
-    match (code, mods, change) {
-        (KeyCode::W, _, KeyChange::Press) => return Some(Event::Drive(DriveAction::Fwd)),
-        (KeyCode::S, _, KeyChange::Press) => return Some(Event::Drive(DriveAction::Rev)),
-        (KeyCode::A, _, KeyChange::Press) => return Some(Event::Drive(DriveAction::Stop)),
-        _ => None,
+    match change {
+        KeyChange::Press => Some(Event::Pressed(KeyBinding {
+            ctrl: mods.control(),
+            alt: mods.alt(),
+            shift: mods.shift(),
+            // the names of keys in the config are the names of the variants of `KeyCode`
+            key: format!("{:?}", code).to_lowercase(),
+        })),
+        KeyChange::Release => None,
     }
 }
 
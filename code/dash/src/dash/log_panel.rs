@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+
+use aareocams_net::{LogAction, LogLevel, LogRecord, Message};
+use iced::{
+    button, pick_list, scrollable, text_input, Alignment, Button, Column, Length, PickList, Row,
+    Scrollable, Text, TextInput,
+};
+
+#[derive(Debug, Clone)]
+pub enum LogPanelEvent {
+    /// ask the bot to forward records at some level
+    RemoteLevelSelected(LogLevel),
+    /// only show records at some level or more severe
+    ShownLevelSelected(LogLevel),
+    SearchInputChange(String),
+    Clear,
+}
+
+/// shows the log records forwarded by the bot
+#[derive(Debug)]
+pub struct LogPanel {
+    /// oldest first
+    records: VecDeque<LogRecord>,
+    /// records kept before the oldest ones are dropped
+    max_records: usize,
+    remote_level: LogLevel,
+    remote_level_pick_state: pick_list::State<LogLevel>,
+    shown_level: LogLevel,
+    shown_level_pick_state: pick_list::State<LogLevel>,
+    /// only records with this in their message or target are shown
+    search_input_state: text_input::State,
+    search_input_text: String,
+    clear_btn_state: button::State,
+    scroll_state: scrollable::State,
+    messages: Vec<Message>,
+}
+
+impl LogPanel {
+    /// `remote_level` is what the bot is asked to forward when connecting to it
+    pub fn new(max_records: usize, remote_level: LogLevel) -> Self {
+        Self {
+            records: VecDeque::new(),
+            max_records,
+            remote_level,
+            remote_level_pick_state: pick_list::State::default(),
+            shown_level: LogLevel::Trace,
+            shown_level_pick_state: pick_list::State::default(),
+            search_input_state: text_input::State::new(),
+            search_input_text: String::new(),
+            clear_btn_state: button::State::new(),
+            scroll_state: scrollable::State::new(),
+            messages: vec![],
+        }
+    }
+
+    /// asks the bot for the records at the selected level, such as when connecting to it
+    pub fn subscribe(&mut self) {
+        self.messages
+            .push(Message::LogCtl(LogAction::SetLevel(self.remote_level)));
+    }
+
+    pub fn view(&mut self) -> iced::Element<LogPanelEvent> {
+        let controlls = Row::<'_, LogPanelEvent>::new()
+            .align_items(Alignment::Center)
+            .height(Length::Shrink)
+            .spacing(2)
+            .push(Text::new("forward"))
+            .push(PickList::new(
+                &mut self.remote_level_pick_state,
+                &LogLevel::ALL[..],
+                Some(self.remote_level),
+                LogPanelEvent::RemoteLevelSelected,
+            ))
+            .push(Text::new("show"))
+            .push(PickList::new(
+                &mut self.shown_level_pick_state,
+                &LogLevel::ALL[..],
+                Some(self.shown_level),
+                LogPanelEvent::ShownLevelSelected,
+            ))
+            .push(
+                TextInput::new(
+                    &mut self.search_input_state,
+                    "search",
+                    &self.search_input_text,
+                    LogPanelEvent::SearchInputChange,
+                )
+                .width(Length::Units(200)),
+            )
+            .push(
+                Button::new(&mut self.clear_btn_state, Text::new("Clear"))
+                    .on_press(LogPanelEvent::Clear),
+            );
+
+        let search = self.search_input_text.to_lowercase();
+        let mut records = Scrollable::new(&mut self.scroll_state)
+            .height(Length::Units(200))
+            .width(Length::Fill)
+            .spacing(1);
+        for record in self.records.iter().filter(|record| {
+            record.level <= self.shown_level
+                && (search.is_empty()
+                    || record.message.to_lowercase().contains(&search)
+                    || record.target.to_lowercase().contains(&search))
+        }) {
+            records = records.push(Text::new(format!(
+                "{} {:5} {}: {}",
+                time_of_day(record.time),
+                record.level,
+                record.target,
+                record.message
+            )));
+        }
+
+        Column::new()
+            .height(Length::Shrink)
+            .padding(5)
+            .spacing(5)
+            .push(controlls)
+            .push(records)
+            .into()
+    }
+
+    pub fn feed_event(&mut self, event: LogPanelEvent) {
+        match event {
+            LogPanelEvent::RemoteLevelSelected(level) => {
+                self.remote_level = level;
+                self.subscribe();
+            }
+            LogPanelEvent::ShownLevelSelected(level) => {
+                self.shown_level = level;
+            }
+            LogPanelEvent::SearchInputChange(new) => {
+                self.search_input_text = new;
+            }
+            LogPanelEvent::Clear => {
+                self.records.clear();
+            }
+        }
+    }
+
+    pub fn messages(&mut self) -> &mut Vec<Message> {
+        &mut self.messages
+    }
+
+    pub fn feed_record(&mut self, record: LogRecord) {
+        if self.records.len() >= self.max_records {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+}
+
+/// `HH:MM:SS.mmm` (UTC) from milliseconds since the unix epoch
+fn time_of_day(millis: u64) -> String {
+    let seconds = millis / 1000;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60,
+        millis % 1000
+    )
+}
@@ -1,21 +1,41 @@
 mod camera_viewer;
+mod drive_panel;
+mod gamepad;
 mod keyboard;
+mod log_panel;
+mod player;
+mod recordings;
+mod telemetry_panel;
 
-use crate::stream::{self, StreamControllMsg};
-use aareocams_net::Message;
-use camera_viewer::{CameraViewer, CameraViewerEvent};
+use crate::{
+    config::{Conf, GamepadConf, InputAction, KeyBinding},
+    snapshot::save_snapshot,
+    stream::{self, StreamControllMsg},
+};
+use aareocams_net::{CloseReason, Message, RecordingAction, VideoStreamInfo, WaypointAction};
+use camera_viewer::{CameraViewer, CameraViewerEvent, StreamState};
+use drive_panel::{DrivePanel, DrivePanelEvent};
 use iced::{
     button::{self, Button},
     Application, Command, Subscription, Text,
 };
-use std::fmt::Debug;
+use log_panel::{LogPanel, LogPanelEvent};
+use recordings::{RecordingsPanel, RecordingsPanelEvent};
+use std::{collections::BTreeMap, fmt::Debug, path::PathBuf, time::Duration};
+use telemetry_panel::{TelemetryPanel, TelemetryPanelEvent};
 use tokio::net::ToSocketAddrs;
 
+/// how often playback of recordings is advanced
+const PLAYBACK_TICK: Duration = Duration::from_millis(10);
+
 #[derive(Debug)]
 pub enum GUIMsg<A: tokio::net::ToSocketAddrs + Debug> {
     Socket(stream::Event<A, Message>),
     Keyboard(keyboard::Event),
+    Gamepad(gamepad::Event),
     Interaction(Interaction),
+    /// time to show the next frame of the recording being played back
+    PlaybackTick(iced::time::Instant),
 }
 
 #[derive(Debug, Clone)]
@@ -23,6 +43,10 @@ pub enum Interaction {
     Connect,
     Disconnect,
     CameraStream(CameraViewerEvent),
+    Drive(DrivePanelEvent),
+    Recordings(RecordingsPanelEvent),
+    Telemetry(TelemetryPanelEvent),
+    Logs(LogPanelEvent),
 }
 
 pub struct GUIState {
@@ -44,6 +68,13 @@ where
     /// holds all communication elements with the stream subscription
     stream: Option<StreamInterface<A>>,
     streams: CameraViewer,
+    drive: DrivePanel,
+    recordings: RecordingsPanel,
+    telemetry: TelemetryPanel,
+    logs: LogPanel,
+    snapshot_dir: PathBuf,
+    keybindings: BTreeMap<KeyBinding, InputAction>,
+    gamepad: GamepadConf,
     /// the state for all GUI elements
     gui: GUIState,
     exit: bool,
@@ -54,20 +85,27 @@ where
     A: ToSocketAddrs + Clone + Sync + Debug + Send + 'static,
 {
     type Message = GUIMsg<A>;
-    /// (ip addr of bot, controller ID)
-    type Flags = (A, usize);
+    /// (ip addr of bot, configuration)
+    type Flags = (A, Conf);
     type Executor = iced::executor::Default;
 
-    fn new(flags: Self::Flags) -> (Self, Command<GUIMsg<A>>) {
+    fn new((addr, cfg): Self::Flags) -> (Self, Command<GUIMsg<A>>) {
         (
             Self {
-                addr: flags.0,
+                addr,
                 stream: None,
                 gui: GUIState {
                     connect: button::State::new(),
                     disconnect: button::State::new(),
                 },
-                streams: CameraViewer::new(),
+                streams: CameraViewer::new(cfg.recording_dir.clone(), cfg.streams),
+                drive: DrivePanel::new(),
+                recordings: RecordingsPanel::new(cfg.recording_dir),
+                telemetry: TelemetryPanel::new(),
+                logs: LogPanel::new(cfg.ui.log_records, cfg.ui.log_level),
+                snapshot_dir: cfg.snapshot_dir,
+                keybindings: cfg.keybindings,
+                gamepad: cfg.gamepad,
                 exit: false,
             },
             Command::none(),
@@ -75,10 +113,17 @@ where
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
-        Subscription::batch(vec![
+        let mut subscriptions = vec![
             stream::like_and_subscribe().map(GUIMsg::Socket),
             keyboard::events().map(GUIMsg::Keyboard),
-        ])
+        ];
+        if self.streams.playing() {
+            subscriptions.push(iced::time::every(PLAYBACK_TICK).map(GUIMsg::PlaybackTick));
+        }
+        if let Some(port) = self.gamepad.port {
+            subscriptions.push(gamepad::events(port).map(GUIMsg::Gamepad));
+        }
+        Subscription::batch(subscriptions)
     }
 
     fn title(&self) -> String {
@@ -110,14 +155,236 @@ where
                         Message::VideoStreamData { id, packet } => {
                             self.streams.feed_message(id, packet);
                         }
+                        Message::VideoStreamFragment { id, data, last } => {
+                            self.streams.feed_fragment(id, data, last);
+                        }
                         Message::VideoStreamCtl { .. } => unreachable!(),
-                        Message::VideoStreamInfo { id, action } => {
-                            //TODO properly handle video stream info messages
-                            info!("VideoStreamInfo: {}: {:?}", id, action);
+                        Message::ListCameras => unreachable!(),
+                        Message::Cameras(cameras) => {
+                            self.streams.set_cameras(cameras);
+                        }
+                        Message::ListCameraFormats { .. } => unreachable!(),
+                        Message::CameraFormats { index, formats } => {
+                            self.streams.set_camera_formats(index, formats);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::Opening,
+                        } => {
+                            self.streams.set_state(id, StreamState::Opening);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::Streaming(format),
+                        } => {
+                            info!("Stream {} is streaming {}", id, format);
+                            self.streams.set_state(id, StreamState::Streaming(format));
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::Paused,
+                        } => {
+                            self.streams.set_state(id, StreamState::Paused);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::Closed { reason },
+                        } => {
+                            info!("Stream {} was closed: {}", id, reason);
+                            self.streams.set_state(id, StreamState::Closed(reason));
+                            if reason != CloseReason::Requested {
+                                // the camera is free again
+                                self.stream
+                                    .as_ref()
+                                    .unwrap()
+                                    .msg_send
+                                    .send(Message::ListCameras)
+                                    .unwrap();
+                            }
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action:
+                                VideoStreamInfo::InitError { message }
+                                | VideoStreamInfo::OpenCamError { message },
+                        } => {
+                            warn!("Failed to open the camera of stream {}: {}", id, message);
+                            let error = format!("failed to open the camera: {}", message);
+                            self.streams.set_error(id, error);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::ReadError { message },
+                        } => {
+                            warn!("Failed to read a frame on stream {}: {}", id, message);
+                            let error = format!("failed to read a frame: {}", message);
+                            self.streams.set_error(id, error);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::EncodeError { message },
+                        } => {
+                            warn!("Failed to encode a frame on stream {}: {}", id, message);
+                            let error = format!("failed to encode a frame: {}", message);
+                            self.streams.set_error(id, error);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::CloseError { message },
+                        } => {
+                            warn!("Failed to stop the camera of stream {}: {}", id, message);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::Reconfigured(settings),
+                        } => {
+                            info!("Stream {} was reconfigured: {:?}", id, settings);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::ReconfigureError { message },
+                        } => {
+                            warn!("Failed to reconfigure stream {}: {}", id, message);
+                            let error = format!("failed to apply settings: {}", message);
+                            self.streams.set_error(id, error);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::Keyframe,
+                        } => {
+                            self.streams.restart_decoder(id);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::OperatingPoint(point),
+                        } => {
+                            self.streams.set_operating_point(id, point);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::Snapshot(snapshot),
+                        } => {
+                            let status = match save_snapshot(&self.snapshot_dir, id, &snapshot) {
+                                Ok(path) => {
+                                    info!("Saved snapshot {:?} to {}", snapshot, path.display());
+                                    format!("saved snapshot to {}", path.display())
+                                }
+                                Err(e) => {
+                                    error!("Failed to save snapshot:\n{:#?}", e);
+                                    format!("failed to save snapshot: {}", e)
+                                }
+                            };
+                            self.streams.set_snapshot_status(id, status);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::SnapshotError { message },
+                        } => {
+                            warn!("Failed to take snapshot on stream {}: {}", id, message);
+                            let status = format!("failed to take snapshot: {}", message);
+                            self.streams.set_snapshot_status(id, status);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::RecordingStarted { name },
+                        } => {
+                            info!("Recording stream {} to {} on the bot", id, name);
+                            let status = format!("recording to {}", name);
+                            self.streams.set_recording_status(id, Some(status));
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::RecordingStopped,
+                        } => {
+                            self.streams.set_recording_status(id, None);
+                            // there is a new recording to download
+                            self.stream
+                                .as_ref()
+                                .unwrap()
+                                .msg_send
+                                .send(Message::RecordingCtl(RecordingAction::List))
+                                .unwrap();
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::RecordingError { message },
+                        } => {
+                            warn!("Recording stream {} failed: {}", id, message);
+                            let status = format!("recording failed: {}", message);
+                            self.streams.set_recording_status(id, Some(status));
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::CameraLost { message },
+                        } => {
+                            warn!("Lost the camera of stream {}: {}", id, message);
+                            let status = format!("camera lost ({}), reopening it", message);
+                            self.streams.set_camera_status(id, Some(status));
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::CameraRecovered,
+                        } => {
+                            info!("Recovered the camera of stream {}", id);
+                            self.streams.set_camera_status(id, None);
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::CameraGaveUp { message },
+                        } => {
+                            error!("Gave up on the camera of stream {}: {}", id, message);
+                            let status = format!("gave up on the camera: {}", message);
+                            self.streams.set_camera_status(id, Some(status));
+                        }
+                        Message::VideoStreamInfo {
+                            id,
+                            action: VideoStreamInfo::NoSuchStream,
+                        } => {
+                            warn!("The bot has no stream {}, closing it", id);
+                            self.streams.close_stream(id);
                         }
                         Message::Drive(..) => unreachable!(),
+                        Message::DriveInfo(info) => {
+                            self.drive.feed_info(info);
+                        }
+                        Message::WaypointCtl(..) => unreachable!(),
+                        Message::Waypoints(waypoints) => {
+                            self.drive.set_waypoints(waypoints);
+                        }
+                        Message::RecordingCtl(..) => unreachable!(),
+                        Message::RecordingInfo(info) => {
+                            self.recordings.feed_info(info);
+                        }
+                        Message::TelemetryCtl(..) => unreachable!(),
+                        Message::Telemetry(telemetry) => {
+                            self.telemetry.feed_telemetry(telemetry);
+                        }
+                        Message::PowerInfo(info) => {
+                            self.telemetry.feed_power_info(info);
+                        }
+                        Message::LogCtl(..) => unreachable!(),
+                        Message::Log(record) => {
+                            self.logs.feed_record(record);
+                        }
                     },
-                    Event::ConnectedTo(_addr) => {}
+                    Event::ConnectedTo(_addr) => {
+                        let msg_send = &self.stream.as_ref().unwrap().msg_send;
+                        msg_send
+                            .send(Message::WaypointCtl(WaypointAction::List))
+                            .unwrap();
+                        msg_send.send(Message::ListCameras).unwrap();
+                        msg_send
+                            .send(Message::RecordingCtl(RecordingAction::List))
+                            .unwrap();
+                        self.telemetry.subscribe();
+                        for message in self.telemetry.messages().drain(..) {
+                            msg_send.send(message).unwrap();
+                        }
+                        self.logs.subscribe();
+                        for message in self.logs.messages().drain(..) {
+                            msg_send.send(message).unwrap();
+                        }
+                    }
                 }
             }
             GUIMsg::Interaction(interaction_event) => match interaction_event {
@@ -150,12 +417,83 @@ where
                             .unwrap();
                     }
                 }
-            },
-            GUIMsg::Keyboard(keyboard_event) => {
-                match keyboard_event {
-                    keyboard::Event::Drive(action) => self.stream.as_ref().unwrap().msg_send.send(Message::Drive(action)).unwrap()
+                Interaction::Drive(event) => {
+                    self.drive.feed_event(event);
+                    for message in self.drive.messages().drain(..) {
+                        self.stream
+                            .as_ref()
+                            .unwrap()
+                            .msg_send
+                            .send(message)
+                            .unwrap();
+                    }
+                }
+                Interaction::Recordings(event) => {
+                    self.recordings.feed_event(event);
+                    for message in self.recordings.messages().drain(..) {
+                        self.stream
+                            .as_ref()
+                            .unwrap()
+                            .msg_send
+                            .send(message)
+                            .unwrap();
+                    }
+                }
+                Interaction::Telemetry(event) => {
+                    self.telemetry.feed_event(event);
+                    for message in self.telemetry.messages().drain(..) {
+                        self.stream
+                            .as_ref()
+                            .unwrap()
+                            .msg_send
+                            .send(message)
+                            .unwrap();
+                    }
+                }
+                Interaction::Logs(event) => {
+                    self.logs.feed_event(event);
+                    for message in self.logs.messages().drain(..) {
+                        self.stream
+                            .as_ref()
+                            .unwrap()
+                            .msg_send
+                            .send(message)
+                            .unwrap();
+                    }
                 }
+            },
+            GUIMsg::PlaybackTick(now) => {
+                self.streams.playback_tick(now);
             }
+            GUIMsg::Keyboard(keyboard_event) => match keyboard_event {
+                keyboard::Event::Pressed(binding) => {
+                    if let Some(action) = self.keybindings.get(&binding) {
+                        match &self.stream {
+                            Some(stream) => stream
+                                .msg_send
+                                .send(Message::Drive(action.clone().into()))
+                                .unwrap(),
+                            None => debug!("Not connected, ignoring {:?}", action),
+                        }
+                    }
+                }
+            },
+            GUIMsg::Gamepad(gamepad_event) => match gamepad_event {
+                gamepad::Event::Pressed(button) => {
+                    if let Some(action) = self.gamepad.buttons.get(&button) {
+                        match &self.stream {
+                            Some(stream) => stream
+                                .msg_send
+                                .send(Message::Drive(action.clone().into()))
+                                .unwrap(),
+                            None => debug!("Not connected, ignoring {:?}", action),
+                        }
+                    }
+                }
+                gamepad::Event::Error(message) => {
+                    error!("{}", message);
+                }
+            },
         }
         Command::none()
     }
@@ -174,7 +512,11 @@ where
                 Button::new(&mut self.gui.disconnect, Text::new("disconnect"))
                     .on_press(Interaction::Disconnect),
             )
+            .push(self.drive.view().map(Interaction::Drive))
             .push(self.streams.view().map(Interaction::CameraStream))
+            .push(self.recordings.view().map(Interaction::Recordings))
+            .push(self.telemetry.view().map(Interaction::Telemetry))
+            .push(self.logs.view().map(Interaction::Logs))
             .into();
         root.map(Self::Message::Interaction)
     }
@@ -0,0 +1,349 @@
+use std::{path::PathBuf, time::Instant};
+
+use aareocams_net::record::RecordEntry;
+use iced::{
+    button,
+    image::{Handle as IcedImageHandle, Image as IcedImage},
+    pick_list, slider, Alignment, Button, Column, Length, PickList, Row, Slider, Text,
+};
+use image::RgbImage;
+use lvenc::Decoder;
+
+use super::camera_viewer::frame_handle;
+use crate::recording::{list_recordings, load_recording};
+
+#[derive(Debug, Clone)]
+pub enum PlayerEvent {
+    RefreshRecordings,
+    RecordingSelected(String),
+    /// open the selected recording
+    Open,
+    PlayPause,
+    /// pause, and show the next frame
+    Step,
+    /// go to some time in the recording, in milliseconds
+    Seek(u32),
+    Close,
+}
+
+/// a recording that is open in the player
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct Playback {
+    name: String,
+    #[derivative(Debug = "ignore")]
+    entries: Vec<RecordEntry>,
+    /// index of the next entry to decode
+    next: usize,
+    #[derivative(Debug = "ignore")]
+    decoder: Decoder,
+    /// milliseconds into the recording
+    time: u64,
+    image_handle: IcedImageHandle,
+    /// when playing was started or last seeked, and the time in the recording then
+    playing: Option<(Instant, u64)>,
+}
+
+impl Playback {
+    fn new(name: String, entries: Vec<RecordEntry>) -> Self {
+        let mut playback = Self {
+            name,
+            entries,
+            next: 0,
+            decoder: Decoder::new(),
+            time: 0,
+            image_handle: IcedImageHandle::from_pixels(0, 0, vec![]),
+            playing: None,
+        };
+        playback.step();
+        playback
+    }
+
+    /// length of the recording, in milliseconds
+    fn duration(&self) -> u64 {
+        self.entries.last().map_or(0, RecordEntry::time)
+    }
+
+    fn at_end(&self) -> bool {
+        self.next >= self.entries.len()
+    }
+
+    /// decodes the next entry, returning the frame it completed if there is one
+    fn decode_next(&mut self) -> Option<RgbImage> {
+        let entry = self.entries.get(self.next)?.clone();
+        self.next += 1;
+        match entry {
+            RecordEntry::Keyframe { .. } => {
+                self.decoder = Decoder::new();
+                None
+            }
+            RecordEntry::Packet { packet, .. } => {
+                self.decoder.feed_packet(packet);
+                self.decoder.frames().last()
+            }
+        }
+    }
+
+    /// decodes everything up to `time`, showing the last frame
+    fn decode_until(&mut self, time: u64) {
+        let mut last_frame = None;
+        while self
+            .entries
+            .get(self.next)
+            .map_or(false, |entry| entry.time() <= time)
+        {
+            if let Some(frame) = self.decode_next() {
+                last_frame = Some(frame);
+            }
+        }
+        if let Some(frame) = last_frame {
+            self.image_handle = frame_handle(frame);
+        }
+        self.time = time;
+    }
+
+    /// shows the frame at `time`, decoding from the keyframe before it
+    fn seek(&mut self, time: u64) {
+        self.next = self
+            .entries
+            .iter()
+            .rposition(|entry| {
+                matches!(entry, RecordEntry::Keyframe { .. }) && entry.time() <= time
+            })
+            .unwrap_or(0);
+        self.decoder = Decoder::new();
+        self.decode_until(time);
+        if self.playing.is_some() {
+            self.playing = Some((Instant::now(), time));
+        }
+    }
+
+    /// shows the next frame
+    fn step(&mut self) {
+        while !self.at_end() {
+            let time = self.entries[self.next].time();
+            if let Some(frame) = self.decode_next() {
+                self.image_handle = frame_handle(frame);
+                self.time = time;
+                break;
+            }
+        }
+    }
+
+    fn play_pause(&mut self) {
+        if self.playing.take().is_none() {
+            if self.at_end() {
+                self.seek(0);
+            }
+            self.playing = Some((Instant::now(), self.time));
+        }
+    }
+
+    fn tick(&mut self, now: Instant) {
+        if let Some((started, start_time)) = self.playing {
+            let time = start_time + now.saturating_duration_since(started).as_millis() as u64;
+            self.decode_until(time.min(self.duration()));
+            if self.at_end() {
+                self.playing = None;
+            }
+        }
+    }
+}
+
+/// plays back recordings from the recording directory, whether they were made on the bot or here
+#[derive(Debug)]
+pub struct Player {
+    /// where recordings are found
+    dir: PathBuf,
+    recordings: Vec<String>,
+    selected: Option<String>,
+    recording_pick_state: pick_list::State<String>,
+    refresh_btn_state: button::State,
+    open_btn_state: button::State,
+    play_btn_state: button::State,
+    step_btn_state: button::State,
+    close_btn_state: button::State,
+    seek_state: slider::State,
+    playback: Option<Playback>,
+    /// why the last recording could not be listed or opened
+    status: String,
+}
+
+impl Player {
+    pub fn new(dir: PathBuf) -> Self {
+        let mut player = Self {
+            dir,
+            recordings: vec![],
+            selected: None,
+            recording_pick_state: pick_list::State::default(),
+            refresh_btn_state: button::State::new(),
+            open_btn_state: button::State::new(),
+            play_btn_state: button::State::new(),
+            step_btn_state: button::State::new(),
+            close_btn_state: button::State::new(),
+            seek_state: slider::State::new(),
+            playback: None,
+            status: String::new(),
+        };
+        player.refresh();
+        player
+    }
+
+    fn refresh(&mut self) {
+        match list_recordings(&self.dir) {
+            Ok(recordings) => {
+                self.recordings = recordings;
+                self.status.clear();
+            }
+            Err(e) => {
+                error!(
+                    "Failed to list recordings in {}:\n{:#?}",
+                    self.dir.display(),
+                    e
+                );
+                self.status = format!("failed to list recordings: {}", e);
+            }
+        }
+    }
+
+    /// if a recording is playing, and needs [`Player::tick`] to be called
+    pub fn playing(&self) -> bool {
+        self.playback
+            .as_ref()
+            .map_or(false, |playback| playback.playing.is_some())
+    }
+
+    /// advances playback to `now`
+    pub fn tick(&mut self, now: Instant) {
+        if let Some(playback) = &mut self.playback {
+            playback.tick(now);
+        }
+    }
+
+    pub fn view(&mut self) -> iced::Element<PlayerEvent> {
+        let mut root = Column::<'_, PlayerEvent>::new()
+            .height(Length::Shrink)
+            .width(Length::Shrink)
+            .spacing(5)
+            .push(
+                Row::new()
+                    .align_items(Alignment::Center)
+                    .height(Length::Shrink)
+                    .spacing(2)
+                    .push(
+                        PickList::new(
+                            &mut self.recording_pick_state,
+                            &self.recordings[..],
+                            self.selected.clone(),
+                            PlayerEvent::RecordingSelected,
+                        )
+                        .placeholder("recording")
+                        .width(Length::Units(200)),
+                    )
+                    .push(
+                        Button::new(&mut self.refresh_btn_state, Text::new("Refresh"))
+                            .on_press(PlayerEvent::RefreshRecordings),
+                    )
+                    .push(
+                        Button::new(&mut self.open_btn_state, Text::new("Open"))
+                            .on_press(PlayerEvent::Open),
+                    ),
+            )
+            .push(Text::new(&self.status));
+
+        if let Some(playback) = &mut self.playback {
+            let duration = playback.duration();
+            root = root
+                .push(Text::new(&playback.name))
+                .push(
+                    IcedImage::new(playback.image_handle.clone())
+                        .height(Length::Shrink)
+                        .content_fit(iced::ContentFit::Contain),
+                )
+                .push(
+                    Row::new()
+                        .align_items(Alignment::Center)
+                        .height(Length::Shrink)
+                        .spacing(2)
+                        .push(
+                            Button::new(
+                                &mut self.play_btn_state,
+                                Text::new(if playback.playing.is_some() {
+                                    "Pause"
+                                } else {
+                                    "Play"
+                                }),
+                            )
+                            .on_press(PlayerEvent::PlayPause),
+                        )
+                        .push(
+                            Button::new(&mut self.step_btn_state, Text::new("Step"))
+                                .on_press(PlayerEvent::Step),
+                        )
+                        .push(
+                            Button::new(&mut self.close_btn_state, Text::new("Close"))
+                                .on_press(PlayerEvent::Close),
+                        )
+                        .push(Text::new(format!(
+                            "{:.1} / {:.1} s",
+                            playback.time as f64 / 1000.0,
+                            duration as f64 / 1000.0
+                        ))),
+                )
+                .push(
+                    Slider::new(
+                        &mut self.seek_state,
+                        0..=duration as u32,
+                        playback.time as u32,
+                        PlayerEvent::Seek,
+                    )
+                    .width(Length::Units(400)),
+                );
+        }
+
+        root.into()
+    }
+
+    pub fn feed_event(&mut self, event: PlayerEvent) {
+        match event {
+            PlayerEvent::RefreshRecordings => self.refresh(),
+            PlayerEvent::RecordingSelected(name) => {
+                self.selected = Some(name);
+            }
+            PlayerEvent::Open => {
+                if let Some(name) = self.selected.clone() {
+                    let path = self.dir.join(&name);
+                    match load_recording(&path) {
+                        Ok(entries) => {
+                            self.playback = Some(Playback::new(name, entries));
+                            self.status.clear();
+                        }
+                        Err(e) => {
+                            error!("Failed to open recording {}:\n{:#?}", path.display(), e);
+                            self.status = format!("failed to open {}: {}", name, e);
+                        }
+                    }
+                }
+            }
+            PlayerEvent::PlayPause => {
+                if let Some(playback) = &mut self.playback {
+                    playback.play_pause();
+                }
+            }
+            PlayerEvent::Step => {
+                if let Some(playback) = &mut self.playback {
+                    playback.playing = None;
+                    playback.step();
+                }
+            }
+            PlayerEvent::Seek(time) => {
+                if let Some(playback) = &mut self.playback {
+                    playback.seek(time.into());
+                }
+            }
+            PlayerEvent::Close => {
+                self.playback = None;
+            }
+        }
+    }
+}
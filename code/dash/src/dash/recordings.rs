@@ -0,0 +1,214 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use aareocams_net::{Message, RecordingAction, RecordingInfo, RecordingListing};
+use iced::{button, Alignment, Button, Column, Length, Row, Text};
+
+#[derive(Debug, Clone)]
+pub enum RecordingsPanelEvent {
+    /// ask the bot for the list of recordings again
+    Refresh,
+    /// download the recording with some name
+    Download(String),
+    /// delete the recording with some name from the bot
+    Delete(String),
+}
+
+#[derive(Debug)]
+struct RecordingEntry {
+    recording: RecordingListing,
+    download_btn: button::State,
+    delete_btn: button::State,
+}
+
+/// a recording being downloaded
+#[derive(Debug)]
+struct Download {
+    name: String,
+    path: PathBuf,
+    file: BufWriter<File>,
+    received: u64,
+}
+
+/// lists the recordings on the bot, and downloads them
+#[derive(Debug)]
+pub struct RecordingsPanel {
+    /// where downloaded recordings are saved
+    dir: PathBuf,
+    recordings: Vec<RecordingEntry>,
+    downloads: Vec<Download>,
+    /// last download or error, shown above the list
+    status: String,
+    refresh_btn_state: button::State,
+    messages: Vec<Message>,
+}
+
+impl RecordingsPanel {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            recordings: vec![],
+            downloads: vec![],
+            status: String::new(),
+            refresh_btn_state: button::State::new(),
+            messages: vec![],
+        }
+    }
+
+    pub fn view(&mut self) -> iced::Element<RecordingsPanelEvent> {
+        let mut recordings = Column::<'_, RecordingsPanelEvent>::new()
+            .height(Length::Shrink)
+            .spacing(2);
+        for entry in &mut self.recordings {
+            let downloading = self
+                .downloads
+                .iter()
+                .find(|download| download.name == entry.recording.name);
+            let size = match downloading {
+                Some(download) => format!(
+                    "{:.1} / {:.1} MB",
+                    download.received as f64 / 1e6,
+                    entry.recording.size as f64 / 1e6
+                ),
+                None => format!("{:.1} MB", entry.recording.size as f64 / 1e6),
+            };
+            recordings = recordings.push(
+                Row::new()
+                    .align_items(Alignment::Center)
+                    .height(Length::Shrink)
+                    .spacing(2)
+                    .push(Text::new(format!("{} ({})", entry.recording.name, size)))
+                    .push(
+                        Button::new(&mut entry.download_btn, Text::new("Download"))
+                            .on_press(RecordingsPanelEvent::Download(entry.recording.name.clone())),
+                    )
+                    .push(
+                        Button::new(&mut entry.delete_btn, Text::new("Delete"))
+                            .on_press(RecordingsPanelEvent::Delete(entry.recording.name.clone())),
+                    ),
+            );
+        }
+
+        Column::new()
+            .height(Length::Shrink)
+            .width(Length::Shrink)
+            .padding(5)
+            .spacing(5)
+            .push(
+                Button::new(&mut self.refresh_btn_state, Text::new("Refresh recordings"))
+                    .on_press(RecordingsPanelEvent::Refresh),
+            )
+            .push(Text::new(&self.status))
+            .push(recordings)
+            .into()
+    }
+
+    pub fn feed_event(&mut self, event: RecordingsPanelEvent) {
+        match event {
+            RecordingsPanelEvent::Refresh => {
+                self.messages
+                    .push(Message::RecordingCtl(RecordingAction::List));
+            }
+            RecordingsPanelEvent::Download(name) => {
+                if self.downloads.iter().any(|download| download.name == name) {
+                    return;
+                }
+                let path = self.dir.join(&name);
+                let file = fs::create_dir_all(&self.dir).and_then(|()| File::create(&path));
+                match file {
+                    Ok(file) => {
+                        self.downloads.push(Download {
+                            name: name.clone(),
+                            path,
+                            file: BufWriter::new(file),
+                            received: 0,
+                        });
+                        self.messages
+                            .push(Message::RecordingCtl(RecordingAction::Download { name }));
+                    }
+                    Err(e) => {
+                        error!("Failed to create {}:\n{:#?}", path.display(), e);
+                        self.status = format!("failed to create {}: {}", path.display(), e);
+                    }
+                }
+            }
+            RecordingsPanelEvent::Delete(name) => {
+                self.messages
+                    .push(Message::RecordingCtl(RecordingAction::Delete { name }));
+            }
+        }
+    }
+
+    pub fn messages(&mut self) -> &mut Vec<Message> {
+        &mut self.messages
+    }
+
+    pub fn feed_info(&mut self, info: RecordingInfo) {
+        match info {
+            RecordingInfo::List(recordings) => {
+                self.recordings = recordings
+                    .into_iter()
+                    .map(|recording| RecordingEntry {
+                        recording,
+                        download_btn: button::State::new(),
+                        delete_btn: button::State::new(),
+                    })
+                    .collect();
+            }
+            RecordingInfo::Chunk { name, data, last } => {
+                let index = match self
+                    .downloads
+                    .iter()
+                    .position(|download| download.name == name)
+                {
+                    Some(index) => index,
+                    None => {
+                        warn!(
+                            "Received part of recording {}, which was not requested",
+                            name
+                        );
+                        return;
+                    }
+                };
+                let download = &mut self.downloads[index];
+                download.received += data.len() as u64;
+                let mut result = download.file.write_all(&data);
+                if last {
+                    result = result.and_then(|()| download.file.flush());
+                }
+                match result {
+                    Ok(()) if last => {
+                        let download = self.downloads.remove(index);
+                        info!("Downloaded recording to {}", download.path.display());
+                        self.status = format!("downloaded {}", download.path.display());
+                    }
+                    Ok(()) => {}
+                    Err(e) => {
+                        let download = self.downloads.remove(index);
+                        error!("Failed to write {}:\n{:#?}", download.path.display(), e);
+                        self.status = format!("failed to write {}: {}", download.path.display(), e);
+                        let _ = fs::remove_file(download.path);
+                    }
+                }
+            }
+            RecordingInfo::DownloadError { name, message } => {
+                warn!("Failed to download recording {}: {}", name, message);
+                self.status = format!("failed to download {}: {}", name, message);
+                if let Some(index) = self
+                    .downloads
+                    .iter()
+                    .position(|download| download.name == name)
+                {
+                    let _ = fs::remove_file(self.downloads.remove(index).path);
+                }
+            }
+            RecordingInfo::DeleteError { name, message } => {
+                warn!("Failed to delete recording {}: {}", name, message);
+                self.status = format!("failed to delete {}: {}", name, message);
+            }
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use aareocams_net::{
+    CameraDevice, CameraState, DriveAction, HomingState, Message, PowerInfo, PowerLevel, Telemetry,
+    TelemetryAction, TelemetryFields, WaypointTarget,
+};
+use iced::{Checkbox, Column, Length, Row, Text};
+
+/// a field of [`Telemetry`] that can be asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TelemetryField {
+    CpuLoad,
+    CpuTemperature,
+    Memory,
+    WifiSignal,
+    Uptime,
+    Drivetrain,
+    Cameras,
+    Power,
+}
+
+impl TelemetryField {
+    const ALL: [Self; 8] = [
+        Self::CpuLoad,
+        Self::CpuTemperature,
+        Self::Memory,
+        Self::WifiSignal,
+        Self::Uptime,
+        Self::Drivetrain,
+        Self::Cameras,
+        Self::Power,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::CpuLoad => "CPU load",
+            Self::CpuTemperature => "CPU temperature",
+            Self::Memory => "memory",
+            Self::WifiSignal => "Wi-Fi signal",
+            Self::Uptime => "uptime",
+            Self::Drivetrain => "drivetrain",
+            Self::Cameras => "cameras",
+            Self::Power => "battery",
+        }
+    }
+
+    fn get(self, fields: &TelemetryFields) -> bool {
+        match self {
+            Self::CpuLoad => fields.cpu_load,
+            Self::CpuTemperature => fields.cpu_temperature,
+            Self::Memory => fields.memory,
+            Self::WifiSignal => fields.wifi_signal,
+            Self::Uptime => fields.uptime,
+            Self::Drivetrain => fields.drivetrain,
+            Self::Cameras => fields.cameras,
+            Self::Power => fields.power,
+        }
+    }
+
+    fn set(self, fields: &mut TelemetryFields, wanted: bool) {
+        *match self {
+            Self::CpuLoad => &mut fields.cpu_load,
+            Self::CpuTemperature => &mut fields.cpu_temperature,
+            Self::Memory => &mut fields.memory,
+            Self::WifiSignal => &mut fields.wifi_signal,
+            Self::Uptime => &mut fields.uptime,
+            Self::Drivetrain => &mut fields.drivetrain,
+            Self::Cameras => &mut fields.cameras,
+            Self::Power => &mut fields.power,
+        } = wanted;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TelemetryPanelEvent {
+    FieldToggled(TelemetryField, bool),
+}
+
+#[derive(Debug)]
+pub struct TelemetryPanel {
+    /// the fields asked for from the bot
+    fields: TelemetryFields,
+    /// the last telemetry received
+    telemetry: Option<Telemetry>,
+    /// the last warning about the battery, shown above the telemetry
+    power_warning: String,
+    messages: Vec<Message>,
+}
+
+impl TelemetryPanel {
+    pub fn new() -> Self {
+        Self {
+            fields: TelemetryFields::ALL,
+            telemetry: None,
+            power_warning: String::new(),
+            messages: vec![],
+        }
+    }
+
+    /// asks the bot for the fields that are checked, such as when connecting to it
+    pub fn subscribe(&mut self) {
+        let action = if TelemetryField::ALL
+            .iter()
+            .any(|field| field.get(&self.fields))
+        {
+            TelemetryAction::Subscribe(self.fields)
+        } else {
+            TelemetryAction::Unsubscribe
+        };
+        self.messages.push(Message::TelemetryCtl(action));
+    }
+
+    pub fn view(&mut self) -> iced::Element<TelemetryPanelEvent> {
+        let mut fields = Row::<'_, TelemetryPanelEvent>::new()
+            .height(Length::Shrink)
+            .spacing(10);
+        for field in TelemetryField::ALL {
+            fields = fields.push(Checkbox::new(
+                field.get(&self.fields),
+                field.label(),
+                move |wanted| TelemetryPanelEvent::FieldToggled(field, wanted),
+            ));
+        }
+
+        let mut values = Column::<'_, TelemetryPanelEvent>::new()
+            .height(Length::Shrink)
+            .spacing(2);
+        match &self.telemetry {
+            Some(telemetry) => {
+                for line in describe(telemetry) {
+                    values = values.push(Text::new(line));
+                }
+            }
+            None => values = values.push(Text::new("no telemetry received")),
+        }
+
+        Column::new()
+            .height(Length::Shrink)
+            .width(Length::Shrink)
+            .padding(5)
+            .spacing(5)
+            .push(fields)
+            .push(Text::new(&self.power_warning))
+            .push(values)
+            .into()
+    }
+
+    pub fn feed_event(&mut self, event: TelemetryPanelEvent) {
+        match event {
+            TelemetryPanelEvent::FieldToggled(field, wanted) => {
+                field.set(&mut self.fields, wanted);
+                self.subscribe();
+            }
+        }
+    }
+
+    pub fn messages(&mut self) -> &mut Vec<Message> {
+        &mut self.messages
+    }
+
+    pub fn feed_telemetry(&mut self, telemetry: Telemetry) {
+        self.telemetry = Some(telemetry);
+    }
+
+    pub fn feed_power_info(&mut self, info: PowerInfo) {
+        self.power_warning = match info {
+            PowerInfo::LevelChanged(status) => match status.level {
+                PowerLevel::Normal => String::new(),
+                PowerLevel::Low => {
+                    warn!("The battery of the bot is low: {:?}", status);
+                    format!("battery low: {:.0}%", status.charge * 100.0)
+                }
+                PowerLevel::Critical => {
+                    warn!("The battery of the bot is critically low: {:?}", status);
+                    format!("battery critically low: {:.0}%", status.charge * 100.0)
+                }
+            },
+            PowerInfo::CriticalAction(action) => match action {
+                DriveAction::GoTo {
+                    target: WaypointTarget::Named(name),
+                    ..
+                } => format!("battery critically low, the bot is going to {}", name),
+                DriveAction::Stop => "battery critically low, the bot stopped".to_string(),
+                other => format!("battery critically low, the bot did {:?}", other),
+            },
+            PowerInfo::SensorError { message } => {
+                warn!("Failed to read the battery: {}", message);
+                format!("failed to read the battery: {}", message)
+            }
+        };
+    }
+}
+
+/// a line of text for every field that was sent
+fn describe(telemetry: &Telemetry) -> Vec<String> {
+    let mut lines = vec![];
+    if let Some(load) = telemetry.cpu_load {
+        lines.push(format!("CPU load: {:.0}%", load * 100.0));
+    }
+    if let Some(temperature) = telemetry.cpu_temperature {
+        lines.push(format!("CPU temperature: {:.1} °C", temperature));
+    }
+    if let Some(memory) = telemetry.memory {
+        lines.push(format!(
+            "memory: {} / {} MB",
+            memory.used / (1024 * 1024),
+            memory.total / (1024 * 1024)
+        ));
+    }
+    if let Some(signal) = telemetry.wifi_signal {
+        lines.push(format!("Wi-Fi signal: {:.0} dBm", signal));
+    }
+    if let Some(uptime) = telemetry.uptime {
+        lines.push(format!(
+            "uptime: {}:{:02}:{:02}",
+            uptime / 3600,
+            uptime / 60 % 60,
+            uptime % 60
+        ));
+    }
+    if let Some(drivetrain) = telemetry.drivetrain {
+        lines.push(format!(
+            "drivetrain: {:.3} m ({})  {:.3} m/s  target: {}  error: {:.3}",
+            drivetrain.position,
+            match drivetrain.homing {
+                HomingState::Unhomed => "not homed",
+                HomingState::Armed => "homing",
+                HomingState::Homed => "homed",
+            },
+            drivetrain.velocity,
+            drivetrain
+                .target
+                .map_or("none".to_string(), |target| format!("{:.3} m", target)),
+            drivetrain.tracking_error,
+        ));
+    }
+    if let Some(cameras) = &telemetry.cameras {
+        if cameras.is_empty() {
+            lines.push("cameras: none open".to_string());
+        }
+        for camera in cameras {
+            let device = match &camera.device {
+                CameraDevice::Index(index) => format!("camera {}", index),
+                CameraDevice::TestPattern(..) => "test pattern".to_string(),
+            };
+            let state = match camera.state {
+                CameraState::Opening => "opening",
+                CameraState::Streaming => "streaming",
+                CameraState::Paused => "paused",
+                CameraState::Lost => "lost",
+            };
+            lines.push(format!(
+                "{}: {} ({} streams)",
+                device, state, camera.streams
+            ));
+        }
+    }
+    if let Some(power) = telemetry.power {
+        lines.push(format!(
+            "battery: {:.0}% ({:.2} V, {:.2} A)",
+            power.charge * 100.0,
+            power.voltage,
+            power.current
+        ));
+    }
+    lines
+}
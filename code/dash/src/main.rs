@@ -1,5 +1,6 @@
 #![feature(drain_filter)]
 
+extern crate aareocams_config;
 extern crate aareocams_core;
 extern crate aareocams_net;
 extern crate aareocams_scomm;
@@ -25,11 +26,13 @@ extern crate rustls;
 
 mod config;
 mod dash;
+mod recording;
+mod snapshot;
 mod stream;
 
-use std::net::SocketAddrV4;
+use std::{env, net::SocketAddrV4};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use dash::Dashboard;
 use iced::{Application, Settings};
 
@@ -43,10 +46,27 @@ fn main() -> Result<()> {
 
     info!("Initialized logging");
 
+    // `--profile <name>` picks which bot to connect to
+    let mut profile = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => match args.next() {
+                Some(name) => profile = Some(name),
+                None => bail!("--profile needs the name of a profile"),
+            },
+            other => bail!("Unknown argument `{}`, expected `--profile <name>`", other),
+        }
+    }
+
     let cfg = config::load_config("config/dash.yml")?;
     info!("Read configuration {:#?}", cfg);
+    let addr = cfg.profile(profile.as_deref())?.addr;
 
-    Dashboard::<SocketAddrV4>::run(Settings::with_flags((cfg.bot_addr, cfg.controller_port)))?;
+    let mut settings = Settings::with_flags((addr, cfg.clone()));
+    settings.window.size = (cfg.ui.width, cfg.ui.height);
+    settings.default_text_size = cfg.ui.text_size;
+    Dashboard::<SocketAddrV4>::run(settings)?;
 
     Ok(())
 }
@@ -0,0 +1,116 @@
+//! Recording video streams as they are received, so that they can be played back without the bot
+//!
+//! recordings use the same format as the ones made on the bot (see [`aareocams_net::record`]),
+//! so both can be played back the same way
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use aareocams_net::record::{RecordEntry, RecordReader, RecordWriter, EXTENSION};
+use anyhow::{anyhow, Result};
+use lvenc::Packet;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct StreamRecording {
+    writer: RecordWriter<BufWriter<File>>,
+    path: PathBuf,
+    /// when the first keyframe was received, which is the start of the recording
+    started: Instant,
+    /// packets can only be decoded from a keyframe on, so nothing is written until the first one
+    keyframe_seen: bool,
+}
+
+impl StreamRecording {
+    /// creates a new recording of `stream` in `dir`.
+    /// the stream should be asked for a keyframe, since the recording starts with the next one
+    pub fn create(dir: &Path, stream: Uuid) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = dir.join(format!(
+            "local-{}-{}.{}",
+            now,
+            &stream.to_simple().to_string()[..8],
+            EXTENSION
+        ));
+        let writer = RecordWriter::new(BufWriter::new(File::create(&path)?))?;
+        Ok(Self {
+            writer,
+            path,
+            started: Instant::now(),
+            keyframe_seen: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// milliseconds since the start of the recording
+    fn time(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    /// records that the stream's encoder was restarted
+    pub fn keyframe(&mut self) -> Result<()> {
+        if !self.keyframe_seen {
+            self.started = Instant::now();
+            self.keyframe_seen = true;
+        }
+        let time = self.time();
+        self.writer.write(&RecordEntry::Keyframe { time })?;
+        Ok(())
+    }
+
+    pub fn packet(&mut self, packet: Packet) -> Result<()> {
+        if self.keyframe_seen {
+            let time = self.time();
+            self.writer.write(&RecordEntry::Packet { time, packet })?;
+        }
+        Ok(())
+    }
+
+    /// flushes everything recorded to disk
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// names of the recordings in `dir`, made here or downloaded from the bot
+pub fn list_recordings(dir: &Path) -> Result<Vec<String>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut recordings = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .map_or(false, |extension| extension == EXTENSION)
+        {
+            if let Some(name) = path.file_name() {
+                recordings.push(name.to_string_lossy().into());
+            }
+        }
+    }
+    recordings.sort();
+    Ok(recordings)
+}
+
+/// reads all of the recording at `path`
+pub fn load_recording(path: &Path) -> Result<Vec<RecordEntry>> {
+    let entries =
+        RecordReader::new(BufReader::new(File::open(path)?))?.collect::<Result<Vec<_>, _>>()?;
+    if entries.is_empty() {
+        return Err(anyhow!("{} is empty", path.display()));
+    }
+    Ok(entries)
+}
@@ -0,0 +1,59 @@
+//! Saving snapshots from the bot
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use aareocams_net::{ImageEncoding, Snapshot};
+use anyhow::Result;
+use uuid::Uuid;
+use yaml_rust::{yaml::Hash, Yaml, YamlEmitter};
+
+/// saves the image of `snapshot` to `dir`, with its metadata in a yaml file of the same name next to it.
+/// returns the path of the image
+pub fn save_snapshot(dir: &Path, stream: Uuid, snapshot: &Snapshot) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let name = format!(
+        "snapshot-{}-{}",
+        snapshot.taken_at,
+        &stream.to_simple().to_string()[..8]
+    );
+    let image_path = dir
+        .join(&name)
+        .with_extension(snapshot.encoding.extension());
+    fs::write(&image_path, &snapshot.data)?;
+
+    let mut meta = Hash::new();
+    let mut insert = |key: &str, value| meta.insert(Yaml::String(key.into()), value);
+    insert(
+        "image",
+        Yaml::String(image_path.file_name().unwrap().to_string_lossy().into()),
+    );
+    insert("stream", Yaml::String(stream.to_string()));
+    // milliseconds since the unix epoch
+    insert("taken_at", Yaml::Integer(snapshot.taken_at as i64));
+    // meters along the cable
+    insert(
+        "position",
+        match snapshot.position {
+            // yaml_rust stores floats as strings, and would write `1` instead of `1.0` with to_string
+            Some(position) => Yaml::Real(format!("{:?}", position)),
+            None => Yaml::Null,
+        },
+    );
+    insert("width", Yaml::Integer(snapshot.width.into()));
+    insert("height", Yaml::Integer(snapshot.height.into()));
+    insert(
+        "encoding",
+        Yaml::String(match snapshot.encoding {
+            ImageEncoding::Png => "png".into(),
+            ImageEncoding::Jpeg { quality } => format!("jpeg, quality {}", quality),
+        }),
+    );
+    let mut out = String::new();
+    YamlEmitter::new(&mut out).dump(&Yaml::Hash(meta))?;
+    fs::write(dir.join(name).with_extension("yml"), out)?;
+
+    Ok(image_path)
+}
@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum WaypointTarget {
+    /// a waypoint saved on the bot
+    Named(String),
+    /// meters along the cable
+    Position(f64),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum DriveAction {
+    Fwd,
+    Rev,
+    Stop,
+    /// drive to a position along the cable, and hold there
+    GoTo {
+        target: WaypointTarget,
+        /// speed to move at in m/s, or `None` to use the default.
+        /// this is limited to the maximum speed configured on the bot
+        speed: Option<f64>,
+    },
+    /// tell the bot where it is (in meters along the cable), for example when it is at a known anchor point
+    SetPosition(f64),
+    /// set the position to zero at the next index pulse of the encoder, homing the bot once it is driven past it
+    ArmHoming,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HomingState {
+    /// the position is relative to wherever the bot was started
+    Unhomed,
+    /// the next index pulse will set the position to zero
+    Armed,
+    /// the position is absolute, and zero is at the index pulse
+    Homed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum DriveInfo {
+    /// sent periodically
+    Status {
+        /// meters along the cable
+        position: f64,
+        /// m/s
+        velocity: f64,
+        /// where the bot is going, if it is going somewhere
+        target: Option<f64>,
+        /// difference between where the bot should be and where it is (m or m/s)
+        tracking_error: f64,
+    },
+    /// reached the target of a [`DriveAction::GoTo`]
+    Arrived {
+        position: f64,
+    },
+    GoToError {
+        message: String,
+    },
+    /// a travel limit was reached, motion toward it will be refused until it is cleared
+    LimitReached {
+        limit: TravelLimit,
+        source: LimitSource,
+    },
+    /// moved back away from a travel limit
+    LimitCleared {
+        limit: TravelLimit,
+    },
+    /// a drive command was ignored, because it would move past a travel limit
+    MotionRefused {
+        limit: TravelLimit,
+    },
+    /// a [`WaypointAction`] could not be done
+    WaypointError {
+        message: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TravelLimit {
+    /// the end of the cable at the lowest position
+    Min,
+    /// the end of the cable at the highest position
+    Max,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitSource {
+    /// the configured minimum or maximum position
+    Soft,
+    /// a limit switch
+    Switch,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Waypoint {
+    pub name: String,
+    /// meters along the cable
+    pub position: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum WaypointAction {
+    /// request the list of waypoints, which is sent as [`crate::Message::Waypoints`]
+    List,
+    /// add a waypoint, or move it if one with the same name exists
+    Set(Waypoint),
+    Remove {
+        name: String,
+    },
+}
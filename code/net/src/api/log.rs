@@ -0,0 +1,58 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// how severe a log record is, from most to least
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub const ALL: [Self; 5] = [
+        Self::Error,
+        Self::Warn,
+        Self::Info,
+        Self::Debug,
+        Self::Trace,
+    ];
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Error => "error",
+                Self::Warn => "warn",
+                Self::Info => "info",
+                Self::Debug => "debug",
+                Self::Trace => "trace",
+            }
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogAction {
+    /// forward records at `level` or more severe from now on.
+    /// records from the libraries the bot uses are only forwarded if they are warnings or errors
+    SetLevel(LogLevel),
+}
+
+/// a log record from the bot
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogRecord {
+    /// when it was logged, in milliseconds since the unix epoch
+    pub time: u64,
+    pub level: LogLevel,
+    /// the module it was logged from
+    pub target: String,
+    pub message: String,
+}
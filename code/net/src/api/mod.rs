@@ -1,9 +1,19 @@
+pub mod drive;
+pub mod log;
 // pub mod motor;
+pub mod power;
+pub mod recording;
+pub mod telemetry;
 pub mod video;
 
 use serde::{Deserialize, Serialize};
 
+pub use drive::*;
+pub use log::*;
 // pub use motor::*;
+pub use power::*;
+pub use recording::*;
+pub use telemetry::*;
 pub use video::*;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -13,6 +23,13 @@ pub enum Message {
         id: uuid::Uuid,
         packet: lvenc::Packet,
     },
+    /// part of a [`lvenc::Packet`] that was too large to send at once, serialized with bincode.
+    /// the parts are sent in order, and the packet is complete when `last` is set
+    VideoStreamFragment {
+        id: uuid::Uuid,
+        data: Vec<u8>,
+        last: bool,
+    },
     VideoStreamInfo {
         id: uuid::Uuid,
         action: VideoStreamInfo,
@@ -21,12 +38,32 @@ pub enum Message {
         id: uuid::Uuid,
         action: VideoStreamAction,
     },
-    Drive (DriveAction)
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum DriveAction {
-    Fwd,
-    Rev,
-    Stop,
+    /// request the list of cameras on the bot, which is sent as [`Message::Cameras`]
+    ListCameras,
+    Cameras(Vec<CameraListing>),
+    /// request the formats supported by the camera with device ID `index`, which are sent as [`Message::CameraFormats`]
+    ListCameraFormats {
+        index: usize,
+    },
+    CameraFormats {
+        index: usize,
+        /// the supported formats, or why they could not be read
+        formats: Result<Vec<CameraFormat>, String>,
+    },
+    Drive (DriveAction),
+    DriveInfo(DriveInfo),
+    WaypointCtl(WaypointAction),
+    /// the current list of waypoints, sent when requested or when it changes
+    Waypoints(Vec<Waypoint>),
+    /// manage the recordings saved on the bot
+    RecordingCtl(RecordingAction),
+    RecordingInfo(RecordingInfo),
+    /// choose what state of the bot is sent periodically as [`Message::Telemetry`]
+    TelemetryCtl(TelemetryAction),
+    Telemetry(Telemetry),
+    /// warnings about the battery of the bot
+    PowerInfo(PowerInfo),
+    /// choose which log records of the bot are forwarded as [`Message::Log`]
+    LogCtl(LogAction),
+    Log(LogRecord),
 }
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+use crate::DriveAction;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PowerStatus {
+    /// battery voltage, in volts
+    pub voltage: f32,
+    /// current drawn from the battery, in amps
+    pub current: f32,
+    /// estimated state of charge, 0 (empty) to 1 (full)
+    pub charge: f32,
+    pub level: PowerLevel,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerLevel {
+    Normal,
+    /// below the warning threshold, the bot should be brought back soon
+    Low,
+    /// below the critical threshold, the bot takes the configured action (stopping or going home)
+    Critical,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum PowerInfo {
+    /// the battery crossed a threshold, in either direction
+    LevelChanged(PowerStatus),
+    /// the battery became critically low, so the bot did this on its own
+    CriticalAction(DriveAction),
+    /// the power sensor could not be read
+    SensorError { message: String },
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum RecordingAction {
+    /// request the list of recordings, which is sent as [`RecordingInfo::List`]
+    List,
+    /// request a recording, which is sent as [`RecordingInfo::Chunk`]s
+    Download {
+        name: String,
+    },
+    Delete {
+        name: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum RecordingInfo {
+    /// the recordings on the bot, oldest first
+    List(Vec<RecordingListing>),
+    /// part of a recording being downloaded, the chunks are sent in order
+    Chunk {
+        name: String,
+        data: Vec<u8>,
+        last: bool,
+    },
+    DownloadError {
+        name: String,
+        message: String,
+    },
+    DeleteError {
+        name: String,
+        message: String,
+    },
+}
+
+/// a recording saved on the bot
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RecordingListing {
+    /// file name, see [`crate::record`] for the format
+    pub name: String,
+    /// in bytes
+    pub size: u64,
+    /// when the recording was last written to, in milliseconds since the unix epoch
+    pub modified: u64,
+}
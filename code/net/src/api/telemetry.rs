@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{CameraDevice, HomingState, PowerStatus};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TelemetryAction {
+    /// start receiving [`Telemetry`] with the fields in `fields`, or change which fields are sent
+    Subscribe(TelemetryFields),
+    Unsubscribe,
+}
+
+/// which fields of [`Telemetry`] are filled in, the others are always `None`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TelemetryFields {
+    pub cpu_load: bool,
+    pub cpu_temperature: bool,
+    pub memory: bool,
+    pub wifi_signal: bool,
+    pub uptime: bool,
+    pub drivetrain: bool,
+    pub cameras: bool,
+    pub power: bool,
+}
+
+impl TelemetryFields {
+    pub const ALL: Self = Self {
+        cpu_load: true,
+        cpu_temperature: true,
+        memory: true,
+        wifi_signal: true,
+        uptime: true,
+        drivetrain: true,
+        cameras: true,
+        power: true,
+    };
+}
+
+/// the state of the bot, sent periodically to a dashboard that subscribed with [`TelemetryAction::Subscribe`].
+/// fields that were not asked for, or could not be read, are `None`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Telemetry {
+    /// when this was sampled, in milliseconds since the unix epoch
+    pub time: u64,
+    /// fraction of the time the CPU was busy since the last sample, 0 to 1
+    pub cpu_load: Option<f32>,
+    /// degrees Celsius
+    pub cpu_temperature: Option<f32>,
+    pub memory: Option<MemoryUsage>,
+    /// signal level of the Wi-Fi connection, in dBm
+    pub wifi_signal: Option<f32>,
+    /// seconds since the bot booted
+    pub uptime: Option<u64>,
+    pub drivetrain: Option<DrivetrainTelemetry>,
+    /// the cameras that are open
+    pub cameras: Option<Vec<CameraTelemetry>>,
+    /// `None` if the bot has no power sensor
+    pub power: Option<PowerStatus>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// bytes in use
+    pub used: u64,
+    pub total: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct DrivetrainTelemetry {
+    /// meters along the cable
+    pub position: f64,
+    /// m/s
+    pub velocity: f64,
+    /// where the bot is going, if it is going somewhere
+    pub target: Option<f64>,
+    /// difference between where the bot should be and where it is (m or m/s)
+    pub tracking_error: f64,
+    /// if `position` is absolute
+    pub homing: HomingState,
+}
+
+/// a camera with streams open on it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CameraTelemetry {
+    pub device: CameraDevice,
+    /// number of streams sharing the camera
+    pub streams: usize,
+    pub state: CameraState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraState {
+    Opening,
+    Streaming,
+    /// every stream on the camera is paused, so it is not being read
+    Paused,
+    /// the camera stopped working, and is being reopened
+    Lost,
+}
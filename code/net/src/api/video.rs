@@ -1,23 +1,306 @@
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum VideoStreamAction {
     Pause,
     Resume,
-    /// initialize stream, opening the camera at device ID `dev`.
-    /// all future requests should be communicated using the uuid provided as part of the main message
+    /// initialize stream, opening the camera `dev`.
+    /// all future requests should be communicated using the uuid provided as part of the main message.
+    /// if a stream is already open on `dev`, the new one shares its camera and encoder. it fails with
+    /// [`VideoStreamInfo::InitError`] if `format` is not what the camera was opened in, and is sent the
+    /// settings it shares with [`VideoStreamInfo::Reconfigured`] if they are not `settings`
     Init {
-        dev: usize,
+        dev: CameraDevice,
+        /// format to open the camera in, or `None` to let the camera pick.
+        /// this is ignored for test patterns, which have their own resolution and frame rate
+        format: Option<CameraFormat>,
+        settings: EncoderSettings,
     },
+    /// change the encoder settings of the stream, without restarting it.
+    /// this changes every stream sharing the camera, and is answered to all of them
+    Reconfigure(EncoderSettings),
+    /// take a still image from the stream's camera, and send it back as a [`VideoStreamInfo::Snapshot`]
+    Snapshot(SnapshotRequest),
+    /// start recording the stream to a file on the bot. the recording is made at full quality,
+    /// independent of the [`EncoderSettings`] of the stream
+    StartRecording,
+    StopRecording,
+    /// restart the encoder with the next frame, so that the stream can be decoded from there on.
+    /// this is answered with a [`VideoStreamInfo::Keyframe`]
+    RequestKeyframe,
     /// close the stream
     Close,
 }
 
+/// the state of a stream is reported with [`VideoStreamInfo::Opening`], [`VideoStreamInfo::Streaming`],
+/// [`VideoStreamInfo::Paused`] and [`VideoStreamInfo::Closed`]. everything else is about a stream in one of them
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum VideoStreamInfo {
-    Initialized,
+    /// the stream was accepted, and its camera is being opened
+    Opening,
+    /// frames are being sent in `format`. sent when the stream starts, resumes, and when the camera is
+    /// reopened after being lost (which can change the format)
+    Streaming(StreamFormat),
+    /// the stream was paused, and no frames are sent until it is resumed
+    Paused,
+    /// the stream ended, this is the last message about it
+    Closed { reason: CloseReason },
     InitError { message: String },
     OpenCamError { message: String },
+    /// reading a frame failed. these are rate limited, so `message` says how many were left out
     ReadError { message: String },
+    /// reading from the camera kept failing, so the stream stopped and is trying to reopen it
+    CameraLost { message: String },
+    /// the camera was reopened after being lost, and the stream continues
+    CameraRecovered,
+    /// the camera could not be reopened, and the stream was closed
+    CameraGaveUp { message: String },
+    /// stopping the camera failed as the stream was closed. it is closed anyway
     CloseError { message: String },
+    /// a command was sent for a stream that does not exist, or has already ended
+    NoSuchStream,
+    EncodeError { message: String },
+    /// the encoder was restarted, so the decoder must be as well before decoding the data that follows
+    Keyframe,
+    /// the encoder settings were changed
+    Reconfigured(EncoderSettings),
+    ReconfigureError { message: String },
+    /// the stream was adapted to how much the link to the dashboard can keep up with
+    OperatingPoint(OperatingPoint),
+    Snapshot(Snapshot),
+    SnapshotError { message: String },
+    /// a new recording file was started, either by [`VideoStreamAction::StartRecording`] or because the last one got too large
+    RecordingStarted { name: String },
+    RecordingStopped,
+    /// recording failed, and was stopped
+    RecordingError { message: String },
+}
+
+/// the format a stream is sent in. this is what the camera was actually opened in, which is not always
+/// the [`CameraFormat`] that was asked for
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamFormat {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+}
+
+impl fmt::Display for StreamFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} @ {} fps", self.width, self.height, self.fps)
+    }
+}
+
+/// why a stream was closed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// with [`VideoStreamAction::Close`]
+    Requested,
+    /// the camera could not be opened. the error was sent before as [`VideoStreamInfo::InitError`]
+    /// or [`VideoStreamInfo::OpenCamError`]
+    InitFailed,
+    /// the camera was lost, and could not be reopened (see [`VideoStreamInfo::CameraGaveUp`])
+    CameraGaveUp,
+    /// the camera server on the bot was stopped
+    Shutdown,
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Requested => "closed",
+                Self::InitFailed => "failed to open the camera",
+                Self::CameraGaveUp => "the camera was lost",
+                Self::Shutdown => "the bot stopped streaming",
+            }
+        )
+    }
+}
+
+/// where a video stream gets its frames from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum CameraDevice {
+    /// a camera on the bot, by device ID
+    Index(usize),
+    /// frames generated on the bot, for testing video streaming without a camera
+    TestPattern(TestPattern),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TestPattern {
+    pub kind: TestPatternKind,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestPatternKind {
+    ColorBars,
+    /// a gradient that scrolls sideways every frame
+    MovingGradient,
+}
+
+/// a camera on the bot
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CameraListing {
+    /// device ID, to open it with [`CameraDevice::Index`]
+    pub index: usize,
+    pub human_name: String,
+    pub description: String,
+    /// if a stream is open on this camera
+    pub in_use: bool,
+}
+
+/// a combination of resolution, frame rate and pixel format that a camera can capture in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CameraFormat {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub pixel_format: PixelFormat,
+}
+
+impl fmt::Display for CameraFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} {:?} @ {} fps",
+            self.width, self.height, self.pixel_format, self.fps
+        )
+    }
+}
+
+/// format of the frames sent by the camera (they are always converted to RGB before encoding)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    /// motion JPEG
+    Mjpeg,
+    /// YUYV 4:2:2
+    Yuyv,
+}
+
+/// how a video stream is encoded
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderSettings {
+    /// most bytes of encoded video sent in one message, larger packets are split into
+    /// [`crate::Message::VideoStreamFragment`]s. `None` to never split packets
+    pub max_packet_size: Option<usize>,
+    /// bits kept of each color channel (1 to 8), lower values compress better but look worse
+    pub quality: u8,
+    /// number of frames between keyframes, or `None` to only have one at the start of the stream.
+    /// a stream can only be joined, or recover from bad data at a keyframe, but they take up a lot of bandwidth
+    pub keyframe_interval: Option<u32>,
+    /// lower the frame rate, resolution and quality of the stream when the link to the dashboard is congested,
+    /// and raise them again when it recovers
+    pub adaptive: bool,
+    /// most frames per second sent, frames from the camera beyond that are skipped. `None` to send every frame
+    pub max_fps: Option<u32>,
+    /// drop frames from the camera while data is still waiting to be sent to the dashboard, instead of queueing
+    /// them up behind it. keeps the latency low (for driving) at the cost of a choppier stream
+    pub latest_frame_only: bool,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> Self {
+        Self {
+            max_packet_size: None,
+            quality: 8,
+            keyframe_interval: None,
+            adaptive: true,
+            max_fps: None,
+            latest_frame_only: false,
+        }
+    }
+}
+
+/// how far an adaptive stream is currently turned down from its [`EncoderSettings`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperatingPoint {
+    /// only every `frame_interval`th frame from the camera is sent
+    pub frame_interval: u32,
+    /// the width and height of the frames are divided by this
+    pub downscale: u32,
+    /// highest [`EncoderSettings::quality`] used, the stream uses the lower of this and its own setting
+    pub max_quality: u8,
+}
+
+impl OperatingPoint {
+    /// the stream as configured, not turned down at all
+    pub const FULL: Self = Self {
+        frame_interval: 1,
+        downscale: 1,
+        max_quality: 8,
+    };
+}
+
+impl fmt::Display for OperatingPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "1/{} frames, 1/{} resolution, quality {}",
+            self.frame_interval, self.downscale, self.max_quality
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotRequest {
+    /// format to take the snapshot in, or `None` to use the stream's. the stream stops for a moment if it is
+    /// different, since the camera can only capture in one format at a time
+    pub format: Option<CameraFormat>,
+    pub encoding: ImageEncoding,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageEncoding {
+    /// lossless
+    Png,
+    Jpeg {
+        /// 1 to 100
+        quality: u8,
+    },
+}
+
+impl ImageEncoding {
+    /// file extension for images in this encoding
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg { .. } => "jpg",
+        }
+    }
+}
+
+/// a still image taken from a camera on the bot
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub width: u32,
+    pub height: u32,
+    pub encoding: ImageEncoding,
+    /// the encoded image
+    pub data: Vec<u8>,
+    /// when the snapshot was taken, in milliseconds since the unix epoch
+    pub taken_at: u64,
+    /// meters along the cable the bot was at when the snapshot was taken, if known
+    pub position: Option<f64>,
+}
+
+impl fmt::Debug for Snapshot {
+    // leaves out the image data, which is far too large to log
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Snapshot")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("encoding", &self.encoding)
+            .field("data", &format_args!("<{} bytes>", self.data.len()))
+            .field("taken_at", &self.taken_at)
+            .field("position", &self.position)
+            .finish()
+    }
 }
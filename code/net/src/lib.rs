@@ -1,9 +1,11 @@
 extern crate aareocams_scomm;
+extern crate bincode;
 extern crate lvenc;
 extern crate serde;
 extern crate tokio;
 extern crate uuid;
 
 mod api;
+pub mod record;
 
 pub use api::*;
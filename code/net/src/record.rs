@@ -0,0 +1,151 @@
+//! File format for recorded video streams, used by both the bot and the dashboard
+//!
+//! a recording is [`MAGIC`], followed by [`RecordEntry`]s, each serialized with bincode (default options)
+//! and prefixed with its length as a big endian u64
+
+use std::io::{self, Read, Write};
+
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+pub const MAGIC: &[u8; 8] = b"AAREOREC";
+/// file extension of recordings
+pub const EXTENSION: &str = "lvr";
+/// largest entry that will be read, so that a corrupt length does not allocate all the memory there is
+const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum RecordEntry {
+    /// the encoder was restarted, so the decoder must be as well. playback can start from here
+    Keyframe {
+        /// milliseconds since the start of the recording
+        time: u64,
+    },
+    Packet {
+        /// milliseconds since the start of the recording
+        time: u64,
+        packet: lvenc::Packet,
+    },
+}
+
+impl RecordEntry {
+    pub fn time(&self) -> u64 {
+        match self {
+            Self::Keyframe { time } | Self::Packet { time, .. } => *time,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecordError {
+    #[error("IO error:\n{0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to (de)serialize entry:\n{0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("File is not a recording")]
+    NotARecording,
+    #[error("Entry is {0} bytes, which is too large to be real")]
+    EntryTooLarge(u64),
+}
+
+#[derive(Debug)]
+pub struct RecordWriter<W: Write> {
+    writer: W,
+    bytes_written: u64,
+}
+
+impl<W: Write> RecordWriter<W> {
+    pub fn new(mut writer: W) -> Result<Self, RecordError> {
+        writer.write_all(MAGIC)?;
+        Ok(Self {
+            writer,
+            bytes_written: MAGIC.len() as u64,
+        })
+    }
+
+    pub fn write(&mut self, entry: &RecordEntry) -> Result<(), RecordError> {
+        let bytes = bincode::DefaultOptions::new().serialize(entry)?;
+        self.writer.write_all(&(bytes.len() as u64).to_be_bytes())?;
+        self.writer.write_all(&bytes)?;
+        self.bytes_written += 8 + bytes.len() as u64;
+        Ok(())
+    }
+
+    /// size of the recording so far, in bytes
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    pub fn flush(&mut self) -> Result<(), RecordError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[derive(Debug)]
+pub struct RecordReader<R: Read> {
+    reader: R,
+}
+
+impl<R: Read> RecordReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, RecordError> {
+        let mut magic = [0; MAGIC.len()];
+        match reader.read_exact(&mut magic) {
+            Ok(()) if &magic == MAGIC => Ok(Self { reader }),
+            Ok(()) => Err(RecordError::NotARecording),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(RecordError::NotARecording),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// reads the next entry, or `None` at the end of the recording
+    pub fn next_entry(&mut self) -> Result<Option<RecordEntry>, RecordError> {
+        let mut len = [0; 8];
+        match self.reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let len = u64::from_be_bytes(len);
+        if len > MAX_ENTRY_SIZE {
+            return Err(RecordError::EntryTooLarge(len));
+        }
+        let mut bytes = vec![0; len as usize];
+        self.reader.read_exact(&mut bytes)?;
+        Ok(Some(bincode::DefaultOptions::new().deserialize(&bytes)?))
+    }
+}
+
+impl<R: Read> Iterator for RecordReader<R> {
+    type Item = Result<RecordEntry, RecordError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    let mut writer = RecordWriter::new(vec![]).unwrap();
+    writer.write(&RecordEntry::Keyframe { time: 0 }).unwrap();
+    writer.write(&RecordEntry::Keyframe { time: 33 }).unwrap();
+    let written = writer.bytes_written();
+    let bytes = writer.into_inner();
+    assert_eq!(written, bytes.len() as u64);
+
+    let entries = RecordReader::new(&bytes[..])
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].time(), 33);
+
+    assert!(matches!(
+        RecordReader::new(&b"not a recording"[..]),
+        Err(RecordError::NotARecording)
+    ));
+}
@@ -63,6 +63,11 @@ impl<M: Serialize + DeserializeOwned, O: BincodeOptions + Clone> Stream<M, O> {
         self.writer.queue(msg)
     }
 
+    /// number of bytes that have been queued, but not yet sent
+    pub fn queued_bytes(&self) -> usize {
+        self.writer.queued_bytes()
+    }
+
     pub fn get(&mut self) -> Option<M> {
         self.reader.get_next()
     }
@@ -42,6 +42,11 @@ impl<M: Serialize + DeserializeOwned, O: BincodeOptions + Clone> SocketWriter<M,
         Ok(())
     }
 
+    /// number of bytes that have been queued, but not yet written to the socket
+    pub fn queued_bytes(&self) -> usize {
+        self.writer.buf_len()
+    }
+
     /// Writes all of the buffered data into the socket
     ///
     /// returns if writing is done
@@ -0,0 +1,62 @@
+//! `deploy-config.sh`, which `tools/deploy.sh` sources for where to deploy the bot to
+
+use std::collections::BTreeMap;
+
+pub const IP: &str = "AAREOCAMS_DEPLOY_TARGET_IP";
+pub const USER: &str = "AAREOCAMS_DEPLOY_TARGET_UNAME";
+/// where the executable goes
+pub const PATH: &str = "AAREOCAMS_DEPLOY_TARGET_PATH";
+/// where the config files go, which is `config/` in [`PATH`] so that the bot finds them
+pub const CONFIG_PATH: &str = "AAREOCAMS_DEPLOY_TARGET_CONFIG_PATH";
+
+/// the variables set in a deploy-config.sh, skipping any lines that are not simple assignments
+pub fn parse(script: &str) -> BTreeMap<String, String> {
+    script
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), unquote(value.trim())))
+        .collect()
+}
+
+/// a deploy-config.sh setting `vars`, as (name, value)
+pub fn render(vars: &[(&str, &str)]) -> String {
+    let mut script = "# written by aareocams-setup, sourced by tools/deploy.sh\n".to_string();
+    for (name, value) in vars {
+        script += &format!("{}={}\n", name, quote(value));
+    }
+    script
+}
+
+/// single quoted, so that the shell does not expand anything in it
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        value[1..value.len() - 1].replace(r"'\''", "'")
+    } else if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        value[1..value.len() - 1].to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[test]
+fn test_deploy_config() {
+    let vars = [
+        (IP, "192.168.1.10"),
+        (USER, "pi"),
+        (PATH, "/home/pi/it's here/"),
+    ];
+    let parsed = parse(&render(&vars));
+    assert_eq!(parsed.len(), 3);
+    for (name, value) in vars {
+        assert_eq!(parsed[name], value);
+    }
+
+    let template = parse(include_str!("../../../config/deploy-config.sh.template"));
+    assert_eq!(template[IP], "ip of the deploy target here");
+    assert_eq!(template.len(), 4);
+}
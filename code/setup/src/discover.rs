@@ -0,0 +1,83 @@
+//! Finding the cameras, gamepads and network interfaces of the computer the setup tool runs on, to suggest
+//! config values from
+
+use std::{
+    fs,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+};
+
+use if_addrs::IfAddr;
+
+const VIDEO4LINUX: &str = "/sys/class/video4linux";
+const INPUT_DEVICES: &str = "/dev/input";
+/// only there on a raspberry pi
+const GPIO_MEM: &str = "/dev/gpiomem";
+
+#[derive(Debug, Clone)]
+pub struct Camera {
+    /// like `/dev/video0`
+    pub device: PathBuf,
+    pub name: String,
+}
+
+/// V4L cameras, leaving out the extra metadata devices that most USB cameras also have
+pub fn cameras() -> Vec<Camera> {
+    let entries = match fs::read_dir(VIDEO4LINUX) {
+        Ok(entries) => entries,
+        Err(..) => return vec![],
+    };
+    let mut cameras = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let dir = entry.path();
+            // the device a camera streams from is the first one it has
+            if fs::read_to_string(dir.join("index")).ok()?.trim() != "0" {
+                return None;
+            }
+            Some(Camera {
+                device: Path::new("/dev").join(entry.file_name()),
+                name: fs::read_to_string(dir.join("name"))
+                    .ok()?
+                    .trim()
+                    .to_string(),
+            })
+        })
+        .collect::<Vec<_>>();
+    cameras.sort_by(|a, b| a.device.cmp(&b.device));
+    cameras
+}
+
+/// ports of the gamepads, which are at `/dev/input/js<port>`
+pub fn gamepads() -> Vec<usize> {
+    let entries = match fs::read_dir(INPUT_DEVICES) {
+        Ok(entries) => entries,
+        Err(..) => return vec![],
+    };
+    let mut ports = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().to_str()?.strip_prefix("js")?.parse().ok())
+        .collect::<Vec<_>>();
+    ports.sort_unstable();
+    ports
+}
+
+/// (name, address) of the network interfaces with IPv4 addresses, other than loopback
+pub fn interfaces() -> Vec<(String, Ipv4Addr)> {
+    match if_addrs::get_if_addrs() {
+        Ok(interfaces) => interfaces
+            .into_iter()
+            .filter(|interface| !interface.is_loopback())
+            .filter_map(|interface| match interface.addr {
+                IfAddr::V4(addr) => Some((interface.name, addr.ip)),
+                IfAddr::V6(..) => None,
+            })
+            .collect(),
+        Err(..) => vec![],
+    }
+}
+
+/// if this is a raspberry pi, with the GPIO the hardware backend of the bot uses
+pub fn has_gpio() -> bool {
+    Path::new(GPIO_MEM).exists()
+}
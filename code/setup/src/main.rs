@@ -0,0 +1,524 @@
+extern crate aareocams_config;
+extern crate aareocams_core;
+extern crate aareocams_net;
+extern crate anyhow;
+extern crate if_addrs;
+extern crate log4rs;
+extern crate serde;
+extern crate serde_yaml;
+
+mod deploy;
+mod discover;
+mod migrate;
+mod prompt;
+
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    net::{Ipv4Addr, SocketAddrV4},
+    path::{Path, PathBuf},
+};
+
+use aareocams_config::{
+    bot::{self, HalBackend},
+    dash,
+};
+use aareocams_net::LogLevel;
+use anyhow::{bail, Context, Result};
+use log4rs::config::RawConfig;
+use serde_yaml::{Mapping, Value};
+
+use prompt::{Questions, QUESTIONS};
+
+const BOT_TEMPLATE: &str = include_str!("../../../config/bot.yml.template");
+const BOT_LOG4RS_TEMPLATE: &str = include_str!("../../../config/bot-log4rs.yml.template");
+const DASH_LOG4RS_TEMPLATE: &str = include_str!("../../../config/dash-log4rs.yml.template");
+const DEPLOY_TEMPLATE: &str = include_str!("../../../config/deploy-config.sh.template");
+
+const USAGE: &str = "\
+Sets up the config files of the bot, the dashboard and the deploy script, asking for the important values
+and keeping everything else that is already set
+
+usage: aareocams-setup [options] [bot] [bot-log4rs] [dash] [dash-log4rs] [deploy]
+
+the files to write are the ones the computer being set up (see --machine) needs by default. answers to the
+questions can be given as flags (`--<flag> <answer>`), everything else is asked for
+
+options:
+  --config-dir <dir>  where the config files are, `config` by default
+  --yes, -y           take the defaults for everything that was not given as a flag, without asking
+  --migrate           only bring bot.yml and dash.yml from older versions up to date
+  --help, -h          show this
+";
+
+/// a file the setup tool writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigFile {
+    Bot,
+    BotLog4rs,
+    Dash,
+    DashLog4rs,
+    Deploy,
+}
+
+impl ConfigFile {
+    const ALL: [Self; 5] = [
+        Self::Bot,
+        Self::BotLog4rs,
+        Self::Dash,
+        Self::DashLog4rs,
+        Self::Deploy,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Bot => "bot",
+            Self::BotLog4rs => "bot-log4rs",
+            Self::Dash => "dash",
+            Self::DashLog4rs => "dash-log4rs",
+            Self::Deploy => "deploy",
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::Bot => "bot.yml",
+            Self::BotLog4rs => "bot-log4rs.yml",
+            Self::Dash => "dash.yml",
+            Self::DashLog4rs => "dash-log4rs.yml",
+            Self::Deploy => "deploy-config.sh",
+        }
+    }
+}
+
+/// the computer being set up, which decides what is found on it is good for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Machine {
+    /// the raspberry pi the bot runs on
+    Bot,
+    /// the computer the dashboard runs on, which the bot is deployed from
+    Dash,
+    /// runs both, like when trying things out with the simulated backend
+    Both,
+}
+
+impl Machine {
+    fn parse(answer: &str) -> Result<Self> {
+        Ok(match answer {
+            "bot" => Self::Bot,
+            "dash" => Self::Dash,
+            "both" => Self::Both,
+            other => bail!("`{}` is not `bot`, `dash` or `both`", other),
+        })
+    }
+
+    fn runs_bot(self) -> bool {
+        self != Self::Dash
+    }
+
+    fn runs_dash(self) -> bool {
+        self != Self::Bot
+    }
+
+    /// the files this computer needs
+    fn files(self) -> Vec<ConfigFile> {
+        ConfigFile::ALL
+            .into_iter()
+            .filter(|file| match file {
+                ConfigFile::Bot | ConfigFile::BotLog4rs => self.runs_bot(),
+                // the bot is deployed from the computer with the dashboard
+                ConfigFile::Dash | ConfigFile::DashLog4rs | ConfigFile::Deploy => self.runs_dash(),
+            })
+            .collect()
+    }
+}
+
+fn main() -> Result<()> {
+    let mut config_dir = PathBuf::from("config");
+    let mut interactive = true;
+    let mut migrate_only = false;
+    let mut answers = BTreeMap::new();
+    let mut files = vec![];
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" | "-h" => {
+                print!("{}", USAGE);
+                println!("questions:");
+                for (flag, question) in QUESTIONS {
+                    println!("  --{:<20} {}", flag, question);
+                }
+                return Ok(());
+            }
+            "--yes" | "-y" => interactive = false,
+            "--migrate" => migrate_only = true,
+            "--config-dir" => match args.next() {
+                Some(dir) => config_dir = dir.into(),
+                None => bail!("--config-dir needs a directory"),
+            },
+            flag if flag.starts_with("--") => {
+                let flag = &flag[2..];
+                if !QUESTIONS.iter().any(|(known, _)| *known == flag) {
+                    bail!("Unknown flag `--{}`, see --help", flag);
+                }
+                match args.next() {
+                    Some(answer) => answers.insert(flag.to_string(), answer),
+                    None => bail!("--{} needs an answer", flag),
+                };
+            }
+            name => match ConfigFile::ALL.iter().find(|file| file.name() == name) {
+                Some(&file) => files.push(file),
+                None => bail!("Unknown config file `{}`, see --help", name),
+            },
+        }
+    }
+    if migrate_only {
+        migrate_file(&config_dir.join("bot.yml"), migrate::bot, bot::parse_config)?;
+        migrate_file(
+            &config_dir.join("dash.yml"),
+            migrate::dash,
+            dash::parse_config,
+        )?;
+        return Ok(());
+    }
+
+    fs::create_dir_all(&config_dir)
+        .with_context(|| format!("Failed to create {:?}", config_dir))?;
+    let mut questions = Questions::new(answers, interactive);
+    // a raspberry pi is most likely running the bot
+    let default_machine = if discover::has_gpio() { "bot" } else { "both" };
+    let machine = questions.ask_with("machine", default_machine, Machine::parse)?;
+    if files.is_empty() {
+        files = machine.files();
+    }
+    print_discovered(machine);
+    let mut bot_addr = None;
+    let mut dash_addr = None;
+    for file in files {
+        println!("\n{}", file.file_name());
+        let path = config_dir.join(file.file_name());
+        match file {
+            ConfigFile::Bot => bot_addr = Some(setup_bot(&mut questions, &path, machine)?),
+            ConfigFile::BotLog4rs => {
+                setup_log4rs(&mut questions, &path, BOT_LOG4RS_TEMPLATE, "bot-log-level")?
+            }
+            ConfigFile::Dash => {
+                dash_addr = Some(setup_dash(&mut questions, &path, bot_addr, machine)?)
+            }
+            ConfigFile::DashLog4rs => setup_log4rs(
+                &mut questions,
+                &path,
+                DASH_LOG4RS_TEMPLATE,
+                "dash-log-level",
+            )?,
+            ConfigFile::Deploy => setup_deploy(&mut questions, &path, dash_addr)?,
+        }
+    }
+    Ok(())
+}
+
+/// what was found on this computer that matters for what it runs, to help answer the questions
+fn print_discovered(machine: Machine) {
+    if machine.runs_bot() {
+        let cameras = discover::cameras();
+        if cameras.is_empty() {
+            println!("No cameras found, the bot will only be able to stream test patterns");
+        }
+        for camera in cameras {
+            println!("Found camera {} ({})", camera.device.display(), camera.name);
+        }
+        if !discover::has_gpio() {
+            println!(
+                "There is no raspberry pi GPIO here, the bot can only use the simulated backend"
+            );
+        }
+    }
+    if machine.runs_dash() {
+        let gamepads = discover::gamepads();
+        if gamepads.is_empty() {
+            println!("No gamepads found");
+        }
+        for port in gamepads {
+            println!("Found gamepad /dev/input/js{}", port);
+        }
+    }
+    for (name, addr) in discover::interfaces() {
+        println!("Found network interface {} at {}", name, addr);
+    }
+}
+
+/// returns the address the bot listens on
+fn setup_bot(questions: &mut Questions, path: &Path, machine: Machine) -> Result<SocketAddrV4> {
+    let (mut cfg, default_backend) = match load_existing(path, migrate::bot, bot::parse_config)? {
+        Some(cfg) => {
+            let backend = cfg.hal.backend;
+            (cfg, backend)
+        }
+        None => (
+            bot::parse_config(BOT_TEMPLATE)?,
+            // the hardware backend only works on a raspberry pi. the bot is deployed to one from the dashboard's
+            // computer, otherwise this is where it runs
+            if machine == Machine::Dash || discover::has_gpio() {
+                HalBackend::Hardware
+            } else {
+                HalBackend::Simulated
+            },
+        ),
+    };
+    cfg.network.addr = questions.ask("bot-addr", &cfg.network.addr)?;
+    cfg.hal.backend = questions.ask("backend", &default_backend)?;
+    cfg.logging.remote_level = questions.ask("remote-log-level", &cfg.logging.remote_level)?;
+
+    let yaml = header(path) + &serde_yaml::to_string(&cfg)?;
+    bot::parse_config(&yaml).context("The new bot config is not valid")?;
+    write_file(path, &yaml)?;
+    Ok(cfg.network.addr)
+}
+
+/// returns the address the dashboard connects to
+fn setup_dash(
+    questions: &mut Questions,
+    path: &Path,
+    bot_addr: Option<SocketAddrV4>,
+    machine: Machine,
+) -> Result<SocketAddrV4> {
+    let mut cfg = load_existing(path, migrate::dash, dash::parse_config)?.unwrap_or_default();
+    let profile = questions.ask_text("profile", &cfg.default_profile)?;
+    let default_addr = match (cfg.profiles.get(&profile), bot_addr) {
+        (Some(existing), _) => existing.addr,
+        // if the bot was just set up to listen on one address, that is where to connect
+        (None, Some(bot_addr)) if !bot_addr.ip().is_unspecified() => bot_addr,
+        (None, bot_addr) => SocketAddrV4::new(
+            Ipv4Addr::LOCALHOST,
+            bot_addr.map_or(6440, |bot_addr| bot_addr.port()),
+        ),
+    };
+    let addr = questions.ask("dash-addr", &default_addr)?;
+    cfg.profiles.insert(profile.clone(), dash::Profile { addr });
+    cfg.default_profile = profile;
+
+    // the gamepads here are only any use if this is where the dashboard runs
+    let found_port = match machine.runs_dash() {
+        true => discover::gamepads().first().copied(),
+        false => None,
+    };
+    let default_port = cfg
+        .gamepad
+        .port
+        .or(found_port)
+        .map_or("none".to_string(), |port| port.to_string());
+    cfg.gamepad.port = questions.ask_with("gamepad-port", &default_port, |answer| {
+        Ok(match answer {
+            "none" => None,
+            port => Some(port.parse()?),
+        })
+    })?;
+    cfg.snapshot_dir = questions
+        .ask_text("snapshot-dir", &cfg.snapshot_dir.to_string_lossy())?
+        .into();
+    cfg.recording_dir = questions
+        .ask_text("recording-dir", &cfg.recording_dir.to_string_lossy())?
+        .into();
+
+    let yaml = header(path) + &serde_yaml::to_string(&cfg)?;
+    dash::parse_config(&yaml).context("The new dashboard config is not valid")?;
+    write_file(path, &yaml)?;
+    Ok(addr)
+}
+
+/// sets the root level of the log4rs config at `path`, starting from `template` if there is none yet
+fn setup_log4rs(
+    questions: &mut Questions,
+    path: &Path,
+    template: &str,
+    level_flag: &str,
+) -> Result<()> {
+    let existing = match fs::read_to_string(path) {
+        Ok(yaml) => Some(yaml),
+        Err(read_error) if read_error.kind() == io::ErrorKind::NotFound => None,
+        Err(read_error) => {
+            return Err(read_error).with_context(|| format!("Failed to read {:?}", path))
+        }
+    };
+    let mut cfg: Mapping = serde_yaml::from_str(existing.as_deref().unwrap_or(template))
+        .with_context(|| format!("Invalid logging config {:?}", path))?;
+    let root = cfg
+        .entry(Value::String("root".to_string()))
+        .or_insert_with(|| Value::Mapping(Mapping::new()))
+        .as_mapping_mut()
+        .with_context(|| format!("Invalid logging config {:?}: root is not a map", path))?;
+    let level_key = Value::String("level".to_string());
+    let level = root
+        .get(&level_key)
+        .and_then(|level| serde_yaml::from_value(level.clone()).ok())
+        .unwrap_or(LogLevel::Info);
+    let level: LogLevel = questions.ask(level_flag, &level)?;
+    root.insert(level_key, serde_yaml::to_value(level)?);
+
+    let yaml = header(path) + &serde_yaml::to_string(&cfg)?;
+    // how the bot checks its logging config too
+    serde_yaml::from_str::<RawConfig>(&yaml).context("The new logging config is not valid")?;
+    write_file(path, &yaml)
+}
+
+fn setup_deploy(
+    questions: &mut Questions,
+    path: &Path,
+    dash_addr: Option<SocketAddrV4>,
+) -> Result<()> {
+    let template = deploy::parse(DEPLOY_TEMPLATE);
+    let existing = fs::read_to_string(path)
+        .map(|script| deploy::parse(&script))
+        .unwrap_or_default();
+    // the values of a copy of the template that were not filled in are no use as defaults
+    let existing = |name: &str| {
+        existing
+            .get(name)
+            .filter(|value| template.get(name) != Some(value))
+            .cloned()
+    };
+    let word = |answer: &str| {
+        if answer.is_empty() || answer.contains(char::is_whitespace) {
+            bail!("must not be empty or have spaces in it");
+        }
+        Ok(answer.to_string())
+    };
+
+    let default_ip = existing(deploy::IP)
+        .or_else(|| dash_addr.map(|addr| addr.ip().to_string()))
+        .unwrap_or_default();
+    let ip = questions.ask_with("deploy-ip", &default_ip, word)?;
+    let user = questions.ask_with(
+        "deploy-user",
+        &existing(deploy::USER).unwrap_or_else(|| "pi".to_string()),
+        word,
+    )?;
+    let target = questions.ask_text(
+        "deploy-path",
+        &existing(deploy::PATH).unwrap_or_else(|| format!("/home/{}/aareocams/", user)),
+    )?;
+    let config_target = questions.ask_text(
+        "deploy-config-path",
+        &existing(deploy::CONFIG_PATH)
+            .unwrap_or_else(|| format!("{}/config/", target.trim_end_matches('/'))),
+    )?;
+
+    write_file(
+        path,
+        &deploy::render(&[
+            (deploy::IP, &ip),
+            (deploy::USER, &user),
+            (deploy::PATH, &target),
+            (deploy::CONFIG_PATH, &config_target),
+        ]),
+    )
+}
+
+/// the config at `path` brought up to date, or `None` if there is none yet, or it is not valid (in which case
+/// it is replaced)
+fn load_existing<T>(
+    path: &Path,
+    migrate: fn(&mut Mapping) -> Vec<String>,
+    parse: fn(&str) -> Result<T>,
+) -> Result<Option<T>> {
+    let yaml = match fs::read_to_string(path) {
+        Ok(yaml) => yaml,
+        Err(read_error) if read_error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(read_error) => {
+            return Err(read_error).with_context(|| format!("Failed to read {:?}", path))
+        }
+    };
+    let upgraded = migrate::upgrade(&yaml, migrate).and_then(|upgraded| match upgraded {
+        Some((upgraded, changes)) => {
+            println!("{} is from an older version:", path.display());
+            for change in changes {
+                println!("  {}", change);
+            }
+            parse(&upgraded)
+        }
+        None => parse(&yaml),
+    });
+    match upgraded {
+        Ok(cfg) => Ok(Some(cfg)),
+        Err(invalid) => {
+            println!(
+                "{} is not valid, starting over from the defaults ({:#})",
+                path.display(),
+                invalid
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// brings the config at `path` up to date without asking anything
+fn migrate_file<T>(
+    path: &Path,
+    migrate: fn(&mut Mapping) -> Vec<String>,
+    parse: fn(&str) -> Result<T>,
+) -> Result<()> {
+    let yaml = match fs::read_to_string(path) {
+        Ok(yaml) => yaml,
+        Err(read_error) if read_error.kind() == io::ErrorKind::NotFound => {
+            println!("There is no {}", path.display());
+            return Ok(());
+        }
+        Err(read_error) => {
+            return Err(read_error).with_context(|| format!("Failed to read {:?}", path))
+        }
+    };
+    match migrate::upgrade(&yaml, migrate)
+        .with_context(|| format!("Invalid config file {:?}", path))?
+    {
+        Some((upgraded, changes)) => {
+            parse(&upgraded).with_context(|| {
+                format!("{:?} is still not valid after bringing it up to date", path)
+            })?;
+            println!("{}:", path.display());
+            for change in changes {
+                println!("  {}", change);
+            }
+            write_file(path, &upgraded)
+        }
+        None => {
+            println!("{} is up to date", path.display());
+            Ok(())
+        }
+    }
+}
+
+/// written at the top of generated yaml files, which do not have the comments of the templates
+fn header(path: &Path) -> String {
+    format!(
+        "# written by aareocams-setup, see {}.template for what each key does\n",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    )
+}
+
+/// writes `contents` to `path`, keeping what was there before as `<path>.old`
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    match fs::read_to_string(path) {
+        Ok(old) if old == contents => {
+            println!("{} is unchanged", path.display());
+            return Ok(());
+        }
+        Ok(..) => {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(".old");
+            fs::rename(path, &backup)
+                .with_context(|| format!("Failed to move {:?} to {:?}", path, backup))?;
+            println!(
+                "Kept the old {} as {}",
+                path.display(),
+                Path::new(&backup).display()
+            );
+        }
+        Err(read_error) if read_error.kind() == io::ErrorKind::NotFound => {}
+        Err(read_error) => {
+            return Err(read_error).with_context(|| format!("Failed to read {:?}", path))
+        }
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {:?}", path))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
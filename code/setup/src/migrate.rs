@@ -0,0 +1,175 @@
+//! Moves the keys of config files written for older versions of the bot and dashboard to where they are now
+//!
+//! each function returns what it changed, which is empty if the config was already up to date
+
+use aareocams_core::yaml;
+use anyhow::Result;
+use serde_yaml::{Mapping, Value};
+
+/// `yaml` brought up to date by `migrate`, with what was changed, or `None` if nothing had to be.
+///
+/// NOTE: the whole file is re-written if anything changed, so comments will be lost
+pub fn upgrade(
+    yaml: &str,
+    migrate: fn(&mut Mapping) -> Vec<String>,
+) -> Result<Option<(String, Vec<String>)>> {
+    let mut cfg: Value = yaml::parse(yaml)?;
+    let changes = match cfg.as_mapping_mut() {
+        Some(cfg) => migrate(cfg),
+        // not a map, so the parser of the config will say what is wrong with it
+        None => vec![],
+    };
+    if changes.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some((serde_yaml::to_string(&cfg)?, changes)))
+}
+
+/// bot.yml from before it was split into sections for the network and cameras, and values could have units
+pub fn bot(cfg: &mut Mapping) -> Vec<String> {
+    let mut changes = vec![];
+    if let Some(addr) = cfg.remove(&key("addr")) {
+        section(cfg, "network").insert(key("addr"), addr);
+        changes.push("moved `addr` to `network.addr`".to_string());
+    }
+    if let Some(mut recording) = cfg.remove(&key("recording")) {
+        // sizes without a unit used to be in MB, and are in bytes now
+        if let Some(size) = recording.get_mut("max_file_size") {
+            if let Some(megabytes) = size.as_f64() {
+                *size = Value::String(format!("{} MB", megabytes));
+                changes.push("gave `recording.max_file_size` its unit, MB".to_string());
+            }
+        }
+        section(cfg, "cameras").insert(key("recording"), recording);
+        changes.push("moved `recording` to `cameras.recording`".to_string());
+    }
+    if let Some(recovery) = cfg.remove(&key("camera_recovery")) {
+        section(cfg, "cameras").insert(key("recovery"), recovery);
+        changes.push("moved `camera_recovery` to `cameras.recovery`".to_string());
+    }
+    if let Some(power) = cfg.get_mut(&key("power")).and_then(Value::as_mapping_mut) {
+        // the waypoint to return home to used to be its own key
+        if let Some(home) = power.remove(&key("home")) {
+            if power.get(&key("critical_action")) == Some(&key("return_home")) {
+                let mut action = Mapping::new();
+                action.insert(key("return_home"), home);
+                power.insert(key("critical_action"), Value::Mapping(action));
+                changes
+                    .push("moved `power.home` to `power.critical_action.return_home`".to_string());
+            } else {
+                changes.push(
+                    "removed `power.home`, which was only used with `critical_action: return_home`"
+                        .to_string(),
+                );
+            }
+        }
+    }
+    changes
+}
+
+/// dash.yml from before it had profiles for more than one bot, and settings for the gamepad
+pub fn dash(cfg: &mut Mapping) -> Vec<String> {
+    let mut changes = vec![];
+    if let Some(addr) = cfg.remove(&key("bot_addr")) {
+        // the old template left the address empty
+        if addr.as_str() == Some("") {
+            changes.push("removed the empty `bot_addr`".to_string());
+        } else {
+            let mut profile = Mapping::new();
+            profile.insert(key("addr"), addr);
+            section(cfg, "profiles").insert(key("bot"), Value::Mapping(profile));
+            cfg.insert(key("default_profile"), key("bot"));
+            changes.push(
+                "moved `bot_addr` to the profile `bot`, which is the default profile now"
+                    .to_string(),
+            );
+        }
+    }
+    if let Some(port) = cfg.remove(&key("controller_port")) {
+        section(cfg, "gamepad").insert(key("port"), port);
+        changes.push("moved `controller_port` to `gamepad.port`".to_string());
+    }
+    changes
+}
+
+fn key(name: &str) -> Value {
+    Value::String(name.to_string())
+}
+
+/// the map at `name` in `cfg`, which is added if it is not there
+fn section<'a>(cfg: &'a mut Mapping, name: &str) -> &'a mut Mapping {
+    let section = cfg
+        .entry(key(name))
+        .or_insert_with(|| Value::Mapping(Mapping::new()));
+    if !section.is_mapping() {
+        *section = Value::Mapping(Mapping::new());
+    }
+    section.as_mapping_mut().unwrap()
+}
+
+#[test]
+fn test_migrate_bot() {
+    use aareocams_config::bot::{self, CriticalPowerAction};
+    use std::time::Duration;
+
+    let old = "addr: \"0.0.0.0:6440\"\n\
+               drivetrain:\n  control_rate: 100\n\
+               recording:\n  dir: \"recordings\"\n  max_file_size: 100\n  max_file_duration: 600\n\
+               camera_recovery:\n  reopen_delay: 0.5\n\
+               power:\n  low_charge: 25\n  critical_action: return_home\n  home: \"start\"\n\
+               waypoints:\n  - name: \"start\"\n    position: 0.0\n";
+    let (yaml, changes) = upgrade(old, bot).unwrap().unwrap();
+    assert_eq!(changes.len(), 5);
+    let cfg = bot::parse_config(&yaml).unwrap();
+    assert_eq!(cfg.network.addr.port(), 6440);
+    assert_eq!(cfg.drivetrain.control_period, Duration::from_millis(10));
+    assert_eq!(cfg.cameras.recording.max_file_size, 100 * 1024 * 1024);
+    assert_eq!(
+        cfg.cameras.recording.max_file_duration,
+        Duration::from_secs(600)
+    );
+    assert_eq!(
+        cfg.cameras.recovery.reopen_delay,
+        Duration::from_millis(500)
+    );
+    assert_eq!(cfg.power.low_charge, 0.25);
+    assert_eq!(
+        cfg.power.critical_action,
+        CriticalPowerAction::GoTo("start".to_string())
+    );
+
+    // already up to date
+    assert!(upgrade(&yaml, bot).unwrap().is_none());
+    assert!(
+        upgrade(include_str!("../../../config/bot.yml.template"), bot)
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_migrate_dash() {
+    use aareocams_config::dash;
+
+    let old = "bot_addr: \"192.168.1.10:6440\"\ncontroller_port: 0\nsnapshot_dir: \"snapshots\"\n";
+    let (yaml, changes) = upgrade(old, dash).unwrap().unwrap();
+    assert_eq!(changes.len(), 2);
+    let cfg = dash::parse_config(&yaml).unwrap();
+    assert_eq!(cfg.default_profile, "bot");
+    assert_eq!(
+        cfg.profile(None).unwrap().addr,
+        "192.168.1.10:6440".parse().unwrap()
+    );
+    assert_eq!(cfg.gamepad.port, Some(0));
+
+    let (yaml, _) = upgrade("bot_addr: \"\"\ncontroller_port: 0\n", dash)
+        .unwrap()
+        .unwrap();
+    assert_eq!(dash::parse_config(&yaml).unwrap().default_profile, "local");
+
+    assert!(
+        upgrade(include_str!("../../../config/dash.yml.template"), dash)
+            .unwrap()
+            .is_none()
+    );
+}
@@ -0,0 +1,120 @@
+//! Asking for config values on the terminal, unless they were given as flags
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use aareocams_core::yaml;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// (flag, question) of everything that can be asked. each answer can also be given as `--<flag> <answer>`
+pub const QUESTIONS: &[(&str, &str)] = &[
+    (
+        "machine",
+        "computer being set up, `bot` for the raspberry pi the bot runs on, `dash` for the one the dashboard runs on, \
+         or `both` to run both here",
+    ),
+    (
+        "bot-addr",
+        "address the bot listens on (0.0.0.0 to listen on every interface)",
+    ),
+    (
+        "backend",
+        "hardware of the bot, `hardware` for the raspberry pi GPIO and cameras or `simulated`",
+    ),
+    (
+        "remote-log-level",
+        "level of the bot's logs forwarded to the dashboard",
+    ),
+    ("bot-log-level", "level the bot logs at"),
+    ("profile", "name of the bot the dashboard connects to"),
+    ("dash-addr", "address the dashboard connects to the bot at"),
+    (
+        "gamepad-port",
+        "gamepad to drive with, at /dev/input/js<port> (`none` to not use one)",
+    ),
+    ("snapshot-dir", "directory the dashboard saves snapshots in"),
+    (
+        "recording-dir",
+        "directory the dashboard saves downloaded recordings in",
+    ),
+    ("dash-log-level", "level the dashboard logs at"),
+    ("deploy-ip", "address of the raspberry pi to deploy to"),
+    ("deploy-user", "user to deploy as"),
+    (
+        "deploy-path",
+        "directory to deploy the bot to on the raspberry pi",
+    ),
+    (
+        "deploy-config-path",
+        "directory to deploy the bot's config to (`config/` in the directory the bot is deployed to)",
+    ),
+];
+
+pub struct Questions {
+    /// by flag
+    answers: BTreeMap<String, String>,
+    /// ask about everything that was not given as a flag, instead of taking the defaults
+    interactive: bool,
+}
+
+impl Questions {
+    /// `answers` are by flag, which must be one of [`QUESTIONS`]
+    pub fn new(answers: BTreeMap<String, String>, interactive: bool) -> Self {
+        Self {
+            answers,
+            interactive,
+        }
+    }
+
+    /// asks the question for `flag`, with an answer in the same syntax as in a config file
+    pub fn ask<T: Serialize + DeserializeOwned>(&mut self, flag: &str, default: &T) -> Result<T> {
+        let default = serde_yaml::to_string(default)?;
+        // the quotes around strings like addresses are not needed in an answer
+        let default = default.trim_start_matches("---").trim().trim_matches('"');
+        self.ask_with(flag, default, |answer| Ok(yaml::parse(answer)?))
+    }
+
+    pub fn ask_text(&mut self, flag: &str, default: &str) -> Result<String> {
+        self.ask_with(flag, default, |answer| Ok(answer.to_string()))
+    }
+
+    /// asks the question for `flag` until `parse` accepts the answer. the answer is `default` if nothing is
+    /// entered
+    pub fn ask_with<T>(
+        &mut self,
+        flag: &str,
+        default: &str,
+        parse: impl Fn(&str) -> Result<T>,
+    ) -> Result<T> {
+        if let Some(answer) = self.answers.get(flag) {
+            return parse(answer).with_context(|| format!("Invalid --{}", flag));
+        }
+        if !self.interactive {
+            return parse(default).with_context(|| format!("Invalid default for --{}", flag));
+        }
+        let question = QUESTIONS
+            .iter()
+            .find(|(known, _)| *known == flag)
+            .map(|(_, question)| *question)
+            .ok_or_else(|| anyhow!("There is no question for --{}", flag))?;
+        loop {
+            print!("{} [{}]: ", question, default);
+            io::stdout().flush()?;
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line)? == 0 {
+                bail!("Stopped before answering --{}", flag);
+            }
+            let answer = match line.trim() {
+                "" => default,
+                answer => answer,
+            };
+            match parse(answer) {
+                Ok(value) => return Ok(value),
+                Err(parse_error) => println!("  {:#}", parse_error),
+            }
+        }
+    }
+}
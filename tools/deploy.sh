@@ -8,4 +8,5 @@ echo $AAREOCAMS_DEPLOY_TARGET_UNAME@$AAREOCAMS_DEPLOY_TARGET_IP:$AAREOCAMS_DEPLO
 
 rsync ./build/$AAREOCAMS_DEPLOY_BUILD_MODE/aareocams-bot $AAREOCAMS_DEPLOY_TARGET_UNAME@$AAREOCAMS_DEPLOY_TARGET_IP:$AAREOCAMS_DEPLOY_TARGET_PATH
 rsync ./config/bot.yml $AAREOCAMS_DEPLOY_TARGET_UNAME@$AAREOCAMS_DEPLOY_TARGET_IP:$AAREOCAMS_DEPLOY_TARGET_CONFIG_PATH
+rsync ./config/bot-log4rs.yml $AAREOCAMS_DEPLOY_TARGET_UNAME@$AAREOCAMS_DEPLOY_TARGET_IP:$AAREOCAMS_DEPLOY_TARGET_CONFIG_PATH
 